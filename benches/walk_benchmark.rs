@@ -47,10 +47,47 @@ fn walk_benches(c: &mut Criterion) {
         b.iter(|| for _ in WalkDir::new(linux_dir()) {})
     });
 
+    c.bench_function("jwalk (count, n threads)", |b| {
+        b.iter(|| black_box(WalkDir::new(linux_dir()).count().unwrap()))
+    });
+
+    c.bench_function("jwalk (into_iter count, n threads)", |b| {
+        b.iter(|| black_box(WalkDir::new(linux_dir()).into_iter().count()))
+    });
+
+    c.bench_function("jwalk (into_iter_with_capacity count, n threads)", |b| {
+        b.iter(|| black_box(WalkDir::new(linux_dir()).into_iter_with_capacity(4096).count()))
+    });
+
+    c.bench_function("std::fs::read_dir (max_depth 1)", |b| {
+        b.iter(|| {
+            for each in std::fs::read_dir(linux_dir()).unwrap() {
+                black_box(each.and_then(|each| each.file_type()).ok());
+            }
+        })
+    });
+
+    c.bench_function("jwalk (max_depth 1)", |b| {
+        b.iter(|| for _ in WalkDir::new(linux_dir()).max_depth(1) {})
+    });
+
+    c.bench_function("jwalk (into_std_iter, max_depth 1)", |b| {
+        b.iter(|| for _ in WalkDir::new(linux_dir()).max_depth(1).into_std_iter() {})
+    });
+
     c.bench_function("jwalk (sorted, n threads)", |b| {
         b.iter(|| for _ in WalkDir::new(linux_dir()).sort(true) {})
     });
 
+    #[cfg(unix)]
+    c.bench_function("jwalk (inode sorted, metadata, n threads)", |b| {
+        b.iter(|| {
+            for each in WalkDir::new(linux_dir()).sort_order(jwalk::Sort::Inode) {
+                black_box(each.and_then(|each| each.metadata()).ok());
+            }
+        })
+    });
+
     c.bench_function("jwalk (sorted, metadata, n threads)", |b| {
         b.iter(|| {
             for _ in WalkDirGeneric::<((), Option<Result<Metadata, Error>>)>::new(linux_dir())
@@ -66,6 +103,35 @@ fn walk_benches(c: &mut Criterion) {
         })
     });
 
+    c.bench_function("jwalk (unsorted, file_size by metadata, n threads)", |b| {
+        b.iter(|| {
+            for each in WalkDir::new(linux_dir()).into_iter().flatten() {
+                black_box(each.metadata().map(|metadata| metadata.len()).ok());
+            }
+        })
+    });
+
+    c.bench_function("jwalk (unsorted, file_size preloaded, n threads)", |b| {
+        b.iter(|| {
+            for each in WalkDir::new(linux_dir()).preload_metadata(true).into_iter().flatten() {
+                black_box(each.file_size());
+            }
+        })
+    });
+
+    c.bench_function("jwalk (size sorted, n threads)", |b| {
+        b.iter(|| for _ in WalkDir::new(linux_dir()).sort_order(jwalk::Sort::Size) {})
+    });
+
+    c.bench_function("jwalk (size sorted, par entries 256, n threads)", |b| {
+        b.iter(|| {
+            for _ in WalkDir::new(linux_dir())
+                .sort_order(jwalk::Sort::Size)
+                .par_entries_threshold(256)
+            {}
+        })
+    });
+
     c.bench_function("jwalk (sorted, n threads, first 100)", |b| {
         b.iter(
             || {
@@ -74,6 +140,14 @@ fn walk_benches(c: &mut Criterion) {
         )
     });
 
+    c.bench_function("jwalk (sorted, n threads, first_n 100)", |b| {
+        b.iter(
+            || {
+                for _ in WalkDir::new(linux_dir()).sort(true).first_n(100) {}
+            },
+        )
+    });
+
     c.bench_function("jwalk (unsorted, 2 threads)", |b| {
         b.iter(
             || {
@@ -90,6 +164,14 @@ fn walk_benches(c: &mut Criterion) {
         )
     });
 
+    c.bench_function("jwalk (unsorted, for_each, 1 thread)", |b| {
+        b.iter(|| {
+            WalkDir::new(linux_dir()).for_each(|each| {
+                black_box(each.is_ok());
+            })
+        })
+    });
+
     c.bench_function("jwalk (sorted, 1 thread)", |b| {
         b.iter(|| {
             for _ in WalkDir::new(linux_dir())
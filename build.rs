@@ -0,0 +1,16 @@
+use std::env;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(jwalk_xattr)");
+
+    // Extended attributes are read with rustix, which supports the Linux and
+    // Apple APIs but not the `extattr` functions of the BSDs.
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_default();
+    let target_vendor = env::var("CARGO_CFG_TARGET_VENDOR").unwrap_or_default();
+    let xattr_target =
+        matches!(target_os.as_str(), "linux" | "android") || target_vendor == "apple";
+    if env::var_os("CARGO_FEATURE_XATTR").is_some() && xattr_target {
+        println!("cargo:rustc-cfg=jwalk_xattr");
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+#[cfg(not(unix))]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Directory read while following links. Used to detect loops.
+///
+/// Identity is `(dev, ino)` on unix. Other platforms fall back to comparing
+/// canonicalized paths.
+#[derive(Clone, Debug)]
+pub(crate) struct Ancestor {
+    pub(crate) path: Arc<Path>,
+    id: FileId,
+}
+
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+#[cfg(not(unix))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FileId {
+    canonical: PathBuf,
+}
+
+impl FileId {
+    /// Identity of the file at `path`, following symlinks.
+    #[cfg(unix)]
+    pub(crate) fn of(path: &Path) -> io::Result<FileId> {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = fs::metadata(path)?;
+        Ok(FileId { dev: metadata.dev(), ino: metadata.ino() })
+    }
+
+    /// Identity of the file at `path`, following symlinks.
+    #[cfg(not(unix))]
+    pub(crate) fn of(path: &Path) -> io::Result<FileId> {
+        Ok(FileId { canonical: fs::canonicalize(path)? })
+    }
+}
+
+impl Ancestor {
+    pub(crate) fn new(path: Arc<Path>) -> io::Result<Ancestor> {
+        let id = FileId::of(&path)?;
+        Ok(Ancestor { path, id })
+    }
+
+    pub(crate) fn is_same(&self, id: &FileId) -> bool {
+        self.id == *id
+    }
+}
@@ -0,0 +1,33 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+#[cfg(not(unix))]
+use std::path::PathBuf;
+
+/// Identity of the filesystem a file lives on.
+///
+/// This is `st_dev` on unix. Other platforms fall back to the prefix (for
+/// example the drive letter) of the canonicalized path.
+#[cfg(unix)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DeviceId(u64);
+
+#[cfg(not(unix))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DeviceId(PathBuf);
+
+impl DeviceId {
+    /// Device of the file at `path`, following symlinks.
+    #[cfg(unix)]
+    pub(crate) fn of(path: &Path) -> io::Result<DeviceId> {
+        use std::os::unix::fs::MetadataExt;
+        Ok(DeviceId(fs::metadata(path)?.dev()))
+    }
+
+    /// Device of the file at `path`, following symlinks.
+    #[cfg(not(unix))]
+    pub(crate) fn of(path: &Path) -> io::Result<DeviceId> {
+        let canonical = fs::canonicalize(path)?;
+        Ok(DeviceId(canonical.components().take(1).collect()))
+    }
+}
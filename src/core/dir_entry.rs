@@ -1,17 +1,23 @@
+use std::borrow::Cow;
 use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs::{self, FileType};
+use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::{DirName, OpenAt};
+use super::{Ancestor, FileId, Gitignore, Listed};
 use crate::{ClientState, Error, ReadDirSpec, Result};
 
 /// Representation of a file or directory.
 ///
 /// This representation does not wrap a `std::fs::DirEntry`. Instead it copies
-/// `file_name`, `file_type`, and optionaly `metadata` out of the underlying
-/// `std::fs::DirEntry`. This allows it to quickly drop the underlying file
-/// descriptor.
+/// `file_name` and `file_type` out of the underlying `std::fs::DirEntry`. This
+/// allows it to quickly drop the underlying file descriptor. Metadata is read
+/// on first use and cached.
 pub struct DirEntry<C: ClientState> {
     /// Depth of this entry relative to the root directory where the walk
     /// started.
@@ -38,8 +44,38 @@ pub struct DirEntry<C: ClientState> {
     pub read_children_error: Option<Error>,
     // True if [`follow_links`] is `true` AND was created from a symlink path.
     follow_link: bool,
-    // Origins of synlinks followed to get to this entry.
-    follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+    // Directories read on the way to this entry when following links.
+    follow_link_ancestors: Arc<Vec<Ancestor>>,
+    // Ignore rules that apply to this entry's children.
+    pub(crate) gitignore: Option<Arc<Gitignore>>,
+    // Metadata read by `metadata()`, or when the entry was created from a path.
+    metadata: OnceLock<fs::Metadata>,
+    // False if the entry's children are read but the entry itself is not
+    // yielded.
+    pub(crate) yield_entry: bool,
+    // Inode number from the directory listing.
+    #[cfg(unix)]
+    ino: u64,
+    // Extended attributes, only read when `read_xattrs` is set.
+    #[cfg(jwalk_xattr)]
+    xattrs: Result<Vec<(OsString, Vec<u8>)>>,
+    // File content hash, only computed when `hash_with` is set.
+    hash: Option<Result<Box<[u8]>>>,
+    // Index of the root this entry descends from.
+    pub(crate) root_index: usize,
+    // Set when the directory containing this entry changed while being read.
+    pub(crate) possibly_stale: bool,
+    // Number of entries read from this directory, set by `DirEntryIter`.
+    pub(crate) child_count: Option<usize>,
+    // Set for directories at `max_depth`, whose contents are never read.
+    pub(crate) contents_unread: bool,
+    // Position among the yielded entries of the same directory.
+    pub(crate) sibling_index: usize,
+    pub(crate) last_sibling: bool,
+    // How to open this directory when walking from a directory file
+    // descriptor.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) open_at: Option<OpenAt>,
 }
 
 impl<C: ClientState> DirEntry<C> {
@@ -47,12 +83,23 @@ impl<C: ClientState> DirEntry<C> {
         depth: usize,
         parent_path: Arc<Path>,
         fs_dir_entry: &fs::DirEntry,
-        follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+        follow_link_ancestors: Arc<Vec<Ancestor>>,
+        preload_metadata: bool,
     ) -> Result<Self> {
         let file_type = fs_dir_entry
             .file_type()
             .map_err(|err| Error::from_path(depth, fs_dir_entry.path(), err))?;
         let file_name = fs_dir_entry.file_name();
+        #[cfg(unix)]
+        let ino = std::os::unix::fs::DirEntryExt::ino(fs_dir_entry);
+        // On Windows the metadata comes with the directory listing, so keep
+        // it. Elsewhere it is read relative to the listed directory where the
+        // platform supports it, such as with `statx` on Linux.
+        let metadata = if cfg!(windows) || preload_metadata {
+            fs_dir_entry.metadata().map(OnceLock::from).unwrap_or_default()
+        } else {
+            OnceLock::new()
+        };
         let read_children_path: Option<Arc<Path>> =
             if file_type.is_dir() { Some(Arc::from(parent_path.join(&file_name))) } else { None };
 
@@ -67,6 +114,22 @@ impl<C: ClientState> DirEntry<C> {
             client_state: C::DirEntryState::default(),
             follow_link: false,
             follow_link_ancestors,
+            gitignore: None,
+            metadata,
+            yield_entry: true,
+            #[cfg(unix)]
+            ino,
+            #[cfg(jwalk_xattr)]
+            xattrs: Ok(Vec::new()),
+            hash: None,
+            root_index: 0,
+            possibly_stale: false,
+            child_count: None,
+            contents_unread: false,
+            sibling_index: 0,
+            last_sibling: false,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            open_at: None,
         })
     }
 
@@ -75,7 +138,7 @@ impl<C: ClientState> DirEntry<C> {
         depth: usize,
         path: &Path,
         follow_link: bool,
-        follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+        follow_link_ancestors: Arc<Vec<Ancestor>>,
     ) -> Result<Self> {
         let metadata = if follow_link {
             fs::metadata(&path).map_err(|err| Error::from_path(depth, path.to_owned(), err))?
@@ -89,6 +152,9 @@ impl<C: ClientState> DirEntry<C> {
         let read_children_path: Option<Arc<Path>> =
             if metadata.file_type().is_dir() { Some(Arc::from(path)) } else { None };
 
+        #[cfg(unix)]
+        let ino = std::os::unix::fs::MetadataExt::ino(&metadata);
+
         Ok(DirEntry {
             depth,
             file_name: root_name.to_owned(),
@@ -100,9 +166,175 @@ impl<C: ClientState> DirEntry<C> {
             client_state: C::DirEntryState::default(),
             follow_link,
             follow_link_ancestors,
+            gitignore: None,
+            metadata: OnceLock::from(metadata),
+            yield_entry: true,
+            #[cfg(unix)]
+            ino,
+            #[cfg(jwalk_xattr)]
+            xattrs: Ok(Vec::new()),
+            hash: None,
+            root_index: 0,
+            possibly_stale: false,
+            child_count: None,
+            contents_unread: false,
+            sibling_index: 0,
+            last_sibling: false,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            open_at: None,
+        })
+    }
+
+    // Only used when walking from a directory file descriptor. Reads the
+    // file type, and the metadata with `preload_metadata`, relative to
+    // `parent_fd` and never follows links.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn from_fd(
+        depth: usize,
+        parent_path: Arc<Path>,
+        parent_fd: &Arc<std::os::fd::OwnedFd>,
+        dir_name: DirName,
+        preload_metadata: bool,
+    ) -> Result<Self> {
+        let path = parent_path.join(&dir_name.name);
+        let (file_type, ino, metadata) = if preload_metadata {
+            let metadata = super::open_at::symlink_metadata_at(parent_fd, &dir_name.name)
+                .map_err(|err| Error::from_path(depth, path.clone(), err))?;
+            let ino = std::os::unix::fs::MetadataExt::ino(&metadata);
+            (metadata.file_type(), ino, OnceLock::from(metadata))
+        } else {
+            let (file_type, ino) = dir_name
+                .file_type(parent_fd)
+                .map_err(|err| Error::from_path(depth, path.clone(), err))?;
+            (file_type, ino, OnceLock::new())
+        };
+        let file_name = dir_name.name;
+        let (read_children_path, open_at) = if file_type.is_dir() {
+            (
+                Some(Arc::from(path.as_path())),
+                Some(OpenAt::new(parent_fd.clone(), file_name.clone())),
+            )
+        } else {
+            (None, None)
+        };
+
+        Ok(DirEntry {
+            depth,
+            file_name,
+            file_type,
+            parent_path,
+            path,
+            read_children_path,
+            read_children_error: None,
+            client_state: C::DirEntryState::default(),
+            follow_link: false,
+            follow_link_ancestors: Arc::new(Vec::new()),
+            gitignore: None,
+            ino,
+            metadata,
+            yield_entry: true,
+            #[cfg(jwalk_xattr)]
+            xattrs: Ok(Vec::new()),
+            hash: None,
+            root_index: 0,
+            possibly_stale: false,
+            child_count: None,
+            contents_unread: false,
+            sibling_index: 0,
+            last_sibling: false,
+            open_at,
         })
     }
 
+    // Overwrite this entry with the next entry lent by `for_each`, keeping
+    // its path buffer.
+    pub(crate) fn reuse_for(&mut self, depth: usize, parent_path: &Arc<Path>, listed: Listed) {
+        let path = self.path.as_mut_os_string();
+        path.clear();
+        path.push(parent_path.as_os_str());
+        self.path.push(&listed.file_name);
+        self.depth = depth;
+        self.file_name = listed.file_name;
+        self.file_type = listed.file_type;
+        self.client_state = C::DirEntryState::default();
+        self.parent_path = parent_path.clone();
+        self.read_children_path =
+            if self.file_type.is_dir() { Some(Arc::from(self.path.as_path())) } else { None };
+        self.read_children_error = None;
+        #[cfg(windows)]
+        {
+            self.metadata = listed.metadata.map(OnceLock::from).unwrap_or_default();
+        }
+        #[cfg(not(windows))]
+        {
+            self.metadata = OnceLock::new();
+        }
+        #[cfg(unix)]
+        {
+            self.ino = listed.ino;
+        }
+        self.yield_entry = true;
+        self.child_count = None;
+        self.follow_link = false;
+    }
+
+    // Only used for the root when walking from a directory file descriptor.
+    // Like the root of a path walk, its metadata is cached.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn from_root_fd(path: &Path, root_fd: &Arc<std::os::fd::OwnedFd>) -> Result<Self> {
+        let root_name = DirName::new(".".into());
+        let mut dir_entry =
+            DirEntry::from_fd(0, Arc::from(Path::new("")), root_fd, root_name, true)?;
+        dir_entry.file_name = path.file_name().unwrap_or(path.as_os_str()).to_owned();
+        dir_entry.path = path.to_path_buf();
+        dir_entry.parent_path = Arc::from(path.parent().unwrap_or(Path::new("")));
+        dir_entry.read_children_path = dir_entry.read_children_path.map(|_| Arc::from(path));
+        Ok(dir_entry)
+    }
+
+    /// Create an entry named `file_name` in the directory described by
+    /// `read_dir_spec`, for use in a [`DirReader`](trait.DirReader.html).
+    ///
+    /// Directories are read with the walk's reader. Metadata isn't cached, so
+    /// [`metadata`](struct.DirEntry.html#method.metadata) reads it from the
+    /// file system at [`path`](struct.DirEntry.html#method.path).
+    pub fn new(read_dir_spec: &ReadDirSpec<C>, file_name: OsString, file_type: FileType) -> Self {
+        let depth = read_dir_spec.depth + 1;
+        let parent_path = read_dir_spec.path.clone();
+        let path = parent_path.join(&file_name);
+        let read_children_path: Option<Arc<Path>> =
+            if file_type.is_dir() { Some(Arc::from(path.as_path())) } else { None };
+
+        DirEntry {
+            depth,
+            file_name,
+            file_type,
+            parent_path,
+            path,
+            read_children_path,
+            read_children_error: None,
+            client_state: C::DirEntryState::default(),
+            follow_link: false,
+            follow_link_ancestors: read_dir_spec.follow_link_ancestors.clone(),
+            gitignore: None,
+            metadata: OnceLock::new(),
+            yield_entry: true,
+            #[cfg(unix)]
+            ino: 0,
+            #[cfg(jwalk_xattr)]
+            xattrs: Ok(Vec::new()),
+            hash: None,
+            root_index: read_dir_spec.root_index,
+            possibly_stale: false,
+            child_count: None,
+            contents_unread: false,
+            sibling_index: 0,
+            last_sibling: false,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            open_at: None,
+        }
+    }
+
     /// Return the file type for the file that this entry points to.
     ///
     /// If this is a symbolic link and [`follow_links`] is `true`, then this
@@ -115,6 +347,33 @@ impl<C: ClientState> DirEntry<C> {
         self.file_type
     }
 
+    /// Returns `true` if this entry is a directory. Shortcut for
+    /// `file_type().is_dir()`.
+    ///
+    /// If this is a symbolic link and [`follow_links`] is `true`, then this
+    /// checks the type of the target.
+    ///
+    /// [`follow_links`]: struct.WalkDir.html#method.follow_links
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    /// Returns `true` if this entry is a regular file. Shortcut for
+    /// `file_type().is_file()`.
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_file()
+    }
+
+    /// Returns `true` if this entry is a symbolic link that was not followed.
+    /// Shortcut for `file_type().is_symlink()`.
+    ///
+    /// Use [`path_is_symlink`] to also detect followed links.
+    ///
+    /// [`path_is_symlink`]: struct.DirEntry.html#method.path_is_symlink
+    pub fn is_symlink(&self) -> bool {
+        self.file_type.is_symlink()
+    }
+
     /// Return the file name of this entry.
     ///
     /// If this entry has no file name (e.g., `/`), then the full path is
@@ -123,20 +382,138 @@ impl<C: ClientState> DirEntry<C> {
         &self.file_name
     }
 
+    /// Return the file name of this entry, replacing invalid UTF-8 with
+    /// `U+FFFD REPLACEMENT CHARACTER`.
+    ///
+    /// This doesn't allocate if the name is valid UTF-8.
+    pub fn file_name_lossy(&self) -> Cow<'_, str> {
+        self.file_name.to_string_lossy()
+    }
+
+    /// Return the path of this entry as a `String`, replacing invalid UTF-8
+    /// with `U+FFFD REPLACEMENT CHARACTER`.
+    pub fn path_lossy(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    /// Return the exact bytes of this entry's path, which need not be valid
+    /// UTF-8.
+    ///
+    /// This never makes any system calls.
+    #[cfg(unix)]
+    pub fn path_bytes(&self) -> &[u8] {
+        std::os::unix::ffi::OsStrExt::as_bytes(self.path.as_os_str())
+    }
+
     /// Returns the depth at which this entry was created relative to the root.
     ///
     /// The smallest depth is `0` and always corresponds to the path given
     /// to the `new` function on `WalkDir`. Its direct descendents have depth
     /// `1`, and their descendents have depth `2`, and so on.
+    ///
+    /// This is one more than the depth passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// for the directory containing this entry, see
+    /// [`read_dir_spec_depth`](struct.DirEntry.html#method.read_dir_spec_depth).
     pub fn depth(&self) -> usize {
         self.depth
     }
 
+    /// Returns the index of the root this entry descends from, in the order
+    /// the roots were given to
+    /// [`from_roots`](struct.WalkDirGeneric.html#method.from_roots). Always
+    /// `0` for walks with a single root.
+    ///
+    /// Depths are relative to each root, so this tells apart entries with the
+    /// same relative path under different roots.
+    pub fn root_index(&self) -> usize {
+        self.root_index
+    }
+
+    /// Returns `true` if the directory containing this entry changed while it
+    /// was read, so its other entries may not be a consistent snapshot.
+    ///
+    /// Always `false` unless
+    /// [`detect_changes`](struct.WalkDirGeneric.html#method.detect_changes)
+    /// is set, and for roots.
+    pub fn possibly_stale(&self) -> bool {
+        self.possibly_stale
+    }
+
+    /// Returns the number of entries read from this directory, or `None` if
+    /// it isn't a directory or wasn't read.
+    ///
+    /// Entries removed by the walk's filters or by
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// aren't counted, errors are. Entries that are kept but not yielded, such
+    /// as those hidden by
+    /// [`file_type_filter`](struct.WalkDirGeneric.html#method.file_type_filter)
+    /// or [`Action::Skip`](enum.Action.html#variant.Skip), are counted.
+    /// Directories below
+    /// [`max_depth`](struct.WalkDirGeneric.html#method.max_depth) aren't read.
+    /// The count is set on entries yielded by
+    /// [`DirEntryIter`](struct.DirEntryIter.html) and
+    /// [`ParDirEntryIter`](struct.ParDirEntryIter.html), which read a
+    /// directory before yielding it.
+    pub fn child_count(&self) -> Option<usize> {
+        self.child_count
+    }
+
+    /// Returns `true` if this directory was read and no entries were found.
+    ///
+    /// See [`child_count`](struct.DirEntry.html#method.child_count).
+    pub fn is_empty_dir(&self) -> bool {
+        self.child_count == Some(0)
+    }
+
+    /// Returns the position of this entry among the entries of its directory,
+    /// in the order they are yielded. Roots are numbered among the roots.
+    ///
+    /// Entries are numbered after they are filtered, sorted and passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// and errors aren't numbered. Directories that
+    /// [`skip_empty_dirs`](struct.WalkDirGeneric.html#method.skip_empty_dirs)
+    /// finds empty are still numbered, since that is only known after their
+    /// siblings are numbered.
+    pub fn sibling_index(&self) -> usize {
+        self.sibling_index
+    }
+
+    /// Returns `true` if this is the last entry of its directory, see
+    /// [`sibling_index`](struct.DirEntry.html#method.sibling_index).
+    pub fn is_last_sibling(&self) -> bool {
+        self.last_sibling
+    }
+
+    /// Returns the depth of the directory that was read to find this entry,
+    /// which is the [`ReadDirSpec::depth`](struct.ReadDirSpec.html#structfield.depth)
+    /// of that read.
+    ///
+    /// This is the same depth that is passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// along with this entry. Returns `None` for roots, which aren't found by
+    /// reading a directory.
+    pub fn read_dir_spec_depth(&self) -> Option<usize> {
+        self.depth.checked_sub(1)
+    }
+
     /// Path to the file/directory represented by this entry.
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    /// Path to the file/directory represented by this entry, relative to the
+    /// root of the walk.
+    ///
+    /// The root entry's relative path is empty.
+    pub fn relative_path(&self) -> &Path {
+        self.path
+            .ancestors()
+            .nth(self.depth)
+            .and_then(|root| self.path.strip_prefix(root).ok())
+            .unwrap_or(&self.path)
+    }
+
     /// Returns `true` if and only if this entry was created from a symbolic
     /// link. This is unaffected by the [`follow_links`] setting.
     ///
@@ -158,7 +535,10 @@ impl<C: ClientState> DirEntry<C> {
     ///
     /// # Platform behavior
     ///
-    /// This always calls [`std::fs::symlink_metadata`].
+    /// The first successful call reads the metadata with
+    /// [`std::fs::symlink_metadata`] and caches it, later calls never make a
+    /// system call. Errors are not cached. The root entry and followed links
+    /// already have their metadata cached from when they were created.
     ///
     /// If this entry is a symbolic link and [`follow_links`] is enabled, then
     /// [`std::fs::metadata`] is called instead.
@@ -174,12 +554,163 @@ impl<C: ClientState> DirEntry<C> {
     /// [`std::fs::metadata`]: https://doc.rust-lang.org/std/fs/fn.metadata.html
     /// [`std::fs::symlink_metadata`]: https://doc.rust-lang.org/stable/std/fs/fn.symlink_metadata.html
     pub fn metadata(&self) -> Result<fs::Metadata> {
-        if self.follow_link {
+        if let Some(metadata) = self.metadata.get() {
+            return Ok(metadata.clone());
+        }
+        let metadata = if self.follow_link {
             fs::metadata(&self.path())
         } else {
             fs::symlink_metadata(&self.path())
         }
-        .map_err(|err| Error::from_entry(self, err))
+        .map_err(|err| Error::from_entry(self, err))?;
+        Ok(self.metadata.get_or_init(|| metadata).clone())
+    }
+
+    /// Returns `true` if this entry's metadata is already cached, so that
+    /// [`metadata`](#method.metadata) won't make a system call.
+    ///
+    /// Metadata is cached once read, for the root and followed links when
+    /// the entry is created, on Windows from the directory listing, and by
+    /// options that read it such as sorting by size.
+    pub fn has_preloaded_metadata(&self) -> bool {
+        self.metadata.get().is_some()
+    }
+
+    /// Returns the length of this entry from its cached metadata, as the OS
+    /// reports it for both files and directories.
+    ///
+    /// Never makes a system call, returns `None` if the metadata isn't
+    /// cached, see
+    /// [`has_preloaded_metadata`](#method.has_preloaded_metadata). Sorting by
+    /// [`Sort::Size`](enum.Sort.html#variant.Size) caches it for every entry.
+    pub fn file_size(&self) -> Option<u64> {
+        self.metadata.get().map(fs::Metadata::len)
+    }
+
+    /// Returns the last modification time of this entry from its cached
+    /// metadata.
+    ///
+    /// Like [`file_size`](#method.file_size) this never makes a system call.
+    /// Returns `None` if the metadata isn't cached or the platform doesn't
+    /// record the time.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.cached_time(fs::Metadata::modified)
+    }
+
+    /// Returns the last access time of this entry from its cached metadata,
+    /// see [`modified`](#method.modified).
+    pub fn accessed(&self) -> Option<SystemTime> {
+        self.cached_time(fs::Metadata::accessed)
+    }
+
+    /// Returns the creation time of this entry from its cached metadata, see
+    /// [`modified`](#method.modified). Not every file system records it.
+    pub fn created(&self) -> Option<SystemTime> {
+        self.cached_time(fs::Metadata::created)
+    }
+
+    fn cached_time(
+        &self,
+        time: impl FnOnce(&fs::Metadata) -> io::Result<SystemTime>,
+    ) -> Option<SystemTime> {
+        time(self.metadata.get()?).ok()
+    }
+
+    /// Returns the inode number of this entry.
+    ///
+    /// This is taken from the directory listing and never makes a system
+    /// call. For the root entry and followed links it is the inode of the
+    /// metadata read when the entry was created. Entries created with
+    /// [`new`](struct.DirEntry.html#method.new) return 0.
+    #[cfg(unix)]
+    pub fn ino(&self) -> u64 {
+        self.ino
+    }
+
+    /// Returns the user ID of the owner of this entry.
+    ///
+    /// Reads the metadata with [`metadata`](#method.metadata), so this makes
+    /// a system call unless the metadata is already cached.
+    #[cfg(unix)]
+    pub fn uid(&self) -> Result<u32> {
+        Ok(std::os::unix::fs::MetadataExt::uid(&self.metadata()?))
+    }
+
+    /// Returns the group ID of the owner of this entry.
+    ///
+    /// Reads the metadata with [`metadata`](#method.metadata), so this makes
+    /// a system call unless the metadata is already cached.
+    #[cfg(unix)]
+    pub fn gid(&self) -> Result<u32> {
+        Ok(std::os::unix::fs::MetadataExt::gid(&self.metadata()?))
+    }
+
+    /// Returns the number of hard links to this entry.
+    ///
+    /// Reads the metadata with [`metadata`](#method.metadata), so this makes
+    /// a system call unless the metadata is already cached.
+    #[cfg(unix)]
+    pub fn nlink(&self) -> Result<u64> {
+        Ok(std::os::unix::fs::MetadataExt::nlink(&self.metadata()?))
+    }
+
+    /// Returns the names and values of this entry's extended attributes, or
+    /// the error reading them.
+    ///
+    /// Empty unless [`read_xattrs`](struct.WalkDirGeneric.html#method.read_xattrs)
+    /// is set, or if the file system doesn't support extended attributes.
+    /// Attributes of symbolic links are read from the link itself unless the
+    /// link is followed.
+    #[cfg(jwalk_xattr)]
+    pub fn xattrs(&self) -> std::result::Result<&[(OsString, Vec<u8>)], &Error> {
+        self.xattrs.as_deref()
+    }
+
+    #[cfg(jwalk_xattr)]
+    pub(crate) fn read_xattrs(&mut self) {
+        self.xattrs = super::xattr::read_xattrs(&self.path, self.follow_link)
+            .map_err(|err| Error::from_entry(self, err));
+    }
+
+    /// Returns the hash of this file's contents computed by the
+    /// [`hash_with`](struct.WalkDirGeneric.html#method.hash_with) callback,
+    /// or the error it returned.
+    ///
+    /// Returns `None` if `hash_with` isn't set or this entry isn't a file.
+    pub fn hash(&self) -> Option<std::result::Result<&[u8], &Error>> {
+        self.hash.as_ref().map(|hash| hash.as_deref())
+    }
+
+    pub(crate) fn set_hash(&mut self, hash: io::Result<Box<[u8]>>) {
+        self.hash = Some(hash.map_err(|err| Error::from_entry(self, err)));
+    }
+
+    /// Return the canonical, absolute form of this entry's path with all
+    /// symbolic links resolved, as with [`std::fs::canonicalize`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the path doesn't exist, including when this entry
+    /// is a broken symbolic link.
+    ///
+    /// [`std::fs::canonicalize`]: https://doc.rust-lang.org/std/fs/fn.canonicalize.html
+    pub fn canonicalize(&self) -> Result<PathBuf> {
+        fs::canonicalize(&self.path).map_err(|err| Error::from_entry(self, err))
+    }
+
+    /// Return the target of this symbolic link as stored in the link, without
+    /// following it, as with [`std::fs::read_link`]. The target may be
+    /// relative to the directory containing the link.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this entry isn't a symbolic link, which
+    /// [`path_is_symlink`](#method.path_is_symlink) tells without a system
+    /// call.
+    ///
+    /// [`std::fs::read_link`]: https://doc.rust-lang.org/std/fs/fn.read_link.html
+    pub fn read_link(&self) -> Result<PathBuf> {
+        fs::read_link(&self.path).map_err(|err| Error::from_entry(self, err))
     }
 
     /// Reference to the path of the directory containing this entry.
@@ -187,16 +718,35 @@ impl<C: ClientState> DirEntry<C> {
         &self.parent_path
     }
 
+    /// Iterate over the paths of the directories containing this entry,
+    /// starting with [`parent_path`] and ending with the root of the walk.
+    ///
+    /// The root entry has no ancestors. This never allocates.
+    ///
+    /// [`parent_path`]: struct.DirEntry.html#method.parent_path
+    pub fn ancestors(&self) -> impl Iterator<Item = &Path> {
+        self.parent_path.ancestors().take(self.depth)
+    }
+
+    // Takes the entry's handle on its parent directory, if any, so that the
+    // parent's file descriptor is closed once its children have been read.
     pub(crate) fn read_children_spec(
-        &self,
+        &mut self,
         client_read_state: C::ReadDirState,
     ) -> Option<ReadDirSpec<C>> {
+        if self.contents_unread {
+            return None;
+        }
         if let Some(read_children_path) = self.read_children_path.as_ref() {
             Some(ReadDirSpec {
                 depth: self.depth,
                 client_read_state,
                 path: read_children_path.clone(),
                 follow_link_ancestors: self.follow_link_ancestors.clone(),
+                gitignore: self.gitignore.clone(),
+                root_index: self.root_index,
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                open_at: self.open_at.take(),
             })
         } else {
             None
@@ -206,13 +756,15 @@ impl<C: ClientState> DirEntry<C> {
     pub(crate) fn follow_symlink(&self) -> Result<Self> {
         let path = self.path();
         let origins = self.follow_link_ancestors.clone();
-        let dir_entry = DirEntry::from_path(self.depth, &path, true, origins)?;
+        let mut dir_entry = DirEntry::from_path(self.depth, path, true, origins)?;
+        dir_entry.root_index = self.root_index;
 
         if dir_entry.file_type.is_dir() {
-            let target = std::fs::read_link(&path).unwrap();
+            let target = FileId::of(path)
+                .map_err(|err| Error::from_path(self.depth, path.to_owned(), err))?;
             for ancestor in self.follow_link_ancestors.iter().rev() {
-                if target.as_path() == ancestor.as_ref() {
-                    return Err(Error::from_loop(self.depth, ancestor.as_ref(), path.as_ref()));
+                if ancestor.is_same(&target) {
+                    return Err(Error::from_loop(self.depth, &ancestor.path, path));
                 }
             }
         }
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::iter::Peekable;
 
 use super::*;
@@ -8,62 +9,246 @@ use crate::Result;
 /// Yields entries from recursive traversal of filesystem.
 pub struct DirEntryIter<C: ClientState> {
     min_depth: usize,
-    // iterator yeilding next ReadDir results when needed
-    read_dir_iter: Peekable<ReadDirIter<C>>,
+    contents_first: bool,
+    skip_empty_dirs: bool,
+    // yield each ReadDir as it arrives rather than below its directory
+    unordered_first: bool,
+    // number of entries left to yield before the walk is stopped
+    remaining: usize,
+    // stop the walk once an error is yielded
+    abort_on_error: bool,
+    // set once `next` will only return `None`
+    finished: bool,
+    // iterator yeilding next ReadDir results when needed, dropped to stop the
+    // walk once `remaining` reaches zero
+    read_dir_iter: Option<Peekable<ReadDirIter<C>>>,
     // stack of ReadDir results, track location in filesystem traversal
     read_dir_results_stack: Vec<vec::IntoIter<Result<DirEntry<C>>>>,
+    // stack of directories whose contents are being yielded, only used when
+    // contents_first or skip_empty_dirs is set
+    held_dirs_stack: Vec<HeldDir<C>>,
+    // entries waiting to be yielded, in order. Used when skip_empty_dirs
+    // releases held directories that must be yielded before an entry
+    ready: VecDeque<Result<DirEntry<C>>>,
+}
+
+struct HeldDir<C: ClientState> {
+    // None once the directory has been released
+    dir_entry: Option<DirEntry<C>>,
+    // true once a descendant has been yielded
+    non_empty: bool,
 }
 
 impl<C: ClientState> DirEntryIter<C> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         root_entry_results: Vec<Result<DirEntry<C>>>,
         parallelism: Parallelism,
+        max_queued_entries: usize,
+        traversal_order: TraversalOrder,
+        unordered_first: bool,
+        background: bool,
+        capacity: usize,
         min_depth: usize,
+        contents_first: bool,
+        skip_empty_dirs: bool,
+        first_n: usize,
+        abort_on_error: bool,
         root_read_dir_state: C::ReadDirState,
         core_read_dir_callback: Arc<ReadDirCallback<C>>,
     ) -> DirEntryIter<C> {
+        let mut root_entry_results = root_entry_results;
         // 1. Gather read_dir_specs from root level
         let read_dir_specs: Vec<_> = root_entry_results
-            .iter()
+            .iter_mut()
             .flat_map(|dir_entry_result| {
                 dir_entry_result
-                    .as_ref()
+                    .as_mut()
                     .ok()?
                     .read_children_spec(root_read_dir_state.clone())
             })
             .collect();
 
         // 2. Init new read_dir_iter from those specs
-        let read_dir_iter = ReadDirIter::new(read_dir_specs, parallelism, core_read_dir_callback);
+        let read_dir_iter = ReadDirIter::new(
+            read_dir_specs,
+            parallelism,
+            max_queued_entries,
+            traversal_order,
+            unordered_first,
+            background,
+            capacity,
+            core_read_dir_callback,
+        );
 
         // 3. Return DirEntryIter that will return initial root entries and then
         //    fill and process read_dir_iter until complete
         DirEntryIter {
             min_depth,
-            read_dir_iter: read_dir_iter.peekable(),
+            contents_first,
+            skip_empty_dirs,
+            unordered_first,
+            remaining: first_n,
+            abort_on_error,
+            finished: false,
+            read_dir_iter: Some(read_dir_iter.peekable()),
             read_dir_results_stack: vec![root_entry_results.into_iter()],
+            held_dirs_stack: Vec::new(),
+            ready: VecDeque::new(),
         }
     }
 
-    fn push_next_read_dir_results(&mut self) -> Result<()> {
+    /// Returns the entries that haven't been yielded yet, so that the walk can
+    /// be continued later with
+    /// [`WalkDirGeneric::resume`](struct.WalkDirGeneric.html#method.resume).
+    ///
+    /// Use `by_ref().take(n)` to yield a page of entries and then call this.
+    /// Errors that haven't been yielded are not included. Returns `None` while
+    /// [`contents_first`](struct.WalkDirGeneric.html#method.contents_first) or
+    /// [`skip_empty_dirs`](struct.WalkDirGeneric.html#method.skip_empty_dirs)
+    /// are holding back directories, because those can't be resumed, or when
+    /// [`unordered_first`](struct.WalkDirGeneric.html#method.unordered_first)
+    /// is set.
+    pub fn state(&self) -> Option<WalkState> {
+        if self.unordered_first || !self.held_dirs_stack.is_empty() {
+            return None;
+        }
+        // Deeper directories are yielded before the rest of their parents
+        let pending = self
+            .read_dir_results_stack
+            .iter()
+            .rev()
+            .flat_map(|read_dir_results| read_dir_results.as_slice());
+        let entries = self
+            .ready
+            .iter()
+            .chain(pending)
+            .flatten()
+            .map(|dir_entry| PendingEntry {
+                path: dir_entry.path.clone(),
+                depth: dir_entry.depth,
+                root_index: dir_entry.root_index,
+                yield_entry: dir_entry.yield_entry,
+                read_children: dir_entry.read_children_path.is_some(),
+            })
+            .collect();
+        Some(WalkState { entries })
+    }
+
+    /// Returns `true` once the walk is complete and every entry has been
+    /// yielded, so `next` will only return `None`.
+    ///
+    /// This is set as soon as the last entry is yielded when the walk is
+    /// stopped by [`first_n`](struct.WalkDirGeneric.html#method.first_n) or
+    /// [`on_error`](struct.WalkDirGeneric.html#method.on_error), and otherwise
+    /// when `next` first returns `None`. By then no more directories are read
+    /// and the walk's threads have let go of it. After
+    /// [`cancellation`](struct.WalkDirGeneric.html#method.cancellation) is set
+    /// entries that were already read are still yielded before the walk
+    /// finishes.
+    ///
+    /// Dropping the iterator before it is finished stops the walk early,
+    /// directories that are being read are finished and their entries
+    /// discarded.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // Returns the number of entries read, or `None` if the directory wasn't
+    // read.
+    fn push_next_read_dir_results(&mut self, dir_entry: &DirEntry<C>) -> Result<Option<usize>> {
+        // No spec was queued for directories that are never read
+        if dir_entry.contents_unread {
+            self.read_dir_results_stack.push(Vec::new().into_iter());
+            return Ok(None);
+        }
+
         // Push next read dir results or return error if read failed
-        let read_dir_result = self.read_dir_iter.next().unwrap();
-        let read_dir = match read_dir_result {
-            Ok(read_dir) => read_dir,
-            Err(err) => return Err(err),
-        };
+        let read_dir = self.read_dir_iter.as_mut().unwrap().next().unwrap()?;
 
-        let ReadDir { results_list, .. } = read_dir;
+        let ReadDir { results_list, read, .. } = read_dir;
+        let child_count = if read { Some(results_list.len()) } else { None };
 
         self.read_dir_results_stack.push(results_list.into_iter());
 
-        Ok(())
+        Ok(child_count)
     }
-}
 
-impl<C: ClientState> Iterator for DirEntryIter<C> {
-    type Item = Result<DirEntry<C>>;
-    fn next(&mut self) -> Option<Self::Item> {
+    fn is_yielded(&self, dir_entry: &DirEntry<C>) -> bool {
+        dir_entry.depth >= self.min_depth && dir_entry.yield_entry
+    }
+
+    // Yield an entry that isn't held. When skipping empty directories, held
+    // directories above it are now known to be non-empty, so those not yet
+    // yielded are released and yielded first.
+    fn yield_entry_result(
+        &mut self,
+        dir_entry_result: Result<DirEntry<C>>,
+    ) -> Option<Result<DirEntry<C>>> {
+        if !self.skip_empty_dirs {
+            return Some(dir_entry_result);
+        }
+
+        // Directories below a non-empty directory are already non-empty
+        let start = self
+            .held_dirs_stack
+            .iter()
+            .rposition(|held_dir| held_dir.non_empty)
+            .map_or(0, |i| i + 1);
+        for held_dir in &mut self.held_dirs_stack[start..] {
+            held_dir.non_empty = true;
+            if !self.contents_first {
+                self.ready.extend(held_dir.dir_entry.take().map(Ok));
+            }
+        }
+
+        self.ready.push_back(dir_entry_result);
+        self.next_ready()
+    }
+
+    fn next_ready(&mut self) -> Option<Result<DirEntry<C>>> {
+        while let Some(dir_entry_result) = self.ready.pop_front() {
+            match dir_entry_result {
+                Ok(dir_entry) if !self.is_yielded(&dir_entry) => {}
+                dir_entry_result => return Some(dir_entry_result),
+            }
+        }
+        None
+    }
+
+    // Yield the remaining root entries, then each ReadDir's results in the
+    // order they arrive. Errors reading a directory are yielded on their own.
+    fn next_unordered_dir_entry_result(&mut self) -> Option<Result<DirEntry<C>>> {
+        loop {
+            if let Some(top_read_dir_results) = self.read_dir_results_stack.last_mut() {
+                match top_read_dir_results.next() {
+                    Some(Ok(dir_entry)) if !self.is_yielded(&dir_entry) => {}
+                    Some(dir_entry_result) => return Some(dir_entry_result),
+                    None => {
+                        self.read_dir_results_stack.pop();
+                    }
+                }
+                continue;
+            }
+
+            match self.read_dir_iter.as_mut()?.next()? {
+                Ok(read_dir) => {
+                    self.read_dir_results_stack.push(read_dir.results_list.into_iter())
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    fn next_dir_entry_result(&mut self) -> Option<Result<DirEntry<C>>> {
+        if self.unordered_first {
+            return self.next_unordered_dir_entry_result();
+        }
+
+        if let Some(dir_entry_result) = self.next_ready() {
+            return Some(dir_entry_result);
+        }
+
         loop {
             if self.read_dir_results_stack.is_empty() {
                 return None;
@@ -77,24 +262,88 @@ impl<C: ClientState> Iterator for DirEntryIter<C> {
                 // 2.1 Handle error case
                 let mut dir_entry = match dir_entry_result {
                     Ok(dir_entry) => dir_entry,
-                    Err(err) => return Some(Err(err)),
+                    Err(err) => return self.yield_entry_result(Err(err)),
                 };
                 // 2.2 If dir_entry has a read_children_path means we need to read a new
                 // directory and push those results onto read_dir_results_stack
                 if dir_entry.read_children_path.is_some() {
-                    if let Err(err) = self.push_next_read_dir_results() {
-                        dir_entry.read_children_error = Some(err);
+                    match self.push_next_read_dir_results(&dir_entry) {
+                        // 2.3 If contents_first then wait until the dir_entry's
+                        // contents have been yielded, if skip_empty_dirs then
+                        // wait until one of them is yielded
+                        Ok(child_count) if self.contents_first || self.skip_empty_dirs => {
+                            dir_entry.child_count = child_count;
+                            self.held_dirs_stack.push(HeldDir {
+                                dir_entry: Some(dir_entry),
+                                non_empty: false,
+                            });
+                            continue;
+                        }
+                        Ok(child_count) => dir_entry.child_count = child_count,
+                        Err(err) => dir_entry.read_children_error = Some(err),
                     }
                 }
 
-                if dir_entry.depth >= self.min_depth {
-                    // 2.3 Finished, return dir_entry
-                    return Some(Ok(dir_entry));
+                if self.is_yielded(&dir_entry) {
+                    // 2.4 Finished, return dir_entry
+                    return self.yield_entry_result(Ok(dir_entry));
                 }
             } else {
                 // If no more results in current then pop stack
                 self.read_dir_results_stack.pop();
+
+                // If contents_first then the directory whose contents were
+                // just finished is now ready, unless skip_empty_dirs is set
+                // and none of its contents were yielded. Held directories
+                // that were never released are empty and dropped.
+                if let Some(HeldDir {
+                    dir_entry: Some(dir_entry),
+                    non_empty,
+                }) = self.held_dirs_stack.pop()
+                {
+                    if self.contents_first
+                        && (non_empty || !self.skip_empty_dirs)
+                        && self.is_yielded(&dir_entry)
+                    {
+                        return Some(Ok(dir_entry));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<C: ClientState> Iterator for DirEntryIter<C> {
+    type Item = Result<DirEntry<C>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = if self.remaining == 0 { None } else { self.next_dir_entry_result() };
+        let dir_entry_result = match next {
+            Some(dir_entry_result) => dir_entry_result,
+            None => {
+                self.finished = true;
+                self.read_dir_iter = None;
+                return None;
             }
+        };
+        self.remaining -= 1;
+        if self.abort_on_error && is_error(&dir_entry_result) {
+            self.remaining = 0;
+        }
+
+        // Stop reading directories once the last requested entry is yielded.
+        // Entries that were already read are kept for `state`.
+        if self.remaining == 0 {
+            self.finished = true;
+            self.read_dir_iter = None;
         }
+
+        Some(dir_entry_result)
     }
 }
+
+// Whether the result is an error or an entry whose children couldn't be read.
+pub(crate) fn is_error<C: ClientState>(dir_entry_result: &Result<DirEntry<C>>) -> bool {
+    dir_entry_result
+        .as_ref()
+        .map_or(true, |dir_entry| dir_entry.read_children_error.is_some())
+}
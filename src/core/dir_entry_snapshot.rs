@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use super::DirEntry;
+use crate::ClientState;
+
+/// Serializable copy of a [`DirEntry`](struct.DirEntry.html).
+///
+/// `DirEntry` serializes as this type, so walk results can be saved and later
+/// loaded as snapshots. Requires the `serde` feature.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirEntrySnapshot {
+    /// Path to the file/directory.
+    pub path: PathBuf,
+    /// Depth relative to the root of the walk.
+    pub depth: usize,
+    /// `file_type().is_dir()`
+    pub is_dir: bool,
+    /// `file_type().is_file()`
+    pub is_file: bool,
+    /// `file_type().is_symlink()`
+    pub is_symlink: bool,
+    /// `metadata().len()`, or `None` if the metadata couldn't be read.
+    pub len: Option<u64>,
+    /// `metadata().modified()`, or `None` if it couldn't be read.
+    pub modified: Option<SystemTime>,
+}
+
+impl<C: ClientState> DirEntry<C> {
+    /// Copy the path, file type and commonly used metadata of this entry into
+    /// a serializable [`DirEntrySnapshot`](struct.DirEntrySnapshot.html).
+    ///
+    /// Reads metadata if it isn't already cached.
+    pub fn snapshot(&self) -> DirEntrySnapshot {
+        // Caches the metadata for `file_size` and `modified`
+        let _ = self.metadata();
+        DirEntrySnapshot {
+            path: self.path.clone(),
+            depth: self.depth,
+            is_dir: self.file_type.is_dir(),
+            is_file: self.file_type.is_file(),
+            is_symlink: self.file_type.is_symlink(),
+            len: self.file_size(),
+            modified: self.modified(),
+        }
+    }
+}
+
+impl<C: ClientState> Serialize for DirEntry<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use futures_core::Stream;
+
+use crate::{ClientState, DirEntry, Result, WalkDirGeneric};
+
+// Entries the walk thread may get ahead of the stream by.
+const MAX_BUFFERED_ENTRIES: usize = 1024;
+
+/// Stream from `WalkDir.into_stream()`.
+///
+/// Yields the same entries in the same order as
+/// [`DirEntryIter`](struct.DirEntryIter.html). The walk runs on its own
+/// thread, so the stream can be polled from any async runtime without
+/// blocking it. Dropping the stream stops the walk.
+pub struct DirEntryStream<C: ClientState> {
+    channel: Arc<Channel<C>>,
+}
+
+struct Channel<C: ClientState> {
+    state: Mutex<ChannelState<C>>,
+    // Signalled when entries are taken or the stream is dropped.
+    condvar: Condvar,
+}
+
+struct ChannelState<C: ClientState> {
+    entries: VecDeque<Result<DirEntry<C>>>,
+    // Set when the walk thread is finished, including if it panicked.
+    done: bool,
+    // Set when the stream is dropped.
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+// Marks the channel done when the walk thread exits.
+struct WalkGuard<C: ClientState> {
+    channel: Arc<Channel<C>>,
+}
+
+impl<C: ClientState> DirEntryStream<C> {
+    pub(crate) fn new(walk_dir: WalkDirGeneric<C>) -> DirEntryStream<C> {
+        let channel = Arc::new(Channel {
+            state: Mutex::new(ChannelState {
+                entries: VecDeque::new(),
+                done: false,
+                closed: false,
+                waker: None,
+            }),
+            condvar: Condvar::new(),
+        });
+        let guard = WalkGuard { channel: channel.clone() };
+        thread::spawn(move || {
+            for dir_entry_result in walk_dir {
+                if !guard.send(dir_entry_result) {
+                    return;
+                }
+            }
+        });
+        DirEntryStream { channel }
+    }
+}
+
+impl<C: ClientState> WalkGuard<C> {
+    // Returns `false` if the stream was dropped.
+    fn send(&self, dir_entry_result: Result<DirEntry<C>>) -> bool {
+        let mut state = self.channel.state.lock().unwrap();
+        while state.entries.len() >= MAX_BUFFERED_ENTRIES && !state.closed {
+            state = self.channel.condvar.wait(state).unwrap();
+        }
+        if state.closed {
+            return false;
+        }
+        state.entries.push_back(dir_entry_result);
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        true
+    }
+}
+
+impl<C: ClientState> Drop for WalkGuard<C> {
+    fn drop(&mut self) {
+        let mut state = self.channel.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.done = true;
+        let waker = state.waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<C: ClientState> Stream for DirEntryStream<C> {
+    type Item = Result<DirEntry<C>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.channel.state.lock().unwrap();
+        if let Some(dir_entry_result) = state.entries.pop_front() {
+            self.channel.condvar.notify_one();
+            return Poll::Ready(Some(dir_entry_result));
+        }
+        if state.done {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<C: ClientState> Drop for DirEntryStream<C> {
+    fn drop(&mut self) {
+        self.channel.state.lock().unwrap_or_else(|err| err.into_inner()).closed = true;
+        self.channel.condvar.notify_one();
+    }
+}
@@ -0,0 +1,79 @@
+use super::*;
+use crate::Result;
+
+/// Directory iterator from `WalkDir.into_dir_iter()`.
+///
+/// Yields the spec of each directory that is read together with its entries,
+/// in the same order the directories are reached by
+/// [`DirEntryIter`](struct.DirEntryIter.html).
+pub struct DirIter<C: ClientState> {
+    min_depth: usize,
+    read_dir_iter: ReadDirIter<C>,
+    // specs of directories whose entries are read next, next on top
+    read_dir_spec_stack: Vec<ReadDirSpec<C>>,
+}
+
+impl<C: ClientState> DirIter<C> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        root_entry_results: Vec<Result<DirEntry<C>>>,
+        parallelism: Parallelism,
+        max_queued_entries: usize,
+        traversal_order: TraversalOrder,
+        background: bool,
+        min_depth: usize,
+        root_read_dir_state: C::ReadDirState,
+        core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    ) -> DirIter<C> {
+        let mut root_entry_results = root_entry_results;
+        let mut read_dir_specs = || -> Vec<_> {
+            root_entry_results
+                .iter_mut()
+                .flat_map(|dir_entry_result| {
+                    dir_entry_result.as_mut().ok()?.read_children_spec(root_read_dir_state.clone())
+                })
+                .collect()
+        };
+        // The specs that are read come first, so they keep the parent handles
+        let read_dir_iter = ReadDirIter::new(
+            read_dir_specs(),
+            parallelism,
+            max_queued_entries,
+            traversal_order,
+            false,
+            background,
+            0,
+            core_read_dir_callback,
+        );
+        DirIter {
+            min_depth,
+            read_dir_iter,
+            read_dir_spec_stack: read_dir_specs().into_iter().rev().collect(),
+        }
+    }
+}
+
+impl<C: ClientState> Iterator for DirIter<C> {
+    type Item = (ReadDirSpec<C>, Vec<Result<DirEntry<C>>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let read_dir_spec = self.read_dir_spec_stack.pop()?;
+        // Directories are read in the order their specs are pushed
+        let results_list = match self.read_dir_iter.next()? {
+            Ok(mut read_dir) => {
+                let children_specs: Vec<_> = read_dir.read_children_specs().collect();
+                self.read_dir_spec_stack.extend(children_specs.into_iter().rev());
+                let min_depth = self.min_depth;
+                let mut results_list = read_dir.results_list;
+                results_list.retain(|dir_entry_result| {
+                    dir_entry_result.as_ref().map_or(true, |dir_entry| {
+                        dir_entry.depth >= min_depth && dir_entry.yield_entry
+                    })
+                });
+                results_list
+            }
+            Err(err) => vec![Err(err)],
+        };
+        Some((read_dir_spec, results_list))
+    }
+}
@@ -0,0 +1,36 @@
+use super::*;
+use crate::Result;
+
+/// Reads the entries of a directory.
+///
+/// Set with [`with_reader`](struct.WalkDirGeneric.html#method.with_reader) to
+/// walk something other than the file system, or to wrap [`FsDirReader`].
+/// Entries are usually created with [`DirEntry::new`], and all of the walk's
+/// filters, sorting and callbacks still apply to them. Roots are always read
+/// from the file system.
+///
+/// An error returned from `read_dir` is yielded like a failed `fs::read_dir`.
+///
+/// [`FsDirReader`]: struct.FsDirReader.html
+/// [`DirEntry::new`]: struct.DirEntry.html#method.new
+pub trait DirReader<C: ClientState>: Send + Sync + 'static {
+    fn read_dir(&self, read_dir_spec: &ReadDirSpec<C>) -> Result<Vec<Result<DirEntry<C>>>>;
+}
+
+/// Default [`DirReader`](trait.DirReader.html) that reads directories with
+/// `fs::read_dir`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FsDirReader;
+
+impl<C: ClientState> DirReader<C> for FsDirReader {
+    fn read_dir(&self, read_dir_spec: &ReadDirSpec<C>) -> Result<Vec<Result<DirEntry<C>>>> {
+        let read_dir = crate::read_dir(
+            read_dir_spec.depth,
+            &read_dir_spec.path,
+            &read_dir_spec.follow_link_ancestors,
+            None,
+            false,
+        )?;
+        Ok(read_dir.collect())
+    }
+}
@@ -0,0 +1,256 @@
+use std::ffi::OsString;
+use std::fs::{self, FileType};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use std::vec;
+
+use super::*;
+use crate::{ClientState, Result};
+
+/// Options that apply to `WalkDirGeneric::for_each`.
+pub(crate) struct LendOptions {
+    pub(crate) sort: bool,
+    pub(crate) include_root: bool,
+    pub(crate) min_depth: usize,
+    pub(crate) max_depth: usize,
+    pub(crate) first_n: usize,
+    pub(crate) skip_hidden: bool,
+    pub(crate) follow_root_links: bool,
+    pub(crate) skip_errors: bool,
+    pub(crate) abort_on_error: bool,
+    pub(crate) cancellation: Option<Arc<AtomicBool>>,
+    pub(crate) deadline: Option<Instant>,
+}
+
+// What `fs::read_dir` lists for one entry, copied out of `fs::DirEntry` so
+// the directory's listing can be sorted and counted before it is lent.
+pub(crate) struct Listed {
+    pub(crate) file_name: OsString,
+    pub(crate) file_type: FileType,
+    #[cfg(unix)]
+    pub(crate) ino: u64,
+    #[cfg(windows)]
+    pub(crate) metadata: Option<fs::Metadata>,
+}
+
+// A directory whose listing is being lent.
+struct Frame {
+    // Depth of the listed entries
+    depth: usize,
+    parent_path: Arc<Path>,
+    listing: vec::IntoIter<Result<Listed>>,
+    len: usize,
+}
+
+/// Walks serially in the order of `DirEntryIter`, calling `f` with one
+/// `DirEntry` that is overwritten for each entry instead of creating one.
+pub(crate) struct EntryLender<C: ClientState, F> {
+    options: LendOptions,
+    f: F,
+    remaining: usize,
+    frames: Vec<Frame>,
+    phantom: std::marker::PhantomData<C>,
+}
+
+impl<C, F> EntryLender<C, F>
+where
+    C: ClientState,
+    F: FnMut(std::result::Result<&DirEntry<C>, &Error>),
+{
+    pub(crate) fn new(options: LendOptions, f: F) -> EntryLender<C, F> {
+        EntryLender {
+            remaining: options.first_n,
+            options,
+            f,
+            frames: Vec::new(),
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    pub(crate) fn walk(mut self, roots: Vec<PathBuf>) {
+        let mut root_entry_results: Vec<_> = roots
+            .iter()
+            .enumerate()
+            .map(|(root_index, root)| {
+                let root_entry = DirEntry::from_path(0, root, false, Arc::new(Vec::new()));
+                let mut root_entry = crate::process_dir_entry_result(root_entry, false)?;
+                root_entry.root_index = root_index;
+                root_entry.yield_entry = self.options.include_root;
+                if !self.options.follow_root_links && root_entry.file_type.is_symlink() {
+                    root_entry.read_children_path = None;
+                }
+                Ok(root_entry)
+            })
+            .collect();
+        crate::number_siblings(&mut root_entry_results);
+
+        for root_entry_result in root_entry_results {
+            let keep_walking = match root_entry_result {
+                // The root's buffers are reused for the entries below it
+                Ok(mut root_entry) => {
+                    self.lend_dir_entry(&mut root_entry) && self.lend_frames(&mut root_entry)
+                }
+                Err(err) => self.lend(Err(&err)),
+            };
+            if !keep_walking {
+                return;
+            }
+        }
+    }
+
+    // Read the directory's contents if they are walked, lend the entry and
+    // push its listing. Returns `false` once the walk is stopped.
+    fn lend_dir_entry(&mut self, dir_entry: &mut DirEntry<C>) -> bool {
+        let read_path = dir_entry.read_children_path.clone();
+        let frame = match read_path {
+            Some(path) if dir_entry.depth < self.options.max_depth && !self.is_stopped() => {
+                match self.read_listing(dir_entry.depth, &path) {
+                    Ok(listing) => {
+                        dir_entry.child_count = Some(listing.len());
+                        Some(Frame {
+                            depth: dir_entry.depth + 1,
+                            parent_path: path,
+                            len: listing.len(),
+                            listing: listing.into_iter(),
+                        })
+                    }
+                    Err(err) => {
+                        if !self.options.skip_errors {
+                            dir_entry.read_children_error = Some(err);
+                        }
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        if dir_entry.yield_entry
+            && dir_entry.depth >= self.options.min_depth
+            && !self.lend(Ok(dir_entry))
+        {
+            return false;
+        }
+        self.frames.extend(frame);
+        true
+    }
+
+    // Lend the listings on the stack, deepest first, overwriting `lent`.
+    fn lend_frames(&mut self, lent: &mut DirEntry<C>) -> bool {
+        while let Some(frame) = self.frames.last_mut() {
+            let sibling_index = frame.len - frame.listing.len();
+            let last_sibling = sibling_index + 1 == frame.len;
+            let (depth, parent_path) = (frame.depth, frame.parent_path.clone());
+            let listed = match frame.listing.next() {
+                Some(Ok(listed)) => listed,
+                Some(Err(err)) => {
+                    if !self.lend(Err(&err)) {
+                        return false;
+                    }
+                    continue;
+                }
+                None => {
+                    self.frames.pop();
+                    continue;
+                }
+            };
+            lent.reuse_for(depth, &parent_path, listed);
+            lent.sibling_index = sibling_index;
+            lent.last_sibling = last_sibling;
+            if !self.lend_dir_entry(lent) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Entries of the directory at `path`, sorted and without hidden entries
+    // and skipped errors.
+    fn read_listing(&self, depth: usize, path: &Path) -> Result<Vec<Result<Listed>>> {
+        let fs_read_dir =
+            fs::read_dir(path).map_err(|err| Error::from_path(depth, path.to_path_buf(), err))?;
+        let mut listing: Vec<_> = fs_read_dir
+            .map(|fs_dir_entry| {
+                let fs_dir_entry = fs_dir_entry
+                    .map_err(|err| Error::from_path(depth + 1, path.to_path_buf(), err))?;
+                let file_type = fs_dir_entry
+                    .file_type()
+                    .map_err(|err| Error::from_path(depth + 1, fs_dir_entry.path(), err))?;
+                Ok(Listed {
+                    file_name: fs_dir_entry.file_name(),
+                    file_type,
+                    #[cfg(unix)]
+                    ino: std::os::unix::fs::DirEntryExt::ino(&fs_dir_entry),
+                    #[cfg(windows)]
+                    metadata: fs_dir_entry.metadata().ok(),
+                })
+            })
+            .filter(|listed| match listed {
+                Ok(listed) => !(self.options.skip_hidden && is_hidden(listed)),
+                Err(_) => !self.options.skip_errors,
+            })
+            .collect();
+        if self.options.sort {
+            // Errors last, like `Sort::Name`
+            listing.sort_by(|a, b| match (a, b) {
+                (Ok(a), Ok(b)) => a.file_name.cmp(&b.file_name),
+                (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+                (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+                (Err(_), Err(_)) => std::cmp::Ordering::Equal,
+            });
+        }
+        Ok(listing)
+    }
+
+    // Like `DirEntryIter`, directories aren't read once the walk is cancelled
+    // or out of time.
+    fn is_stopped(&self) -> bool {
+        self.options
+            .cancellation
+            .as_ref()
+            .is_some_and(|cancellation| cancellation.load(Ordering::Relaxed))
+            || self
+                .options
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    // Returns `false` once `first_n` results are lent or an error ends the
+    // walk.
+    fn lend(&mut self, dir_entry_result: std::result::Result<&DirEntry<C>, &Error>) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+        if self.options.skip_errors && dir_entry_result.is_err() {
+            return true;
+        }
+        let is_error = match dir_entry_result {
+            Ok(dir_entry) => dir_entry.read_children_error.is_some(),
+            Err(_) => true,
+        };
+        (self.f)(dir_entry_result);
+        self.remaining -= 1;
+        if self.options.abort_on_error && is_error {
+            self.remaining = 0;
+        }
+        self.remaining > 0
+    }
+}
+
+// Same rule as `skip_hidden` uses for `DirEntry`.
+fn is_hidden(listed: &Listed) -> bool {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        if listed
+            .metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+        {
+            return true;
+        }
+    }
+    listed.file_name.as_encoded_bytes().starts_with(b".")
+}
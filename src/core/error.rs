@@ -15,6 +15,9 @@ use crate::{ClientState, DirEntry};
 /// * The path, if any, associated with the IO error.
 /// * An indication that a loop occurred when following symbolic links. In this
 /// case, there is no underlying IO error.
+/// * An indication that a directory was too deep to descend into, see
+///   [`max_recursion_depth`](struct.WalkDirGeneric.html#method.max_recursion_depth).
+///   In this case, there is no underlying IO error either.
 ///
 /// To maintain good ergonomics, this type has a
 /// [`impl From<Error> for std::io::Error`][impl] defined which preserves the original context.
@@ -34,13 +37,16 @@ pub struct Error {
 enum ErrorInner {
     Io { path: Option<PathBuf>, err: io::Error },
     Loop { ancestor: PathBuf, child: PathBuf },
+    TooDeep { path: PathBuf },
 }
 
 impl Error {
     /// Returns the path associated with this error if one exists.
     ///
     /// For example, if an error occurred while opening a directory handle,
-    /// the error will include the path passed to [`std::fs::read_dir`].
+    /// the error will include the path passed to [`std::fs::read_dir`]. If an
+    /// error occurred while reading the entries of a directory, the path is
+    /// that directory.
     ///
     /// [`std::fs::read_dir`]: https://doc.rust-lang.org/stable/std/fs/fn.read_dir.html
     pub fn path(&self) -> Option<&Path> {
@@ -48,6 +54,7 @@ impl Error {
             ErrorInner::Io { path: None, .. } => None,
             ErrorInner::Io { path: Some(ref path), .. } => Some(path),
             ErrorInner::Loop { ref child, .. } => Some(child),
+            ErrorInner::TooDeep { ref path } => Some(path),
         }
     }
 
@@ -62,7 +69,7 @@ impl Error {
     /// [`path`] method.
     ///
     /// [`None`]: https://doc.rust-lang.org/stable/std/option/enum.Option.html#variant.None
-    /// [`path`]: struct.Error.html#path
+    /// [`path`]: struct.Error.html#method.path
     pub fn loop_ancestor(&self) -> Option<&Path> {
         match self.inner {
             ErrorInner::Loop { ref ancestor, .. } => Some(ancestor),
@@ -70,6 +77,16 @@ impl Error {
         }
     }
 
+    /// Returns `true` if this error was produced because a directory was at
+    /// the [`max_recursion_depth`] and wasn't descended into. The directory's
+    /// path is returned by the [`path`] method.
+    ///
+    /// [`max_recursion_depth`]: struct.WalkDirGeneric.html#method.max_recursion_depth
+    /// [`path`]: struct.Error.html#method.path
+    pub fn is_too_deep(&self) -> bool {
+        matches!(self.inner, ErrorInner::TooDeep { .. })
+    }
+
     /// Returns the depth at which this error occurred relative to the root.
     ///
     /// The smallest depth is `0` and always corresponds to the path given to
@@ -102,7 +119,7 @@ impl Error {
     /// use std::io;
     /// use std::path::Path;
     ///
-    /// use walkdir::WalkDir;
+    /// use jwalk::WalkDir;
     ///
     /// for entry in WalkDir::new("foo") {
     ///     match entry {
@@ -143,7 +160,7 @@ impl Error {
     pub fn io_error(&self) -> Option<&io::Error> {
         match self.inner {
             ErrorInner::Io { ref err, .. } => Some(err),
-            ErrorInner::Loop { .. } => None,
+            ErrorInner::Loop { .. } | ErrorInner::TooDeep { .. } => None,
         }
     }
 
@@ -155,7 +172,7 @@ impl Error {
     pub fn into_io_error(self) -> Option<io::Error> {
         match self.inner {
             ErrorInner::Io { err, .. } => Some(err),
-            ErrorInner::Loop { .. } => None,
+            ErrorInner::Loop { .. } | ErrorInner::TooDeep { .. } => None,
         }
     }
 
@@ -170,10 +187,6 @@ impl Error {
         }
     }
 
-    pub(crate) fn from_io(depth: usize, err: io::Error) -> Self {
-        Error { depth, inner: ErrorInner::Io { path: None, err } }
-    }
-
     pub(crate) fn from_loop(depth: usize, ancestor: &Path, child: &Path) -> Self {
         Error {
             depth,
@@ -183,6 +196,10 @@ impl Error {
             },
         }
     }
+
+    pub(crate) fn from_too_deep<C: ClientState>(dent: &DirEntry<C>) -> Self {
+        Error { depth: dent.depth(), inner: ErrorInner::TooDeep { path: dent.path().to_owned() } }
+    }
 }
 
 impl error::Error for Error {
@@ -191,6 +208,7 @@ impl error::Error for Error {
         match self.inner {
             ErrorInner::Io { ref err, .. } => err.description(),
             ErrorInner::Loop { .. } => "file system loop found",
+            ErrorInner::TooDeep { .. } => "maximum recursion depth reached",
         }
     }
 
@@ -201,7 +219,7 @@ impl error::Error for Error {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match self.inner {
             ErrorInner::Io { ref err, .. } => Some(err),
-            ErrorInner::Loop { .. } => None,
+            ErrorInner::Loop { .. } | ErrorInner::TooDeep { .. } => None,
         }
     }
 }
@@ -219,6 +237,8 @@ impl fmt::Display for Error {
                 child.display(),
                 ancestor.display()
             ),
+            ErrorInner::TooDeep { ref path } =>
+                write!(f, "Maximum recursion depth reached: {} was not read", path.display()),
         }
     }
 }
@@ -238,7 +258,8 @@ impl From<Error> for io::Error {
     fn from(walk_err: Error) -> io::Error {
         let kind = match walk_err {
             Error { inner: ErrorInner::Io { ref err, .. }, .. } => err.kind(),
-            Error { inner: ErrorInner::Loop { .. }, .. } => io::ErrorKind::Other,
+            Error { inner: ErrorInner::Loop { .. } | ErrorInner::TooDeep { .. }, .. } =>
+                io::ErrorKind::Other,
         };
         io::Error::new(kind, walk_err)
     }
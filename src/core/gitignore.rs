@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use super::Glob;
+
+/// Rules read from a directory's `.gitignore`, chained to the rules of the
+/// directories above it.
+#[derive(Debug)]
+pub(crate) struct Gitignore {
+    dir: Arc<Path>,
+    rules: Vec<Rule>,
+    parent: Option<Arc<Gitignore>>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl Gitignore {
+    /// Read `dir/.gitignore` if it exists. Returns `parent` unchanged if there
+    /// is no `.gitignore` or it contains no rules.
+    pub(crate) fn read(dir: &Arc<Path>, parent: Option<Arc<Gitignore>>) -> Option<Arc<Gitignore>> {
+        let contents = match fs::read_to_string(dir.join(".gitignore")) {
+            Ok(contents) => contents,
+            Err(_) => return parent,
+        };
+        let rules: Vec<Rule> = contents.lines().filter_map(Rule::parse).collect();
+        if rules.is_empty() {
+            return parent;
+        }
+        Some(Arc::new(Gitignore { dir: dir.clone(), rules, parent }))
+    }
+
+    /// Returns `true` if `path` is ignored by these rules or the rules of a
+    /// parent directory.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if let Ok(relative_path) = path.strip_prefix(&self.dir) {
+            let matched = self
+                .rules
+                .iter()
+                .rev()
+                .find(|rule| (!rule.dir_only || is_dir) && rule.glob.is_match(relative_path));
+            if let Some(rule) = matched {
+                return !rule.negated;
+            }
+        }
+        self.parent.as_ref().is_some_and(|parent| parent.is_ignored(path, is_dir))
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        if pattern.is_empty() {
+            return None;
+        }
+
+        // Patterns without a slash match at any depth below the .gitignore,
+        // others are relative to it.
+        let glob = if pattern.contains('/') {
+            Glob::new(pattern.trim_start_matches('/'))
+        } else {
+            Glob::new(&format!("**/{}", pattern))
+        };
+
+        Some(Rule { glob, negated, dir_only })
+    }
+}
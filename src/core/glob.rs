@@ -0,0 +1,182 @@
+use std::path::{Component, Path};
+
+/// Shell style pattern matched against relative paths.
+///
+/// Supports `?`, `*`, `[a-z]`, `[!a-z]` and `\` escapes within a path
+/// component. A `**` component matches zero or more components. Patterns are
+/// compiled once when the walk is configured.
+#[derive(Clone, Debug)]
+pub(crate) struct Glob {
+    components: Vec<GlobComponent>,
+}
+
+#[derive(Clone, Debug)]
+enum GlobComponent {
+    AnyComponents,
+    Tokens(Vec<Token>),
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyChars,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> Glob {
+        let components = pattern
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .map(|component| {
+                if component == "**" {
+                    GlobComponent::AnyComponents
+                } else {
+                    GlobComponent::Tokens(parse_tokens(component))
+                }
+            })
+            .collect();
+        Glob { components }
+    }
+
+    pub(crate) fn is_match(&self, path: &Path) -> bool {
+        let names: Vec<Vec<char>> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().chars().collect()),
+                _ => None,
+            })
+            .collect();
+        match_components(&self.components, &names)
+    }
+}
+
+fn parse_tokens(component: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = component.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '?' => Token::AnyChar,
+            '*' => {
+                while chars.peek() == Some(&'*') {
+                    chars.next();
+                }
+                Token::AnyChars
+            }
+            '\\' => Token::Char(chars.next().unwrap_or('\\')),
+            '[' => {
+                let rest: String = chars.clone().collect();
+                match parse_class(&rest) {
+                    Some((token, consumed)) => {
+                        for _ in 0..consumed {
+                            chars.next();
+                        }
+                        token
+                    }
+                    None => Token::Char('['),
+                }
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+// Parse the contents of a `[...]` class, returning the token and the number of
+// chars consumed including the closing `]`. Returns `None` if unterminated.
+fn parse_class(rest: &str) -> Option<(Token, usize)> {
+    let chars: Vec<char> = rest.chars().collect();
+    let mut i = 0;
+    let negated = matches!(chars.first(), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ']' && !first {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        first = false;
+        if i + 2 < chars.len() && chars[i + 1] == '-' && chars[i + 2] != ']' {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    None
+}
+
+fn match_components(pattern: &[GlobComponent], names: &[Vec<char>]) -> bool {
+    match_wildcards(
+        pattern,
+        names,
+        |component| matches!(component, GlobComponent::AnyComponents),
+        |component, name| match component {
+            GlobComponent::AnyComponents => true,
+            GlobComponent::Tokens(tokens) => match_tokens(tokens, name),
+        },
+    )
+}
+
+fn match_tokens(tokens: &[Token], name: &[char]) -> bool {
+    match_wildcards(
+        tokens,
+        name,
+        |token| matches!(token, Token::AnyChars),
+        |token, c| match_token(token, *c),
+    )
+}
+
+// Match `items` against `pattern`, where wildcards match any number of items
+// and every other element matches a single item. Only the most recent
+// wildcard is retried when a match fails, since an earlier one can't do any
+// better, so this takes at most `pattern.len() * items.len()` steps instead
+// of backtracking exponentially on patterns such as `*a*a*a*b`.
+fn match_wildcards<P, I>(
+    pattern: &[P],
+    items: &[I],
+    is_wildcard: impl Fn(&P) -> bool,
+    is_match: impl Fn(&P, &I) -> bool,
+) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // Pattern index after the last wildcard and the item index it resumes at
+    let mut retry = None;
+    while i < items.len() {
+        match pattern.get(p) {
+            Some(each) if is_wildcard(each) => {
+                p += 1;
+                retry = Some((p, i));
+            }
+            Some(each) if is_match(each, &items[i]) => {
+                p += 1;
+                i += 1;
+            }
+            _ => match retry {
+                Some((retry_p, retry_i)) => {
+                    p = retry_p;
+                    i = retry_i + 1;
+                    retry = Some((retry_p, i));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(is_wildcard)
+}
+
+fn match_token(token: &Token, c: char) -> bool {
+    match token {
+        Token::Char(expected) => *expected == c,
+        Token::AnyChar => true,
+        Token::AnyChars => true,
+        Token::Class { negated, ranges } => {
+            ranges.iter().any(|(start, end)| *start <= c && c <= *end) != *negated
+        }
+    }
+}
@@ -1,31 +1,74 @@
+mod ancestor;
+mod device_id;
 mod dir_entry;
+#[cfg(feature = "serde")]
+mod dir_entry_snapshot;
 mod dir_entry_iter;
+#[cfg(feature = "async")]
+mod dir_entry_stream;
+mod dir_iter;
+mod dir_reader;
+mod entry_lender;
 mod error;
+mod gitignore;
+mod glob;
 mod index_path;
 mod jwalk_par_bridge;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod open_at;
 mod ordered;
 mod ordered_queue;
+mod par_dir_entry_iter;
+mod queue_limit;
 mod read_dir;
 mod read_dir_iter;
 mod read_dir_spec;
 mod run_context;
+mod semaphore;
+mod std_dir_entry_iter;
+mod thread_state;
+mod walk_state;
+#[cfg(jwalk_xattr)]
+mod xattr;
 
 use rayon::prelude::*;
 use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::vec;
 
+pub(crate) use ancestor::{Ancestor, FileId};
+pub(crate) use device_id::DeviceId;
+pub(crate) use entry_lender::{EntryLender, LendOptions, Listed};
+pub(crate) use gitignore::Gitignore;
+pub(crate) use glob::Glob;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use open_at::{DirName, DirNames, OpenAt};
+pub(crate) use semaphore::Semaphore;
+pub(crate) use thread_state::ThreadState;
+pub(crate) use walk_state::PendingEntry;
+use dir_entry_iter::is_error;
 use index_path::*;
 use ordered::*;
 use ordered_queue::*;
+use queue_limit::QueueLimit;
 use read_dir_iter::*;
+pub(crate) use read_dir_iter::ReadDirCallback;
 use run_context::*;
 
 pub use self::jwalk_par_bridge::{JWalkIterBridge, JWalkParallelBridge};
 pub use dir_entry::DirEntry;
+#[cfg(feature = "serde")]
+pub use dir_entry_snapshot::DirEntrySnapshot;
 pub use dir_entry_iter::DirEntryIter;
+#[cfg(feature = "async")]
+pub use dir_entry_stream::DirEntryStream;
+pub use dir_iter::DirIter;
+pub use dir_reader::{DirReader, FsDirReader};
 pub use error::Error;
+pub use par_dir_entry_iter::ParDirEntryIter;
 pub use read_dir::ReadDir;
 pub use read_dir_spec::ReadDirSpec;
+pub use std_dir_entry_iter::StdDirEntryIter;
+pub use walk_state::WalkState;
 
-use crate::{ClientState, Parallelism};
+use crate::{ClientState, Parallelism, TraversalOrder};
@@ -0,0 +1,150 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::sync::{Arc, OnceLock};
+
+use rustix::fs::{openat, statat, AtFlags, Dir, FileType, Mode, OFlags};
+
+/// A directory opened relative to the file descriptor of its parent, so that
+/// it can't be swapped for a symbolic link between being listed and read.
+#[derive(Clone, Debug)]
+pub(crate) struct OpenAt {
+    parent: Arc<OwnedFd>,
+    name: OsString,
+}
+
+impl OpenAt {
+    pub(crate) fn new(parent: Arc<OwnedFd>, name: OsString) -> OpenAt {
+        OpenAt { parent, name }
+    }
+
+    /// Open the directory. Fails if it is no longer a directory, including
+    /// when it has been replaced by a symbolic link.
+    pub(crate) fn open(&self) -> io::Result<Arc<OwnedFd>> {
+        let flags = OFlags::RDONLY | OFlags::DIRECTORY | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+        Ok(Arc::new(openat(&*self.parent, &*self.name, flags, Mode::empty())?))
+    }
+}
+
+/// An entry listed by `DirNames`, with the file type and inode number the
+/// listing reports.
+pub(crate) struct DirName {
+    pub(crate) name: OsString,
+    file_type: FileType,
+    ino: u64,
+}
+
+impl DirName {
+    /// The entry `name`, whose file type is read when it is needed.
+    pub(crate) fn new(name: OsString) -> DirName {
+        DirName { name, file_type: FileType::Unknown, ino: 0 }
+    }
+
+    /// File type and inode number of this entry in the directory `dir`,
+    /// without following symbolic links. Only read with `statat` when the
+    /// file system doesn't report the type in the listing.
+    pub(crate) fn file_type(&self, dir: &OwnedFd) -> io::Result<(fs::FileType, u64)> {
+        let (file_type, ino) = match self.file_type {
+            FileType::Unknown => {
+                let stat = statat(dir, &*self.name, AtFlags::SYMLINK_NOFOLLOW)?;
+                (FileType::from_raw_mode(stat.st_mode), stat.st_ino)
+            }
+            file_type => (file_type, self.ino),
+        };
+        Ok((std_file_type(dir, &self.name, file_type)?, ino))
+    }
+}
+
+/// Entries of a directory, excluding `.` and `..`.
+pub(crate) struct DirNames(Dir);
+
+impl DirNames {
+    pub(crate) fn read_from(dir: &OwnedFd) -> io::Result<DirNames> {
+        Ok(DirNames(Dir::read_from(dir)?))
+    }
+}
+
+impl Iterator for DirNames {
+    type Item = io::Result<DirName>;
+
+    fn next(&mut self) -> Option<io::Result<DirName>> {
+        loop {
+            match self.0.next()? {
+                Ok(entry) => {
+                    let name = entry.file_name().to_bytes();
+                    if name != b"." && name != b".." {
+                        return Some(Ok(DirName {
+                            name: OsStr::from_bytes(name).to_owned(),
+                            file_type: entry.file_type(),
+                            ino: entry.ino(),
+                        }));
+                    }
+                }
+                Err(err) => return Some(Err(err.into())),
+            }
+        }
+    }
+}
+
+/// The `fs::FileType` of `file_type`, for the entry `name` in the directory
+/// `dir`.
+fn std_file_type(dir: &OwnedFd, name: &OsStr, file_type: FileType) -> io::Result<fs::FileType> {
+    // `fs::FileType` can't be built from a mode, so one is kept for each
+    // kind of file the first time it is seen.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const UNSEEN: OnceLock<fs::FileType> = OnceLock::new();
+    static FILE_TYPES: [OnceLock<fs::FileType>; 8] = [UNSEEN; 8];
+
+    let index = match file_type {
+        FileType::RegularFile => 0,
+        FileType::Directory => 1,
+        FileType::Symlink => 2,
+        FileType::Fifo => 3,
+        FileType::Socket => 4,
+        FileType::CharacterDevice => 5,
+        FileType::BlockDevice => 6,
+        FileType::Unknown => 7,
+    };
+    Ok(match FILE_TYPES[index].get() {
+        Some(file_type) => *file_type,
+        None => {
+            let file_type = symlink_metadata_at(dir, name)?.file_type();
+            // The entry may have been replaced in between
+            if index == file_type_index(file_type) {
+                let _ = FILE_TYPES[index].set(file_type);
+            }
+            file_type
+        }
+    })
+}
+
+fn file_type_index(file_type: fs::FileType) -> usize {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_file() {
+        0
+    } else if file_type.is_dir() {
+        1
+    } else if file_type.is_symlink() {
+        2
+    } else if file_type.is_fifo() {
+        3
+    } else if file_type.is_socket() {
+        4
+    } else if file_type.is_char_device() {
+        5
+    } else if file_type.is_block_device() {
+        6
+    } else {
+        7
+    }
+}
+
+/// Metadata of the entry `name` in the directory `dir`, without following
+/// symbolic links.
+pub(crate) fn symlink_metadata_at(dir: &OwnedFd, name: &OsStr) -> io::Result<fs::Metadata> {
+    let flags = OFlags::PATH | OFlags::NOFOLLOW | OFlags::CLOEXEC;
+    File::from(openat(dir, name, flags, Mode::empty())?).metadata()
+}
@@ -1,6 +1,7 @@
 //! Ordered queue backed by a channel.
 
 use crossbeam::channel::{self, Receiver, SendError, Sender, TryRecvError};
+use std::cmp::Reverse;
 use std::collections::BinaryHeap;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
 use std::sync::Arc;
@@ -18,8 +19,14 @@ where
 }
 
 pub enum Ordering {
+    /// Yield the first received item in depth first order.
     Relaxed,
+    /// Yield the first received item in breadth first order.
+    RelaxedBreadthFirst,
+    /// Yield items in depth first order without gaps.
     Strict,
+    /// Yield items in the order they are received, without gaps.
+    Arrival,
 }
 
 pub struct OrderedQueueIter<T>
@@ -29,9 +36,13 @@ where
     ordering: Ordering,
     stop: Arc<AtomicBool>,
     receiver: Receiver<Ordered<T>>,
-    receive_buffer: BinaryHeap<Ordered<T>>,
+    // items keyed by depth when breadth first, otherwise by zero
+    receive_buffer: BinaryHeap<(Reverse<usize>, Ordered<T>)>,
     pending_count: Arc<AtomicUsize>,
     ordered_matcher: OrderedMatcher,
+    // items still to be received when ordering by arrival, each received item
+    // is replaced by its children
+    arrival_remaining: usize,
 }
 
 struct OrderedMatcher {
@@ -42,6 +53,8 @@ struct OrderedMatcher {
 pub(crate) fn new_ordered_queue<T>(
     stop: Arc<AtomicBool>,
     ordering: Ordering,
+    root_count: usize,
+    capacity: usize,
 ) -> (OrderedQueue<T>, OrderedQueueIter<T>)
 where
     T: Send,
@@ -57,9 +70,10 @@ where
         OrderedQueueIter {
             ordering,
             receiver,
-            ordered_matcher: OrderedMatcher::default(),
-            receive_buffer: BinaryHeap::new(),
+            ordered_matcher: OrderedMatcher::new(root_count),
+            receive_buffer: BinaryHeap::with_capacity(capacity),
             pending_count,
+            arrival_remaining: root_count,
             stop,
         },
     )
@@ -104,16 +118,24 @@ where
         self.stop.load(AtomicOrdering::SeqCst)
     }
 
+    fn buffer(&mut self, ordered: Ordered<T>) {
+        let depth = match self.ordering {
+            Ordering::RelaxedBreadthFirst => ordered.index_path.indices.len(),
+            Ordering::Relaxed | Ordering::Strict | Ordering::Arrival => 0,
+        };
+        self.receive_buffer.push((Reverse(depth), ordered));
+    }
+
     fn try_next_relaxed(&mut self) -> Result<Ordered<T>, TryRecvError> {
         if self.is_stop() {
             return Err(TryRecvError::Disconnected);
         }
 
         while let Ok(ordered_work) = self.receiver.try_recv() {
-            self.receive_buffer.push(ordered_work)
+            self.buffer(ordered_work)
         }
 
-        if let Some(ordered_work) = self.receive_buffer.pop() {
+        if let Some((_, ordered_work)) = self.receive_buffer.pop() {
             Ok(ordered_work)
         } else if self.pending_count() == 0 {
             Err(TryRecvError::Disconnected)
@@ -122,16 +144,26 @@ where
         }
     }
 
+    fn try_next_arrival(&mut self) -> Result<Ordered<T>, TryRecvError> {
+        if self.is_stop() || self.arrival_remaining == 0 {
+            return Err(TryRecvError::Disconnected);
+        }
+
+        let ordered = self.receiver.try_recv()?;
+        self.arrival_remaining += ordered.child_count;
+        self.arrival_remaining -= 1;
+        Ok(ordered)
+    }
+
     fn try_next_strict(&mut self) -> Result<Ordered<T>, TryRecvError> {
-        let looking_for = &self.ordered_matcher.looking_for;
         loop {
             if self.is_stop() {
                 return Err(TryRecvError::Disconnected);
             }
 
             let top_ordered = self.receive_buffer.peek();
-            if let Some(top_ordered) = top_ordered {
-                if top_ordered.index_path.eq(looking_for) {
+            if let Some((_, top_ordered)) = top_ordered {
+                if top_ordered.index_path.eq(&self.ordered_matcher.looking_for) {
                     break;
                 }
             }
@@ -142,7 +174,7 @@ where
 
             match self.receiver.try_recv() {
                 Ok(ordered) => {
-                    self.receive_buffer.push(ordered);
+                    self.buffer(ordered);
                 }
                 Err(err) => match err {
                     TryRecvError::Empty => thread::yield_now(),
@@ -151,7 +183,7 @@ where
             }
         }
 
-        let ordered = self.receive_buffer.pop().unwrap();
+        let (_, ordered) = self.receive_buffer.pop().unwrap();
         self.ordered_matcher.advance_past(&ordered);
         Ok(ordered)
     }
@@ -165,8 +197,9 @@ where
     fn next(&mut self) -> Option<Ordered<T>> {
         loop {
             let try_next = match self.ordering {
-                Ordering::Relaxed => self.try_next_relaxed(),
+                Ordering::Relaxed | Ordering::RelaxedBreadthFirst => self.try_next_relaxed(),
                 Ordering::Strict => self.try_next_strict(),
+                Ordering::Arrival => self.try_next_arrival(),
             };
             match try_next {
                 Ok(next) => {
@@ -210,11 +243,11 @@ impl OrderedMatcher {
     }
 }
 
-impl Default for OrderedMatcher {
-    fn default() -> OrderedMatcher {
+impl OrderedMatcher {
+    fn new(root_count: usize) -> OrderedMatcher {
         OrderedMatcher {
             looking_for: IndexPath::new(vec![0]),
-            child_count_stack: vec![1],
+            child_count_stack: vec![root_count],
         }
     }
 }
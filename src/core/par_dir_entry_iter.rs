@@ -0,0 +1,205 @@
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::ParallelIterator;
+use std::sync::atomic::Ordering;
+
+use super::*;
+use crate::Result;
+
+/// Unordered parallel DirEntry iterator from `WalkDir.into_par_iter()`.
+///
+/// Directories are read by the rayon thread that consumes their parent entry,
+/// so entries are yielded in no particular order. This avoids the ordering and
+/// buffering done by [`DirEntryIter`](struct.DirEntryIter.html).
+pub struct ParDirEntryIter<C: ClientState> {
+    parallelism: Parallelism,
+    producer: DirEntryProducer<C>,
+}
+
+// Entry that hasn't been yielded yet, with the spec for reading its children.
+type Unread<C> = (Result<DirEntry<C>>, Option<ReadDirSpec<C>>);
+
+struct DirEntryProducer<C: ClientState> {
+    min_depth: usize,
+    // set once an error is yielded, shared by all producers when aborting on
+    // error
+    aborted: Option<Arc<AtomicBool>>,
+    core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    // entries whose children have been read, ready to be yielded
+    ready: Vec<Result<DirEntry<C>>>,
+    // stack of entries whose children still need to be read
+    unread: Vec<Unread<C>>,
+}
+
+impl<C: ClientState> ParDirEntryIter<C> {
+    pub(crate) fn new(
+        root_entry_results: Vec<Result<DirEntry<C>>>,
+        parallelism: Parallelism,
+        min_depth: usize,
+        abort_on_error: bool,
+        root_read_dir_state: C::ReadDirState,
+        core_read_dir_callback: Arc<ReadDirCallback<C>>,
+    ) -> ParDirEntryIter<C> {
+        let mut unread = Vec::new();
+        push_unread(&mut unread, root_entry_results, root_read_dir_state);
+        ParDirEntryIter {
+            parallelism,
+            producer: DirEntryProducer {
+                min_depth,
+                aborted: abort_on_error.then(|| Arc::new(AtomicBool::new(false))),
+                core_read_dir_callback,
+                ready: Vec::new(),
+                unread,
+            },
+        }
+    }
+}
+
+impl<C: ClientState> ParallelIterator for ParDirEntryIter<C> {
+    type Item = Result<DirEntry<C>>;
+
+    fn drive_unindexed<T>(self, consumer: T) -> T::Result
+    where
+        T: UnindexedConsumer<Self::Item>,
+    {
+        let ParDirEntryIter { parallelism, producer } = self;
+        match parallelism {
+            Parallelism::Serial => producer.fold_with(consumer.into_folder()).complete(),
+            Parallelism::RayonDefaultPool => bridge_unindexed(producer, consumer),
+            Parallelism::RayonExistingPool(thread_pool) => {
+                thread_pool.install(|| bridge_unindexed(producer, consumer))
+            }
+            Parallelism::RayonNewPool(num_threads) => {
+                match Parallelism::build_thread_pool(num_threads) {
+                    Some(thread_pool) => {
+                        thread_pool.install(|| bridge_unindexed(producer, consumer))
+                    }
+                    None => bridge_unindexed(producer, consumer),
+                }
+            }
+        }
+    }
+}
+
+impl<C: ClientState> DirEntryProducer<C> {
+    // Read the children of an unread entry, pushing them onto `unread`, and
+    // return the entry ready to be yielded.
+    fn read(
+        core_read_dir_callback: &ReadDirCallback<C>,
+        (dir_entry_result, read_dir_spec): Unread<C>,
+        unread: &mut Vec<Unread<C>>,
+    ) -> Result<DirEntry<C>> {
+        let mut dir_entry = dir_entry_result?;
+        if let Some(read_dir_spec) = read_dir_spec {
+            match core_read_dir_callback(read_dir_spec) {
+                Ok(ReadDir { read_dir_state, results_list, read }) => {
+                    dir_entry.child_count = if read { Some(results_list.len()) } else { None };
+                    push_unread(unread, results_list, read_dir_state)
+                }
+                Err(err) => dir_entry.read_children_error = Some(err),
+            }
+        }
+        Ok(dir_entry)
+    }
+
+    fn is_yielded(&self, dir_entry_result: &Result<DirEntry<C>>) -> bool {
+        dir_entry_result.as_ref().map_or(true, |dir_entry| {
+            dir_entry.depth >= self.min_depth && dir_entry.yield_entry
+        })
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.aborted
+            .as_ref()
+            .is_some_and(|aborted| aborted.load(Ordering::Relaxed))
+    }
+
+    // Yield an entry, returning false once the walk should stop.
+    fn consume<F>(&self, folder: F, dir_entry_result: Result<DirEntry<C>>) -> (F, bool)
+    where
+        F: Folder<Result<DirEntry<C>>>,
+    {
+        if let Some(aborted) = self.aborted.as_ref() {
+            if is_error(&dir_entry_result) {
+                aborted.store(true, Ordering::Relaxed);
+            }
+        }
+        let folder = folder.consume(dir_entry_result);
+        let more = !folder.full() && !self.is_aborted();
+        (folder, more)
+    }
+}
+
+impl<C: ClientState> UnindexedProducer for DirEntryProducer<C> {
+    type Item = Result<DirEntry<C>>;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        // Read single directories until there is more than one entry to share
+        while self.unread.len() == 1 && !self.is_aborted() {
+            let next = self.unread.pop().unwrap();
+            let dir_entry_result =
+                Self::read(&*self.core_read_dir_callback, next, &mut self.unread);
+            self.ready.push(dir_entry_result);
+        }
+
+        if self.unread.len() < 2 || self.is_aborted() {
+            return (self, None);
+        }
+
+        let other = DirEntryProducer {
+            min_depth: self.min_depth,
+            aborted: self.aborted.clone(),
+            core_read_dir_callback: self.core_read_dir_callback.clone(),
+            ready: Vec::new(),
+            unread: self.unread.split_off(self.unread.len() / 2),
+        };
+        (self, Some(other))
+    }
+
+    fn fold_with<F>(mut self, mut folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        if self.is_aborted() {
+            return folder;
+        }
+
+        for dir_entry_result in std::mem::take(&mut self.ready) {
+            if self.is_yielded(&dir_entry_result) {
+                let (next_folder, more) = self.consume(folder, dir_entry_result);
+                folder = next_folder;
+                if !more {
+                    return folder;
+                }
+            }
+        }
+
+        while let Some(next) = self.unread.pop() {
+            let dir_entry_result =
+                Self::read(&*self.core_read_dir_callback, next, &mut self.unread);
+            if self.is_yielded(&dir_entry_result) {
+                let (next_folder, more) = self.consume(folder, dir_entry_result);
+                folder = next_folder;
+                if !more {
+                    break;
+                }
+            }
+        }
+
+        folder
+    }
+}
+
+// Push entries in reverse so that they are popped in order.
+fn push_unread<C: ClientState>(
+    unread: &mut Vec<Unread<C>>,
+    dir_entry_results: Vec<Result<DirEntry<C>>>,
+    read_dir_state: C::ReadDirState,
+) {
+    unread.extend(dir_entry_results.into_iter().rev().map(|mut dir_entry_result| {
+        let read_dir_spec = match dir_entry_result.as_mut() {
+            Ok(dir_entry) => dir_entry.read_children_spec(read_dir_state.clone()),
+            Err(_) => None,
+        };
+        (dir_entry_result, read_dir_spec)
+    }));
+}
@@ -0,0 +1,66 @@
+use std::sync::{Condvar, Mutex};
+
+/// Limits how many entries are read ahead of the consumer.
+///
+/// Workers wait before reading a directory while the limit is reached. To
+/// avoid deadlock they never wait while the consumer is itself waiting for a
+/// directory, so the limit can be exceeded when results arrive out of order.
+pub(crate) struct QueueLimit {
+    max_queued_entries: usize,
+    state: Mutex<QueueLimitState>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct QueueLimitState {
+    queued_entries: usize,
+    consumer_waiting: bool,
+    closed: bool,
+}
+
+impl QueueLimit {
+    pub(crate) fn new(max_queued_entries: usize) -> QueueLimit {
+        QueueLimit {
+            max_queued_entries,
+            state: Mutex::new(QueueLimitState::default()),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Called by workers before reading a directory. Waiting workers are
+    /// woken when the consumer takes a directory, starts waiting or is
+    /// dropped.
+    pub(crate) fn wait(&self) {
+        let mut state = self.state.lock().unwrap();
+        while state.queued_entries >= self.max_queued_entries
+            && !state.consumer_waiting
+            && !state.closed
+        {
+            state = self.condvar.wait(state).unwrap();
+        }
+    }
+
+    /// Called by workers after reading a directory with `count` entries.
+    pub(crate) fn add(&self, count: usize) {
+        self.state.lock().unwrap().queued_entries += count;
+    }
+
+    /// Called by the consumer after taking a directory with `count` entries.
+    pub(crate) fn remove(&self, count: usize) {
+        self.update(|state| state.queued_entries = state.queued_entries.saturating_sub(count));
+    }
+
+    pub(crate) fn set_consumer_waiting(&self, consumer_waiting: bool) {
+        self.update(|state| state.consumer_waiting = consumer_waiting);
+    }
+
+    /// Called when the consumer is dropped. Releases all waiting workers.
+    pub(crate) fn close(&self) {
+        self.update(|state| state.closed = true);
+    }
+
+    fn update<F: FnOnce(&mut QueueLimitState)>(&self, f: F) {
+        f(&mut self.state.lock().unwrap());
+        self.condvar.notify_all();
+    }
+}
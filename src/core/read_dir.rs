@@ -6,6 +6,8 @@ use crate::Result;
 pub struct ReadDir<C: ClientState> {
     pub(crate) read_dir_state: C::ReadDirState,
     pub(crate) results_list: Vec<Result<DirEntry<C>>>,
+    // False if the directory was skipped without being read.
+    pub(crate) read: bool,
 }
 
 impl<C: ClientState> ReadDir<C> {
@@ -16,19 +18,30 @@ impl<C: ClientState> ReadDir<C> {
         ReadDir {
             read_dir_state,
             results_list,
+            read: true,
         }
     }
 
-    pub fn read_children_specs(&self) -> impl Iterator<Item = ReadDirSpec<C>> + '_ {
-        self.results_list.iter().filter_map(move |each| {
-            each.as_ref()
+    // Result for a directory that is skipped without being read.
+    pub(crate) fn unread(read_dir_state: C::ReadDirState) -> ReadDir<C> {
+        ReadDir {
+            read_dir_state,
+            results_list: Vec::new(),
+            read: false,
+        }
+    }
+
+    pub fn read_children_specs(&mut self) -> impl Iterator<Item = ReadDirSpec<C>> + '_ {
+        let read_dir_state = &self.read_dir_state;
+        self.results_list.iter_mut().filter_map(move |each| {
+            each.as_mut()
                 .ok()?
-                .read_children_spec(self.read_dir_state.clone())
+                .read_children_spec(read_dir_state.clone())
         })
     }
 
     pub fn ordered_read_children_specs(
-        &self,
+        &mut self,
         index_path: &IndexPath,
     ) -> Vec<Ordered<ReadDirSpec<C>>> {
         self.read_children_specs()
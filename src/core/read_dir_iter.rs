@@ -1,3 +1,4 @@
+use crossbeam::channel::{self, Receiver};
 use std::sync::Arc;
 
 use super::*;
@@ -19,32 +20,83 @@ pub enum ReadDirIter<C: ClientState> {
     },
     ParWalk {
         read_dir_result_iter: OrderedQueueIter<Result<ReadDir<C>>>,
+        queue_limit: Arc<QueueLimit>,
+    },
+    BackgroundWalk {
+        read_dir_result_receiver: Receiver<Result<ReadDir<C>>>,
+        queue_limit: Arc<QueueLimit>,
     },
 }
 
 impl<C: ClientState> ReadDirIter<C> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         read_dir_specs: Vec<ReadDirSpec<C>>,
         parallelism: Parallelism,
+        max_queued_entries: usize,
+        traversal_order: TraversalOrder,
+        unordered: bool,
+        background: bool,
+        capacity: usize,
         core_read_dir_callback: Arc<ReadDirCallback<C>>,
     ) -> Self {
         if let Parallelism::Serial = parallelism {
-            ReadDirIter::Walk {
-                read_dir_spec_stack: read_dir_specs,
+            let mut read_dir_spec_stack = Vec::with_capacity(capacity);
+            read_dir_spec_stack.extend(read_dir_specs.into_iter().rev());
+            let walk = ReadDirIter::Walk {
+                read_dir_spec_stack,
                 core_read_dir_callback,
+            };
+            if !background {
+                return walk;
+            }
+
+            // Same serial walk, read ahead on its own thread
+            let queue_limit = Arc::new(QueueLimit::new(max_queued_entries));
+            let (read_dir_result_sender, read_dir_result_receiver) = channel::unbounded();
+            let worker_queue_limit = queue_limit.clone();
+            std::thread::spawn(move || {
+                let mut walk = walk;
+                loop {
+                    worker_queue_limit.wait();
+                    let Some(read_dir_result) = walk.next() else {
+                        break;
+                    };
+                    if let Ok(read_dir) = read_dir_result.as_ref() {
+                        worker_queue_limit.add(read_dir.results_list.len());
+                    }
+                    if read_dir_result_sender.send(read_dir_result).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            ReadDirIter::BackgroundWalk {
+                read_dir_result_receiver,
+                queue_limit,
             }
         } else {
             let stop = Arc::new(AtomicBool::new(false));
-            let read_dir_result_queue = new_ordered_queue(stop.clone(), Ordering::Strict);
+            let queue_limit = Arc::new(QueueLimit::new(max_queued_entries));
+            let root_count = read_dir_specs.len();
+            let result_ordering = if unordered { Ordering::Arrival } else { Ordering::Strict };
+            let read_dir_result_queue =
+                new_ordered_queue(stop.clone(), result_ordering, root_count, capacity);
             let (read_dir_result_queue, read_dir_result_iter) = read_dir_result_queue;
 
+            let worker_queue_limit = queue_limit.clone();
             let walk_closure = move || {
-                let read_dir_spec_queue = new_ordered_queue(stop.clone(), Ordering::Relaxed);
+                let ordering = match traversal_order {
+                    TraversalOrder::DepthFirst => Ordering::Relaxed,
+                    TraversalOrder::BreadthFirst => Ordering::RelaxedBreadthFirst,
+                };
+                let read_dir_spec_queue =
+                    new_ordered_queue(stop.clone(), ordering, root_count, capacity);
                 let (read_dir_spec_queue, read_dir_spec_iter) = read_dir_spec_queue;
 
                 for (i, read_dir_spec) in read_dir_specs.into_iter().enumerate() {
                     read_dir_spec_queue
-                        .push(Ordered::new(read_dir_spec, IndexPath::new(vec![0]), i))
+                        .push(Ordered::new(read_dir_spec, IndexPath::new(vec![i]), 0))
                         .unwrap();
                 }
 
@@ -52,6 +104,7 @@ impl<C: ClientState> ReadDirIter<C> {
                     stop,
                     read_dir_spec_queue,
                     read_dir_result_queue,
+                    queue_limit: worker_queue_limit,
                     core_read_dir_callback,
                 };
 
@@ -71,6 +124,7 @@ impl<C: ClientState> ReadDirIter<C> {
 
             ReadDirIter::ParWalk {
                 read_dir_result_iter,
+                queue_limit,
             }
         }
     }
@@ -85,9 +139,9 @@ impl<C: ClientState> Iterator for ReadDirIter<C> {
                 core_read_dir_callback,
             } => {
                 let read_dir_spec = read_dir_spec_stack.pop()?;
-                let read_dir_result = core_read_dir_callback(read_dir_spec);
+                let mut read_dir_result = core_read_dir_callback(read_dir_spec);
 
-                if let Ok(read_dir) = read_dir_result.as_ref() {
+                if let Ok(read_dir) = read_dir_result.as_mut() {
                     for each_spec in read_dir
                         .read_children_specs()
                         .collect::<Vec<_>>()
@@ -103,9 +157,43 @@ impl<C: ClientState> Iterator for ReadDirIter<C> {
 
             ReadDirIter::ParWalk {
                 read_dir_result_iter,
-            } => read_dir_result_iter
-                .next()
-                .map(|read_dir_result| read_dir_result.value),
+                queue_limit,
+            } => {
+                queue_limit.set_consumer_waiting(true);
+                let read_dir_result = read_dir_result_iter.next();
+                queue_limit.set_consumer_waiting(false);
+
+                let read_dir_result = read_dir_result?.value;
+                if let Ok(read_dir) = read_dir_result.as_ref() {
+                    queue_limit.remove(read_dir.results_list.len());
+                }
+                Some(read_dir_result)
+            }
+
+            ReadDirIter::BackgroundWalk {
+                read_dir_result_receiver,
+                queue_limit,
+            } => {
+                queue_limit.set_consumer_waiting(true);
+                let read_dir_result = read_dir_result_receiver.recv().ok();
+                queue_limit.set_consumer_waiting(false);
+
+                let read_dir_result = read_dir_result?;
+                if let Ok(read_dir) = read_dir_result.as_ref() {
+                    queue_limit.remove(read_dir.results_list.len());
+                }
+                Some(read_dir_result)
+            }
+        }
+    }
+}
+
+impl<C: ClientState> Drop for ReadDirIter<C> {
+    fn drop(&mut self) {
+        match self {
+            ReadDirIter::ParWalk { queue_limit, .. }
+            | ReadDirIter::BackgroundWalk { queue_limit, .. } => queue_limit.close(),
+            ReadDirIter::Walk { .. } => {}
         }
     }
 }
@@ -120,9 +208,13 @@ fn multi_threaded_walk_dir<C: ClientState>(
         ..
     } = ordered_read_dir_spec;
 
-    let read_dir_result = (run_context.core_read_dir_callback)(read_dir_spec);
+    run_context.queue_limit.wait();
+    let mut read_dir_result = (run_context.core_read_dir_callback)(read_dir_spec);
+    if let Ok(read_dir) = read_dir_result.as_ref() {
+        run_context.queue_limit.add(read_dir.results_list.len());
+    }
     let ordered_read_children_specs = read_dir_result
-        .as_ref()
+        .as_mut()
         .ok()
         .map(|read_dir| read_dir.ordered_read_children_specs(&index_path));
     let ordered_read_dir_result = Ordered::new(
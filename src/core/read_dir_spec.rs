@@ -1,6 +1,9 @@
 use std::path::Path;
 use std::sync::Arc;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::OpenAt;
+use super::{Ancestor, Gitignore};
 use crate::ClientState;
 
 /// Specification for reading a directory.
@@ -22,6 +25,13 @@ pub struct ReadDirSpec<C: ClientState> {
     /// when reading this directory's parent. One intended use case is to store
     /// `.gitignore` state to filter entries during the walk.
     pub client_read_state: C::ReadDirState,
-    // Origins of synlinks followed to get to this entry.
-    pub(crate) follow_link_ancestors: Arc<Vec<Arc<Path>>>,
+    // Directories read on the way to this directory when following links.
+    pub(crate) follow_link_ancestors: Arc<Vec<Ancestor>>,
+    // Ignore rules inherited from parent directories.
+    pub(crate) gitignore: Option<Arc<Gitignore>>,
+    // Index of the root this directory descends from.
+    pub(crate) root_index: usize,
+    // Set when walking from a directory file descriptor.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) open_at: Option<OpenAt>,
 }
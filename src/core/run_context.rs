@@ -1,13 +1,16 @@
 use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::sync::Arc;
 
-use super::{ClientState, Ordered, OrderedQueue, ReadDir, ReadDirCallback, ReadDirSpec};
+use super::{
+    ClientState, Ordered, OrderedQueue, QueueLimit, ReadDir, ReadDirCallback, ReadDirSpec,
+};
 use crate::Result;
 
 pub(crate) struct RunContext<C: ClientState> {
     pub(crate) stop: Arc<AtomicBool>,
     pub(crate) read_dir_spec_queue: OrderedQueue<ReadDirSpec<C>>,
     pub(crate) read_dir_result_queue: OrderedQueue<Result<ReadDir<C>>>,
+    pub(crate) queue_limit: Arc<QueueLimit>,
     pub(crate) core_read_dir_callback: Arc<ReadDirCallback<C>>,
 }
 
@@ -38,6 +41,7 @@ impl<C: ClientState> Clone for RunContext<C> {
             stop: self.stop.clone(),
             read_dir_spec_queue: self.read_dir_spec_queue.clone(),
             read_dir_result_queue: self.read_dir_result_queue.clone(),
+            queue_limit: self.queue_limit.clone(),
             core_read_dir_callback: self.core_read_dir_callback.clone(),
         }
     }
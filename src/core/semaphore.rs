@@ -0,0 +1,38 @@
+use std::sync::{Condvar, Mutex};
+
+/// Counting semaphore limiting how many directories are read at once.
+pub(crate) struct Semaphore {
+    permits: Mutex<usize>,
+    condvar: Condvar,
+}
+
+/// Permit returned by `Semaphore::acquire`, released when dropped.
+pub(crate) struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub(crate) fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            permits: Mutex::new(permits),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Wait until a permit is available and take it.
+    pub(crate) fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.condvar.wait(permits).unwrap();
+        }
+        *permits -= 1;
+        SemaphorePermit { semaphore: self }
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock().unwrap() += 1;
+        self.semaphore.condvar.notify_one();
+    }
+}
@@ -0,0 +1,111 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::*;
+use crate::Result;
+
+/// `std::fs::DirEntry` iterator from `WalkDir.into_std_iter()`.
+///
+/// Yields the entries of `fs::read_dir` as they are listed, without
+/// converting them to [`DirEntry`](struct.DirEntry.html).
+pub struct StdDirEntryIter {
+    min_depth: usize,
+    max_depth: usize,
+    skip_hidden: bool,
+    // roots left to walk, next on top
+    roots: Vec<PathBuf>,
+    // directories being listed, innermost on top. Opened when they reach the
+    // top, so only the directories above the next entry are open.
+    read_dir_stack: Vec<(usize, PathBuf, Option<fs::ReadDir>)>,
+}
+
+impl StdDirEntryIter {
+    pub(crate) fn new(
+        roots: Vec<PathBuf>,
+        min_depth: usize,
+        max_depth: usize,
+        skip_hidden: bool,
+    ) -> StdDirEntryIter {
+        StdDirEntryIter {
+            min_depth,
+            max_depth,
+            skip_hidden,
+            roots: roots.into_iter().rev().collect(),
+            read_dir_stack: Vec::new(),
+        }
+    }
+}
+
+impl Iterator for StdDirEntryIter {
+    type Item = Result<fs::DirEntry>;
+
+    fn next(&mut self) -> Option<Result<fs::DirEntry>> {
+        loop {
+            if self.read_dir_stack.is_empty() {
+                let root = self.roots.pop()?;
+                match fs::metadata(&root) {
+                    Ok(metadata) if metadata.is_dir() && self.max_depth > 0 => {
+                        self.read_dir_stack.push((1, root, None));
+                    }
+                    Ok(_) => {}
+                    Err(err) => return Some(Err(Error::from_path(0, root, err))),
+                }
+                continue;
+            }
+
+            let (depth, path, read_dir) = self.read_dir_stack.last_mut().unwrap();
+            let depth = *depth;
+            let read_dir = match read_dir {
+                Some(read_dir) => read_dir,
+                None => match fs::read_dir(&*path) {
+                    Ok(opened) => read_dir.insert(opened),
+                    Err(err) => {
+                        let path = self.read_dir_stack.pop().unwrap().1;
+                        return Some(Err(Error::from_path(depth - 1, path, err)));
+                    }
+                },
+            };
+            let fs_dir_entry = match read_dir.next() {
+                Some(Ok(fs_dir_entry)) => fs_dir_entry,
+                Some(Err(err)) => return Some(Err(Error::from_path(depth, path.clone(), err))),
+                None => {
+                    self.read_dir_stack.pop();
+                    continue;
+                }
+            };
+            if self.skip_hidden && is_hidden(&fs_dir_entry) {
+                continue;
+            }
+            let file_type = match fs_dir_entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(err) => return Some(Err(Error::from_path(depth, fs_dir_entry.path(), err))),
+            };
+            if file_type.is_dir() && depth < self.max_depth {
+                self.read_dir_stack.push((depth + 1, fs_dir_entry.path(), None));
+            }
+            if depth >= self.min_depth {
+                return Some(Ok(fs_dir_entry));
+            }
+        }
+    }
+}
+
+fn is_hidden(fs_dir_entry: &fs::DirEntry) -> bool {
+    fs_dir_entry.file_name().as_encoded_bytes().starts_with(b".")
+        || has_hidden_attribute(fs_dir_entry)
+}
+
+#[cfg(windows)]
+fn has_hidden_attribute(fs_dir_entry: &fs::DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    // Metadata comes with the directory listing on Windows
+    fs_dir_entry
+        .metadata()
+        .is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(not(windows))]
+fn has_hidden_attribute(_fs_dir_entry: &fs::DirEntry) -> bool {
+    false
+}
@@ -0,0 +1,68 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+type States = HashMap<usize, (Weak<()>, Box<dyn Any>)>;
+
+thread_local! {
+    // States of every `ThreadState` used on this thread, keyed by id. The weak
+    // reference tells whether the owning `ThreadState` still exists.
+    static STATES: RefCell<States> = RefCell::new(HashMap::new());
+}
+
+/// One lazily created state of type `S` for each thread that uses it.
+///
+/// A thread only ever sees its own state, so using it takes no lock. States
+/// left on threads after the `ThreadState` is dropped are freed the next time
+/// that thread creates a state.
+pub(crate) struct ThreadState<S> {
+    id: usize,
+    alive: Arc<()>,
+    init: Box<dyn Fn() -> S + Send + Sync>,
+}
+
+impl<S: 'static> ThreadState<S> {
+    pub(crate) fn new<I>(init: I) -> ThreadState<S>
+    where
+        I: Fn() -> S + Send + Sync + 'static,
+    {
+        ThreadState {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            alive: Arc::new(()),
+            init: Box::new(init),
+        }
+    }
+
+    /// Call `f` with this thread's state, creating it first if needed.
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+        // The state is taken out of the map while `f` runs, so a nested walk
+        // on this thread can use the map too.
+        let taken = STATES.with(|states| states.borrow_mut().remove(&self.id));
+        let mut state = match taken {
+            Some((_, state)) => state,
+            None => {
+                // Dropped after the map is released, in case dropping a state
+                // uses the map.
+                let _stale: Vec<_> = STATES.with(|states| {
+                    let mut states = states.borrow_mut();
+                    let stale_ids: Vec<usize> = states
+                        .iter()
+                        .filter(|(_, (alive, _))| alive.strong_count() == 0)
+                        .map(|(id, _)| *id)
+                        .collect();
+                    stale_ids.iter().filter_map(|id| states.remove(id)).collect()
+                });
+                Box::new((self.init)())
+            }
+        };
+        let result = f(state.downcast_mut().unwrap());
+        STATES.with(|states| {
+            states.borrow_mut().insert(self.id, (Arc::downgrade(&self.alive), state))
+        });
+        result
+    }
+}
@@ -0,0 +1,37 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Entries that a walk has not yet yielded, used to resume it later.
+///
+/// Returned by [`DirEntryIter::state`](struct.DirEntryIter.html#method.state)
+/// and resumed with
+/// [`WalkDirGeneric::resume`](struct.WalkDirGeneric.html#method.resume).
+/// Serializable with the `serde` feature, so a walk can be continued by
+/// another process.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WalkState {
+    // Pending entries in the order they are yielded. Their contents haven't
+    // been yielded either.
+    pub(crate) entries: Vec<PendingEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct PendingEntry {
+    pub(crate) path: PathBuf,
+    pub(crate) depth: usize,
+    pub(crate) root_index: usize,
+    // Whether the entry itself is yielded, it may only be walked through
+    pub(crate) yield_entry: bool,
+    // Whether the entry's contents are read, the walk may have pruned them
+    pub(crate) read_children: bool,
+}
+
+impl WalkState {
+    /// Returns `true` if no entries are pending, so the walk is finished.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
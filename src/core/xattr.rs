@@ -0,0 +1,72 @@
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+
+use rustix::fs::{getxattr, lgetxattr, listxattr, llistxattr};
+use rustix::io::Errno;
+
+// Returned for an attribute that doesn't exist.
+#[cfg(target_vendor = "apple")]
+const NO_ATTR: Errno = Errno::NOATTR;
+#[cfg(not(target_vendor = "apple"))]
+const NO_ATTR: Errno = Errno::NODATA;
+
+/// Read the names and values of all extended attributes of `path`. Symbolic
+/// links are only followed if `follow_link` is set. A file system without
+/// extended attributes has none, and attributes removed while reading are
+/// skipped.
+pub(crate) fn read_xattrs(path: &Path, follow_link: bool) -> io::Result<Vec<(OsString, Vec<u8>)>> {
+    let names = match read_to_vec(|buf| {
+        if follow_link {
+            listxattr(path, buf)
+        } else {
+            llistxattr(path, buf)
+        }
+    }) {
+        Ok(names) => names,
+        Err(Errno::NOTSUP) => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut xattrs = Vec::new();
+    // Names are nul terminated
+    for name in names
+        .split(|byte| *byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let value = read_to_vec(|buf| {
+            if follow_link {
+                getxattr(path, name, buf)
+            } else {
+                lgetxattr(path, name, buf)
+            }
+        });
+        match value {
+            Ok(value) => xattrs.push((OsString::from_vec(name.to_vec()), value)),
+            Err(NO_ATTR) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(xattrs)
+}
+
+// Call `read` with an empty buffer to get the required size, then again with
+// a buffer of that size.
+fn read_to_vec<F>(read: F) -> rustix::io::Result<Vec<u8>>
+where
+    F: Fn(&mut [u8]) -> rustix::io::Result<usize>,
+{
+    loop {
+        let mut buf = vec![0; read(&mut [])?];
+        match read(&mut buf) {
+            Ok(len) => {
+                buf.truncate(len);
+                return Ok(buf);
+            }
+            // The list or value grew between the two calls
+            Err(Errno::RANGE) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -80,6 +80,19 @@
 //! # Ok(())
 //! # }
 //! ```
+//! # Ordering
+//!
+//! The iterator from `into_iter` always yields entries in depth first order,
+//! as if the walk were done on a single thread, regardless of
+//! [`Parallelism`](enum.Parallelism.html). Without sorting, the entries of
+//! each directory are yielded in the order `fs::read_dir` returned them, so
+//! walks of an unchanged tree are reproducible as long as the filesystem lists
+//! directories in a stable order. No total order is guaranteed across
+//! filesystems or platforms, use [`sort`](struct.WalkDirGeneric.html#method.sort)
+//! for that. Only
+//! [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter) yields
+//! entries in no particular order.
+//!
 //! # Inspiration
 //!
 //! This crate is inspired by both [`walkdir`](https://crates.io/crates/walkdir)
@@ -114,22 +127,46 @@
 
 mod core;
 
+use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::default::Default;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt::Debug;
 use std::fs;
+use std::io;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::fd::OwnedFd;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::core::{ReadDir, ReadDirSpec};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::core::{DirNames, OpenAt};
+use crate::core::{
+    Ancestor, DeviceId, EntryLender, Gitignore, Glob, LendOptions, ReadDir, ReadDirCallback,
+    Semaphore, ThreadState,
+};
 
-pub use crate::core::{DirEntry, DirEntryIter, Error};
+#[cfg(feature = "serde")]
+pub use crate::core::DirEntrySnapshot;
+#[cfg(feature = "async")]
+pub use crate::core::DirEntryStream;
+pub use crate::core::{
+    DirEntry, DirEntryIter, DirIter, DirReader, Error, FsDirReader, ParDirEntryIter, ReadDirSpec,
+    StdDirEntryIter, WalkState,
+};
 
 /// Builder for walking a directory.
 pub type WalkDir = WalkDirGeneric<((), ())>;
 
+/// Options for walking directories, see
+/// [`WalkOptionsGeneric`](struct.WalkOptionsGeneric.html).
+pub type WalkOptions = WalkOptionsGeneric<((), ())>;
+
 /// A specialized Result type for WalkDir.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -156,7 +193,40 @@ pub trait ClientState: Send + Default + Debug + 'static {
 /// Use [`WalkDir`](type.WalkDir.html) if you don't need to store client state
 /// into yeilded DirEntries.
 pub struct WalkDirGeneric<C: ClientState> {
-    root: PathBuf,
+    roots: Vec<PathBuf>,
+    // Depth of each root, empty unless resuming a walk
+    root_depths: Vec<usize>,
+    // Root index of each root, empty unless resuming a walk
+    root_indices: Vec<usize>,
+    // Whether each root is yielded and whether its contents are read, empty
+    // unless resuming a walk
+    root_actions: Vec<(bool, bool)>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    root_dir: Option<Arc<OwnedFd>>,
+    options: WalkDirOptions<C>,
+}
+
+/// Options of a [`WalkDirGeneric`](struct.WalkDirGeneric.html) without its
+/// roots, to configure once and start many walks from.
+///
+/// Options are set with the `WalkDirGeneric` builder methods through
+/// [`configure`](#method.configure), or taken from an existing builder with
+/// [`WalkDirGeneric::options`](struct.WalkDirGeneric.html#method.options).
+/// Callbacks are shared between the walks started from the same options,
+/// and so is the flag given to
+/// [`cancellation`](struct.WalkDirGeneric.html#method.cancellation). Options
+/// are `Send` and `Sync` when the client's `ReadDirState` is, so they can be
+/// shared across threads.
+///
+/// ```
+/// use jwalk::{walk_dir, WalkOptions};
+///
+/// let options = WalkOptions::new().configure(|walk_dir| walk_dir.sort(true).max_depth(1));
+/// for entry in walk_dir(&options, ".") {
+///     println!("{}", entry.unwrap().path().display());
+/// }
+/// ```
+pub struct WalkOptionsGeneric<C: ClientState> {
     options: WalkDirOptions<C>,
 }
 
@@ -165,6 +235,24 @@ type ProcessReadDirFunction<C> = dyn Fn(Option<usize>, &Path, &mut <C as ClientS
     + Sync
     + 'static;
 
+type SortByFunction<C> = dyn Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering + Send + Sync + 'static;
+
+// Sorts the entries of a directory by a key, reversed when the flag is set.
+type SortByKeyFunction<C> = dyn Fn(bool, &mut Vec<Result<DirEntry<C>>>) + Send + Sync + 'static;
+
+type FilterEntryFunction<C> = dyn Fn(&DirEntry<C>) -> bool + Send + Sync + 'static;
+
+type FilterMapEntryFunction<C> = dyn Fn(&DirEntry<C>) -> Action + Send + Sync + 'static;
+
+type PruneDirsFunction = dyn Fn(&OsStr) -> bool + Send + Sync + 'static;
+
+type OnReadDirFunction = dyn Fn(&Path, usize) + Send + Sync + 'static;
+
+type HashFunction = dyn Fn(&Path) -> io::Result<Box<[u8]>> + Send + Sync + 'static;
+
+type ClientStateFunction<C> =
+    dyn Fn(&DirEntry<C>) -> <C as ClientState>::DirEntryState + Send + Sync + 'static;
+
 /// Degree of parallelism to use when performing walk.
 ///
 /// Parallelism happens at the directory level. It will help when walking deep
@@ -184,15 +272,193 @@ pub enum Parallelism {
     RayonNewPool(usize),
 }
 
+/// Order in which the entries of each directory are yielded.
+///
+/// Errors are always yielded after all successfully read entries of a
+/// directory. Use [`sort_reverse`](struct.WalkDirGeneric.html#method.sort_reverse)
+/// to reverse the order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Sort {
+    /// Sort by `file_name`
+    Name,
+    /// Sort by `file_name`, comparing runs of digits numerically so that
+    /// `img2` is yielded before `img10`. Names that aren't valid UTF-8 are
+    /// compared as with [`Sort::Name`](enum.Sort.html#variant.Name).
+    NameNatural,
+    /// Sort by lowercased `file_name` so that `Apple`, `banana` and `Cherry`
+    /// are interleaved. Names that differ only in case are compared as with
+    /// [`Sort::Name`](enum.Sort.html#variant.Name).
+    NameCaseInsensitive,
+    /// Sort by `metadata().len()`, smallest first. Entries of the same size
+    /// are sorted by `file_name`. Entries whose metadata can't be read are
+    /// yielded last, sorted by `file_name`, even when the order is reversed.
+    Size,
+    /// Sort by inode number, taken from the directory listing without reading
+    /// metadata. Reading entries in inode order tends to access the disk
+    /// sequentially, which speeds up walks with a cold cache.
+    #[cfg(unix)]
+    Inode,
+    /// Yield directories before other entries, each group sorted by
+    /// `file_name`. Uses `file_type`, so symlinks to directories are only
+    /// grouped with directories when
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is set.
+    DirsFirst,
+    /// Like [`Sort::DirsFirst`](enum.Sort.html#variant.DirsFirst), with
+    /// directories yielded after other entries.
+    FilesFirst,
+}
+
+/// Unicode normalization form that file names are converted to before they
+/// are matched against patterns. See
+/// [`normalize_names`](struct.WalkDirGeneric.html#method.normalize_names).
+#[cfg(feature = "unicode")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NormalizationForm {
+    /// Canonical composition, where `é` is a single code point. Used by most
+    /// text input.
+    Nfc,
+    /// Canonical decomposition, where `é` is `e` followed by a combining
+    /// accent. Used for file names by HFS+ and often on APFS.
+    Nfd,
+}
+
+/// Order in which directories are scheduled to be read by parallel walks. See
+/// [`traversal_order`](struct.WalkDirGeneric.html#method.traversal_order).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TraversalOrder {
+    /// Read the first directory in depth first order that is waiting to be
+    /// read, finishing subtrees before starting their siblings
+    DepthFirst,
+    /// Read the shallowest directory that is waiting to be read
+    BreadthFirst,
+}
+
+/// What to do with an entry, returned by the
+/// [`filter_map_entry`](struct.WalkDirGeneric.html#method.filter_map_entry)
+/// callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Action {
+    /// Yield the entry and descend into it
+    Keep,
+    /// Descend into the entry without yielding it
+    Skip,
+    /// Neither yield the entry nor descend into it
+    SkipSubtree,
+    /// Yield the entry without descending into it
+    SkipContents,
+}
+
+/// Kinds of entries yielded by the iterator. See
+/// [`file_type_filter`](struct.WalkDirGeneric.html#method.file_type_filter).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FileTypeFilter {
+    /// Yield all entries
+    All,
+    /// Yield only entries where `file_type().is_file()`
+    Files,
+    /// Yield only entries where `file_type().is_dir()`
+    Dirs,
+}
+
+/// How errors are handled by the iterator. See
+/// [`on_error`](struct.WalkDirGeneric.html#method.on_error).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorHandling {
+    /// Yield errors as `Err` results
+    Yield,
+    /// Drop errors, directories that can't be read are yielded without
+    /// contents and without `read_children_error`
+    Skip,
+    /// Yield the first error and then end the walk
+    Abort,
+}
+
+/// Totals collected by [`WalkDirGeneric::stats`](struct.WalkDirGeneric.html#method.stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WalkStats {
+    /// Sum of `metadata().len()` of all files.
+    pub total_bytes: u64,
+    /// Number of files.
+    pub files: usize,
+    /// Number of directories, including the root.
+    pub dirs: usize,
+    /// Number of symbolic links. Followed links are also counted as the file
+    /// or directory they point to.
+    pub symlinks: usize,
+    /// Number of errors, including directories that couldn't be read and
+    /// files whose metadata couldn't be read.
+    pub errors: usize,
+}
+
 struct WalkDirOptions<C: ClientState> {
-    sort: bool,
+    sort: Option<Sort>,
+    sort_by: Option<Arc<SortByFunction<C>>>,
+    sort_by_key: Option<Arc<SortByKeyFunction<C>>>,
+    sort_reverse: bool,
+    sort_errors_last: bool,
+    include_root: bool,
     min_depth: usize,
     max_depth: usize,
+    max_recursion_depth: usize,
+    first_n: usize,
+    max_entries_per_dir: usize,
     skip_hidden: bool,
+    hidden_predicate: Option<Arc<FilterEntryFunction<C>>>,
+    #[cfg(unix)]
+    skip_special_files: bool,
     follow_links: bool,
+    follow_root_links: bool,
+    absolute: bool,
+    contents_first: bool,
+    skip_empty_dirs: bool,
+    same_file_system: bool,
+    respect_gitignore: bool,
+    #[cfg(jwalk_xattr)]
+    read_xattrs: bool,
+    preload_metadata: bool,
+    #[cfg(windows)]
+    windows_long_paths: bool,
     parallelism: Parallelism,
+    max_queued_entries: usize,
+    max_concurrent_reads: usize,
+    read_dir_batch_size: usize,
+    par_entries_threshold: usize,
+    traversal_order: TraversalOrder,
+    unordered_first: bool,
+    background: bool,
+    detect_changes: bool,
+    cancellation: Option<Arc<AtomicBool>>,
+    time_budget: Option<Duration>,
     root_read_dir_state: C::ReadDirState,
+    skip_dirs: Option<Arc<HashSet<OsString>>>,
+    prune_dirs: Option<Arc<PruneDirsFunction>>,
+    extensions: Option<Arc<Vec<String>>>,
+    modified_after: Option<SystemTime>,
+    modified_before: Option<SystemTime>,
+    min_file_size: Option<u64>,
+    max_file_size: Option<u64>,
+    glob_include: Option<Arc<Vec<String>>>,
+    glob_exclude: Option<Arc<Vec<String>>>,
+    #[cfg(feature = "regex")]
+    path_regex_exclude: Option<regex::Regex>,
+    #[cfg(feature = "unicode")]
+    normalize_names: Option<NormalizationForm>,
+    filter_entry: Option<Arc<FilterEntryFunction<C>>>,
+    filter_map_entry: Option<Arc<FilterMapEntryFunction<C>>>,
+    file_type_filter: FileTypeFilter,
+    on_error: ErrorHandling,
     process_read_dir: Option<Arc<ProcessReadDirFunction<C>>>,
+    on_read_dir: Option<Arc<OnReadDirFunction>>,
+    hash_with: Option<Arc<HashFunction>>,
+    client_state_with: Option<Arc<ClientStateFunction<C>>>,
+    dir_reader: Option<Arc<dyn DirReader<C>>>,
 }
 
 impl<C: ClientState> WalkDirGeneric<C> {
@@ -201,40 +467,277 @@ impl<C: ClientState> WalkDirGeneric<C> {
     /// the iterator. If root is a file, then it is the first and only item
     /// yielded by the iterator.
     pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        WalkDirGeneric::from_roots(std::iter::once(root))
+    }
+
+    /// Create a builder for a recursive walk of the already open directory
+    /// `dir`.
+    ///
+    /// Directories are opened relative to the file descriptor of their
+    /// parent with `openat`, so a directory that is replaced by a symbolic
+    /// link during the walk can't redirect it elsewhere. File types are read
+    /// the same way while listing each directory, and so is metadata with
+    /// [`preload_metadata`](struct.WalkDirGeneric.html#method.preload_metadata),
+    /// while [`metadata`](struct.DirEntry.html#method.metadata) is read by
+    /// path. Symbolic links are never followed.
+    ///
+    /// `path` is used to name the yielded entries. Options that read files
+    /// by path, such as
+    /// [`respect_gitignore`](struct.WalkDirGeneric.html#method.respect_gitignore)
+    /// and [`same_file_system`](struct.WalkDirGeneric.html#method.same_file_system),
+    /// still use it.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn from_dir<P: AsRef<Path>>(dir: fs::File, path: P) -> Self {
+        let mut walk_dir = WalkDirGeneric::new(path);
+        walk_dir.root_dir = Some(Arc::new(OwnedFd::from(dir)));
+        walk_dir
+    }
+
+    /// Create a builder that continues a walk from the
+    /// [`state`](struct.DirEntryIter.html#method.state) of its iterator.
+    ///
+    /// Set the same options as the original walk, and the remaining entries
+    /// are yielded in the same order with the same depths. Pending
+    /// directories whose contents the original walk skipped, for example with
+    /// [`skip_dirs`](struct.WalkDirGeneric.html#method.skip_dirs) or
+    /// `Action::SkipContents`, stay skipped. Pending entries are walked as
+    /// roots, so
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// is called for them with the root read dir state, ignore rules of
+    /// directories above them no longer apply and link loop detection starts
+    /// over.
+    pub fn resume(state: WalkState) -> Self {
+        let mut walk_dir = WalkDirGeneric::from_roots(std::iter::empty::<PathBuf>());
+        for entry in state.entries {
+            walk_dir.roots.push(entry.path);
+            walk_dir.root_depths.push(entry.depth);
+            walk_dir.root_indices.push(entry.root_index);
+            walk_dir.root_actions.push((entry.yield_entry, entry.read_children));
+        }
+        walk_dir
+    }
+
+    /// Create a builder for a single recursive walk over several roots.
+    ///
+    /// Redundant separators and `.` components are removed from each root, so
+    /// `foo/` and `foo` yield the same paths.
+    ///
+    /// Roots are yielded in the given order, each followed by its contents,
+    /// as if walking each root in turn. Depths are relative to each root, so
+    /// every root has depth `0`. The walk is done in one pass so directories
+    /// from all roots are read in parallel.
+    pub fn from_roots<I, P>(roots: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
         WalkDirGeneric {
-            root: root.as_ref().to_path_buf(),
+            roots: roots.into_iter().map(|root| normalize_root(root.as_ref())).collect(),
+            root_depths: Vec::new(),
+            root_indices: Vec::new(),
+            root_actions: Vec::new(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            root_dir: None,
             options: WalkDirOptions {
-                sort: false,
+                sort: None,
+                sort_by: None,
+                sort_by_key: None,
+                sort_reverse: false,
+                sort_errors_last: true,
+                include_root: true,
                 min_depth: 0,
                 max_depth: ::std::usize::MAX,
+                max_recursion_depth: usize::MAX,
+                first_n: usize::MAX,
+                max_entries_per_dir: usize::MAX,
                 skip_hidden: true,
+                hidden_predicate: None,
+                #[cfg(unix)]
+                skip_special_files: false,
                 follow_links: false,
+                follow_root_links: true,
+                absolute: false,
+                contents_first: false,
+                skip_empty_dirs: false,
+                same_file_system: false,
+                respect_gitignore: false,
+                #[cfg(jwalk_xattr)]
+                read_xattrs: false,
+                preload_metadata: false,
+                #[cfg(windows)]
+                windows_long_paths: false,
                 parallelism: Parallelism::RayonDefaultPool,
+                max_queued_entries: usize::MAX,
+                max_concurrent_reads: usize::MAX,
+                read_dir_batch_size: usize::MAX,
+                par_entries_threshold: usize::MAX,
+                traversal_order: TraversalOrder::DepthFirst,
+                unordered_first: false,
+                background: false,
+                detect_changes: false,
+                cancellation: None,
+                time_budget: None,
                 root_read_dir_state: C::ReadDirState::default(),
+                skip_dirs: None,
+                prune_dirs: None,
+                extensions: None,
+                modified_after: None,
+                modified_before: None,
+                min_file_size: None,
+                max_file_size: None,
+                glob_include: None,
+                glob_exclude: None,
+                #[cfg(feature = "regex")]
+                path_regex_exclude: None,
+                #[cfg(feature = "unicode")]
+                normalize_names: None,
+                filter_entry: None,
+                filter_map_entry: None,
+                file_type_filter: FileTypeFilter::All,
+                on_error: ErrorHandling::Yield,
                 process_read_dir: None,
+                on_read_dir: None,
+                hash_with: None,
+                client_state_with: None,
+                dir_reader: None,
             },
         }
     }
 
-    /// Root path of the walk.
+    /// Root path of the walk. When walking several roots this is the first
+    /// root, or an empty path if there are none.
     pub fn root(&self) -> &Path {
-        &self.root
+        self.roots.first().map(PathBuf::as_path).unwrap_or_else(|| Path::new(""))
+    }
+
+    /// Root paths of the walk.
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Copy of this builder's options without its roots, see
+    /// [`WalkOptionsGeneric`](struct.WalkOptionsGeneric.html).
+    pub fn options(&self) -> WalkOptionsGeneric<C> {
+        WalkOptionsGeneric { options: self.options.clone() }
     }
 
     /// Sort entries by `file_name` per directory. Defaults to `false`. Use
     /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir) for custom
     /// sorting or filtering.
+    ///
+    /// Unsorted entries are yielded in the order returned by `fs::read_dir`,
+    /// see [Ordering](index.html#ordering).
     pub fn sort(mut self, sort: bool) -> Self {
-        self.options.sort = sort;
+        self.options.sort = if sort { Some(Sort::Name) } else { None };
+        self
+    }
+
+    /// Sort entries per directory using the given [`Sort`](enum.Sort.html)
+    /// order.
+    ///
+    /// [`Sort::Size`](enum.Sort.html#variant.Size) reads the metadata of each
+    /// entry, so it is slower than sorting by name.
+    pub fn sort_order(mut self, sort: Sort) -> Self {
+        self.options.sort = Some(sort);
+        self
+    }
+
+    /// Sort entries per directory with a custom comparator, as with
+    /// `Vec::sort_by`. Takes precedence over
+    /// [`sort`](struct.WalkDirGeneric.html#method.sort) and
+    /// [`sort_order`](struct.WalkDirGeneric.html#method.sort_order) when set.
+    pub fn sort_by<F>(mut self, compare: F) -> Self
+    where
+        F: Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering + Send + Sync + 'static,
+    {
+        self.options.sort_by = Some(Arc::new(compare));
+        self.options.sort_by_key = None;
+        self
+    }
+
+    /// Sort entries per directory by the key returned by `f`, as with
+    /// `slice::sort_by_cached_key`. The key is computed once per entry, so
+    /// `f` can be expensive, for example parsing a number out of the name.
+    /// Entries with equal keys are sorted by `file_name`.
+    ///
+    /// Takes precedence over [`sort`](struct.WalkDirGeneric.html#method.sort)
+    /// and [`sort_order`](struct.WalkDirGeneric.html#method.sort_order) when
+    /// set, and replaces [`sort_by`](struct.WalkDirGeneric.html#method.sort_by).
+    pub fn sort_by_key<K, F>(mut self, f: F) -> Self
+    where
+        K: Ord,
+        F: Fn(&DirEntry<C>) -> K + Send + Sync + 'static,
+    {
+        self.options.sort_by_key = Some(Arc::new(move |reverse, dir_entry_results| {
+            sort_dir_entry_results_by_key(dir_entry_results, reverse, |dir_entry| {
+                Some(f(dir_entry))
+            })
+        }));
+        self.options.sort_by = None;
+        self
+    }
+
+    /// Reverse the order of sorted entries. Defaults to `false`.
+    ///
+    /// Errors are still yielded after all successfully read entries of a
+    /// directory, see
+    /// [`sort_errors_last`](struct.WalkDirGeneric.html#method.sort_errors_last).
+    pub fn sort_reverse(mut self, sort_reverse: bool) -> Self {
+        self.options.sort_reverse = sort_reverse;
+        self
+    }
+
+    /// Yield errors after all successfully read entries of a directory when
+    /// sorting. Defaults to `true`.
+    ///
+    /// When `false` and sorting by name, errors for an entry of the directory
+    /// are sorted by the file name of their
+    /// [`path`](struct.Error.html#method.path) among the other entries.
+    /// Errors for the directory itself, and errors when sorting with
+    /// [`sort_by`](struct.WalkDirGeneric.html#method.sort_by),
+    /// [`sort_by_key`](struct.WalkDirGeneric.html#method.sort_by_key) or by
+    /// size or inode, are still yielded last.
+    pub fn sort_errors_last(mut self, sort_errors_last: bool) -> Self {
+        self.options.sort_errors_last = sort_errors_last;
         self
     }
 
     /// Skip hidden entries. Enabled by default.
+    ///
+    /// Entries whose name starts with `.` are hidden. On Windows entries with
+    /// the hidden file attribute are also hidden. Use
+    /// [`hidden_predicate`](struct.WalkDirGeneric.html#method.hidden_predicate)
+    /// to change which entries are hidden.
     pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
         self.options.skip_hidden = skip_hidden;
         self
     }
 
+    /// Decide which entries are hidden, replacing the default rule described
+    /// in [`skip_hidden`](struct.WalkDirGeneric.html#method.skip_hidden).
+    ///
+    /// Hidden entries are only skipped while `skip_hidden` is enabled. The
+    /// predicate is called for every entry below the root as it is read.
+    pub fn hidden_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&DirEntry<C>) -> bool + Send + Sync + 'static,
+    {
+        self.options.hidden_predicate = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Skip entries that aren't regular files, directories or symbolic links,
+    /// such as sockets, fifos and device files. By default, this is disabled.
+    ///
+    /// When following links, links are skipped if they point to such a file.
+    /// Skipped entries are never passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir).
+    #[cfg(unix)]
+    pub fn skip_special_files(mut self, skip_special_files: bool) -> Self {
+        self.options.skip_special_files = skip_special_files;
+        self
+    }
+
     /// Follow symbolic links. By default, this is disabled.
     ///
     /// When `yes` is `true`, symbolic links are followed as if they were normal
@@ -251,11 +754,174 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self
     }
 
+    /// Walk the target of a root that is a symbolic link to a directory. By
+    /// default, this is enabled.
+    ///
+    /// This applies even when
+    /// [`follow_links`](struct.WalkDirGeneric.html#method.follow_links) is
+    /// `false`, in which case the root is still yielded as a symbolic link
+    /// and links found deeper aren't followed. When `false` and `follow_links`
+    /// is also `false`, a symbolic link root is yielded and not walked.
+    pub fn follow_root_links(mut self, follow_root_links: bool) -> Self {
+        self.options.follow_root_links = follow_root_links;
+        self
+    }
+
+    /// Canonicalize each root when the walk starts, so that the paths of all
+    /// entries are absolute. Defaults to `false`.
+    ///
+    /// Each root is canonicalized once with `fs::canonicalize` and entry
+    /// paths are joined onto it, which is much cheaper than canonicalizing
+    /// every entry. Symbolic links in the root path are resolved, so a root
+    /// that is a link is walked as its target. A root that can't be
+    /// canonicalized is yielded as an error.
+    pub fn absolute(mut self, absolute: bool) -> Self {
+        self.options.absolute = absolute;
+        self
+    }
+
+    /// Yield a directory's contents before the directory itself. By default,
+    /// this is disabled.
+    ///
+    /// When `contents_first` is `false` (as is the default), the directory is
+    /// yielded before its contents are read. This is useful when, e.g. you
+    /// want to skip processing of some directories.
+    ///
+    /// When `contents_first` is `true`, the iterator yields the contents of a
+    /// directory before yielding the directory itself. This is useful when,
+    /// e.g. you want to recursively delete a directory.
+    ///
+    /// Sorted order among siblings is preserved. Each directory entry is held
+    /// in memory until all of its descendents have been yielded, so the
+    /// iterator buffers one entry per level of the directory currently being
+    /// walked.
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.options.contents_first = contents_first;
+        self
+    }
+
+    /// Skip directories that have no yielded descendants, so that only
+    /// directories leading to files (or errors) are yielded. Defaults to
+    /// `false`.
+    ///
+    /// Emptiness is decided after filtering. Entries removed by
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// [`filter_entry`](struct.WalkDirGeneric.html#method.filter_entry) or the
+    /// other filters, and entries that aren't yielded because of
+    /// [`min_depth`](struct.WalkDirGeneric.html#method.min_depth) or
+    /// [`file_type_filter`](struct.WalkDirGeneric.html#method.file_type_filter),
+    /// don't make a directory non-empty. Directories whose contents aren't read,
+    /// for example because `read_children_path` was set to `None` or because of
+    /// [`max_depth`](struct.WalkDirGeneric.html#method.max_depth), are yielded
+    /// like files.
+    ///
+    /// A directory can only be yielded once one of its descendants has been
+    /// found, so each directory is held until then. As with
+    /// [`contents_first`](struct.WalkDirGeneric.html#method.contents_first)
+    /// this buffers at most one entry per level of the directory currently
+    /// being walked. Ignored by
+    /// [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter).
+    pub fn skip_empty_dirs(mut self, skip_empty_dirs: bool) -> Self {
+        self.options.skip_empty_dirs = skip_empty_dirs;
+        self
+    }
+
+    /// Do not cross file system boundaries. By default, this is disabled.
+    ///
+    /// When enabled, directories on a different file system than the root are
+    /// still yielded, but their contents are not read.
+    pub fn same_file_system(mut self, same_file_system: bool) -> Self {
+        self.options.same_file_system = same_file_system;
+        self
+    }
+
+    /// Skip entries matched by `.gitignore` files. By default, this is
+    /// disabled.
+    ///
+    /// When enabled, each directory's `.gitignore` is read before its entries
+    /// are yielded. Rules apply to the directory containing the `.gitignore`
+    /// and all of its descendents, with rules in deeper `.gitignore` files
+    /// taking precedence. Ignored directories are not descended into.
+    ///
+    /// Only `.gitignore` files at or below the root are read. Git's global
+    /// and `.git/info/exclude` rules are not applied.
+    pub fn respect_gitignore(mut self, respect_gitignore: bool) -> Self {
+        self.options.respect_gitignore = respect_gitignore;
+        self
+    }
+
+    /// Read the extended attributes of each entry, available from
+    /// [`DirEntry::xattrs`](struct.DirEntry.html#method.xattrs). Defaults to
+    /// `false`.
+    ///
+    /// Attributes are read by the threads reading directories, after entries
+    /// are filtered and before
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// is called, so the cost of the extra system calls is spread across the
+    /// walk. Errors reading them are returned by `DirEntry::xattrs`.
+    ///
+    /// Requires the `xattr` feature, and is only available on Linux, Android
+    /// and Apple platforms. The BSDs aren't supported, their `extattr`
+    /// functions organize attributes by namespace instead.
+    #[cfg(jwalk_xattr)]
+    pub fn read_xattrs(mut self, read_xattrs: bool) -> Self {
+        self.options.read_xattrs = read_xattrs;
+        self
+    }
+
+    /// Read the metadata of each entry as its directory is listed, so that
+    /// [`DirEntry::metadata`](struct.DirEntry.html#method.metadata) and the
+    /// accessors that use cached metadata, such as
+    /// [`DirEntry::file_size`](struct.DirEntry.html#method.file_size), don't
+    /// make a system call. Defaults to `false`.
+    ///
+    /// On Linux and Android the metadata is read with `statx` relative to
+    /// the open directory being listed, so the path isn't resolved again for
+    /// each entry. On Windows it comes with the listing, elsewhere it is read
+    /// by path. Either way it is read while the directory is open, within
+    /// [`max_concurrent_reads`](struct.WalkDirGeneric.html#method.max_concurrent_reads).
+    /// Entries removed by filters are read too, and the metadata filters
+    /// such as [`min_file_size`](struct.WalkDirGeneric.html#method.min_file_size)
+    /// use it. Errors aren't cached, `DirEntry::metadata` reports them.
+    /// Entries from a [`DirReader`](trait.DirReader.html) aren't preloaded.
+    pub fn preload_metadata(mut self, preload_metadata: bool) -> Self {
+        self.options.preload_metadata = preload_metadata;
+        self
+    }
+
+    /// Walk the roots using extended-length (`\\?\`) paths. Defaults to
+    /// `false`.
+    ///
+    /// Windows paths are normally limited to 260 characters, so deep trees
+    /// fail to read past that length. When `true` each root is made absolute
+    /// and given the `\\?\` prefix, which every yielded path then carries.
+    /// Roots that can't be made absolute are walked unchanged. Only available
+    /// on Windows.
+    #[cfg(windows)]
+    pub fn windows_long_paths(mut self, windows_long_paths: bool) -> Self {
+        self.options.windows_long_paths = windows_long_paths;
+        self
+    }
+
+    /// Yield the root entry. Defaults to `true`.
+    ///
+    /// When `false` the root's contents are still walked, and errors reading
+    /// the root are still yielded. Unlike
+    /// [`min_depth`](struct.WalkDirGeneric.html#method.min_depth) this
+    /// doesn't change which other entries are yielded.
+    pub fn include_root(mut self, include_root: bool) -> Self {
+        self.options.include_root = include_root;
+        self
+    }
+
     /// Set the minimum depth of entries yielded by the iterator.
     ///
     /// The smallest depth is `0` and always corresponds to the path given
     /// to the `new` function on this type. Its direct descendents have depth
     /// `1`, and their descendents have depth `2`, and so on.
+    ///
+    /// Entries shallower than `depth` are not yielded, but their contents are
+    /// still read so that deeper entries are reached.
     pub fn min_depth(mut self, depth: usize) -> Self {
         self.options.min_depth = depth;
         if self.options.min_depth > self.options.max_depth {
@@ -277,7 +943,9 @@ impl<C: ClientState> WalkDirGeneric<C> {
     ///
     /// Note that this will not simply filter the entries of the iterator, but
     /// it will actually avoid descending into directories when the depth is
-    /// exceeded.
+    /// exceeded. Directories at the maximum depth are yielded without
+    /// scheduling a read of their contents, so a shallow walk such as
+    /// `max_depth(1)` costs little more than `fs::read_dir`.
     pub fn max_depth(mut self, depth: usize) -> Self {
         self.options.max_depth = depth;
         if self.options.max_depth < self.options.min_depth {
@@ -289,6 +957,47 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self
     }
 
+    /// Yield an error instead of descending into directories at `depth`. By
+    /// default, there is no limit.
+    ///
+    /// Unlike [`max_depth`](struct.WalkDirGeneric.html#method.max_depth),
+    /// which silently stops the walk at a depth, this reports the directories
+    /// where the walk was cut short. It guards against runaway depth, for
+    /// example from symbolic links when following links. Each directory at
+    /// `depth` that would be read is yielded as an error for which
+    /// [`Error::is_too_deep`](struct.Error.html#method.is_too_deep) returns
+    /// `true`.
+    pub fn max_recursion_depth(mut self, depth: usize) -> Self {
+        self.options.max_recursion_depth = depth;
+        self
+    }
+
+    /// Yield at most `n` entries and then stop the walk. By default, there is
+    /// no limit.
+    ///
+    /// Unlike `.take(n)` on the iterator, which lets the walk keep reading
+    /// directories in the background until the iterator is dropped, no more
+    /// directories are read once the `n`th entry has been yielded. Together
+    /// with sorting this gives the first `n` entries of the walk in order.
+    /// This is ignored by
+    /// [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter).
+    pub fn first_n(mut self, n: usize) -> Self {
+        self.options.first_n = n;
+        self
+    }
+
+    /// Keep at most `n` entries of each directory below the root. By
+    /// default, there is no limit.
+    ///
+    /// Each directory is still read in full and its entries are filtered and
+    /// sorted before the rest are dropped, so with sorting the first `n`
+    /// entries in order are kept. Kept subdirectories are walked as usual.
+    /// Errors don't count toward the limit and are always kept.
+    pub fn max_entries_per_dir(mut self, n: usize) -> Self {
+        self.options.max_entries_per_dir = n;
+        self
+    }
+
     /// Degree of parallelism to use when performing walk. Defaults to
     /// [`Parallelism::RayonDefaultPool`](enum.Parallelism.html#variant.RayonDefaultPool).
     pub fn parallelism(mut self, parallelism: Parallelism) -> Self {
@@ -296,6 +1005,214 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self
     }
 
+    /// Run the walk in a rayon thread pool you already own. Shorthand for
+    /// [`parallelism(Parallelism::RayonExistingPool(thread_pool))`](enum.Parallelism.html#variant.RayonExistingPool).
+    ///
+    /// Reusing a pool avoids building a new one for every walk, as
+    /// [`Parallelism::RayonNewPool`](enum.Parallelism.html#variant.RayonNewPool)
+    /// does, and lets you control thread naming and lifetime. Replaces any
+    /// previously set parallelism.
+    pub fn thread_pool(self, thread_pool: Arc<ThreadPool>) -> Self {
+        self.parallelism(Parallelism::RayonExistingPool(thread_pool))
+    }
+
+    /// Limit how many entries are read ahead of the iterator. By default,
+    /// there is no limit.
+    ///
+    /// With parallel walks, rayon threads read directories ahead of the
+    /// iterator and queue their entries until they are yielded. When this
+    /// limit is reached the threads wait for the iterator to catch up, which
+    /// bounds memory use when entries are consumed slowly.
+    ///
+    /// The limit is approximate. Threads keep reading while the iterator is
+    /// waiting for a directory that hasn't been read yet, so the queue can
+    /// grow past `n` when directories finish out of order. This has no effect
+    /// on [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) walks
+    /// or on [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter).
+    ///
+    /// Waiting threads stay blocked in the pool. If the code consuming the
+    /// iterator runs its own work in the same rayon pool, that work can wait
+    /// on threads that are waiting for it, so walk in a separate pool with
+    /// [`Parallelism::RayonNewPool`](enum.Parallelism.html#variant.RayonNewPool).
+    pub fn max_queued_entries(mut self, n: usize) -> Self {
+        self.options.max_queued_entries = n;
+        self
+    }
+
+    /// Limit how many directories are open for reading at once, independent
+    /// of the number of threads. By default, there is no limit.
+    ///
+    /// Each directory read holds a file descriptor until all of its entries
+    /// have been read. Use this to cap descriptor use in processes with a low
+    /// `RLIMIT_NOFILE`, or to limit concurrent opens on network mounts. Threads
+    /// wait for a free slot before opening a directory. A limit of `0` is
+    /// treated as `1`.
+    pub fn max_concurrent_reads(mut self, n: usize) -> Self {
+        self.options.max_concurrent_reads = n;
+        self
+    }
+
+    /// Read the entries of each directory in batches of `n`, filtering each
+    /// batch before reading the next. By default, all entries of a directory
+    /// are read before they are filtered.
+    ///
+    /// Entries removed by [`skip_hidden`], [`respect_gitignore`],
+    /// [`extensions`], [`glob_include`], [`filter_entry`] and similar filters are
+    /// dropped after each batch, so they don't accumulate in memory when
+    /// walking huge directories where most entries are filtered out. Entries
+    /// that pass the filters are still held until the whole directory has
+    /// been read, because they are sorted and passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// together. A batch size of `0` is treated as `1`.
+    ///
+    /// [`skip_hidden`]: struct.WalkDirGeneric.html#method.skip_hidden
+    /// [`respect_gitignore`]: struct.WalkDirGeneric.html#method.respect_gitignore
+    /// [`extensions`]: struct.WalkDirGeneric.html#method.extensions
+    /// [`glob_include`]: struct.WalkDirGeneric.html#method.glob_include
+    /// [`filter_entry`]: struct.WalkDirGeneric.html#method.filter_entry
+    pub fn read_dir_batch_size(mut self, n: usize) -> Self {
+        self.options.read_dir_batch_size = n;
+        self
+    }
+
+    /// Process the entries of directories with at least `n` entries in
+    /// parallel. By default, this is disabled.
+    ///
+    /// Parallel walks read one directory per thread, so a directory with a
+    /// million files is read and processed by a single thread. The read
+    /// itself is still serial, but with this set the per entry work after it
+    /// is split across the walk's thread pool: [`filter_entry`],
+    /// [`filter_map_entry`], the metadata filters such as [`min_file_size`],
+    /// loading metadata for [`Sort::Size`], [`read_xattrs`], [`hash_with`]
+    /// and [`client_state_with`]. The callbacks must not depend on the order
+    /// they are called in. Entries are yielded in the same order either way.
+    ///
+    /// Splitting work has a cost, so keep `n` in the thousands. It's only
+    /// worth it when the per entry work is slow, such as reading metadata or
+    /// hashing on a network file system. With [`read_dir_batch_size`] the
+    /// threshold applies to each batch. Filters are processed serially when
+    /// [`max_concurrent_reads`] is set, and all per entry work is when
+    /// [`max_queued_entries`] is set, since a thread splitting work could
+    /// pick up a directory read that waits for a permit or for the iterator.
+    /// Has no effect on
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) walks.
+    ///
+    /// [`filter_entry`]: struct.WalkDirGeneric.html#method.filter_entry
+    /// [`filter_map_entry`]: struct.WalkDirGeneric.html#method.filter_map_entry
+    /// [`min_file_size`]: struct.WalkDirGeneric.html#method.min_file_size
+    /// [`Sort::Size`]: enum.Sort.html#variant.Size
+    /// [`read_xattrs`]: struct.WalkDirGeneric.html#method.read_xattrs
+    /// [`hash_with`]: struct.WalkDirGeneric.html#method.hash_with
+    /// [`client_state_with`]: struct.WalkDirGeneric.html#method.client_state_with
+    /// [`read_dir_batch_size`]: struct.WalkDirGeneric.html#method.read_dir_batch_size
+    /// [`max_concurrent_reads`]: struct.WalkDirGeneric.html#method.max_concurrent_reads
+    /// [`max_queued_entries`]: struct.WalkDirGeneric.html#method.max_queued_entries
+    pub fn par_entries_threshold(mut self, n: usize) -> Self {
+        self.options.par_entries_threshold = n;
+        self
+    }
+
+    /// Order in which threads pick directories to read. Defaults to
+    /// [`TraversalOrder::DepthFirst`](enum.TraversalOrder.html#variant.DepthFirst).
+    ///
+    /// This only changes scheduling, entries are always yielded in depth
+    /// first order. Depth first reads directories close to the order they
+    /// are yielded in, so fewer results wait in memory for the iterator.
+    /// Breadth first reads shallow directories first, so results of deep
+    /// directories are held until the iterator reaches them, which can use
+    /// much more memory on large trees. Has no effect on
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) walks
+    /// or on [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter).
+    pub fn traversal_order(mut self, traversal_order: TraversalOrder) -> Self {
+        self.options.traversal_order = traversal_order;
+        self
+    }
+
+    /// Yield each directory's entries as soon as it has been read, instead of
+    /// waiting for the directories before it in depth first order. Defaults
+    /// to `false`.
+    ///
+    /// Entries within a directory are still sorted and yielded together, but
+    /// directories are yielded in the order their reads complete, so a slow
+    /// directory doesn't hold back the rest of the walk. A directory is
+    /// yielded before its contents, which may not follow it directly. An error
+    /// reading a directory is yielded as its own item rather than set on the
+    /// directory's entry, and [`child_count`] is always `None`.
+    /// [`contents_first`] and [`skip_empty_dirs`] are ignored and
+    /// [`DirEntryIter::state`] returns `None`. Has no effect on
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial) walks,
+    /// which read directories in order anyway.
+    ///
+    /// [`child_count`]: struct.DirEntry.html#method.child_count
+    /// [`contents_first`]: struct.WalkDirGeneric.html#method.contents_first
+    /// [`skip_empty_dirs`]: struct.WalkDirGeneric.html#method.skip_empty_dirs
+    /// [`DirEntryIter::state`]: struct.DirEntryIter.html#method.state
+    pub fn unordered_first(mut self, unordered_first: bool) -> Self {
+        self.options.unordered_first = unordered_first;
+        self
+    }
+
+    /// Read directories on a separate thread even when the walk is
+    /// [`Parallelism::Serial`](enum.Parallelism.html#variant.Serial). By
+    /// default, this is disabled.
+    ///
+    /// Serial walks read each directory on the calling thread when the
+    /// iterator reaches it, so `next` can block on slow file systems. When
+    /// `true` a single background thread reads ahead and the iterator only
+    /// waits for directories that aren't read yet, which keeps a UI thread
+    /// responsive. Entries are yielded in the same order and read ahead is
+    /// limited by
+    /// [`max_queued_entries`](struct.WalkDirGeneric.html#method.max_queued_entries).
+    /// The roots themselves are still read when the iterator is created.
+    /// Parallel walks always read on other threads, so this only affects
+    /// serial walks, and it has no effect on
+    /// [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter).
+    pub fn background(mut self, background: bool) -> Self {
+        self.options.background = background;
+        self
+    }
+
+    /// Flag entries of directories that changed while they were read, see
+    /// [`DirEntry::possibly_stale`](struct.DirEntry.html#method.possibly_stale).
+    /// By default, this is disabled.
+    ///
+    /// The modification time of each directory is read before and after its
+    /// entries are listed, at the cost of two extra system calls per
+    /// directory. This is best effort: a change within the time resolution
+    /// of the file system isn't noticed, and changes after a directory is read
+    /// aren't either. Changes never fail the walk. Directories read with
+    /// [`with_reader`](struct.WalkDirGeneric.html#method.with_reader) aren't
+    /// checked, since they need not exist on disk.
+    pub fn detect_changes(mut self, detect_changes: bool) -> Self {
+        self.options.detect_changes = detect_changes;
+        self
+    }
+
+    /// Stop the walk early once `cancellation` is set to `true`.
+    ///
+    /// The flag is checked before each directory is read. Once set, no more
+    /// directories are read and the iterator finishes after yielding entries
+    /// that were already read. Those entries keep their `read_children_path`
+    /// even though their contents won't be yielded.
+    pub fn cancellation(mut self, cancellation: Arc<AtomicBool>) -> Self {
+        self.options.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Stop the walk early once `time_budget` has elapsed since it started.
+    ///
+    /// The walk starts when it is turned into an iterator. Like
+    /// [`cancellation`](struct.WalkDirGeneric.html#method.cancellation), the
+    /// time is checked before each directory is read. Once the budget is
+    /// spent no more directories are read, and the iterator finishes after
+    /// yielding the entries that were already read. A directory that is
+    /// being read when the budget runs out is finished, so the walk can run
+    /// over.
+    pub fn time_budget(mut self, time_budget: Duration) -> Self {
+        self.options.time_budget = Some(time_budget);
+        self
+    }
+
     /// Initial ClientState::ReadDirState that is passed to
     /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
     /// when processing root. Defaults to ClientState::ReadDirState::default().
@@ -304,175 +1221,1918 @@ impl<C: ClientState> WalkDirGeneric<C> {
         self
     }
 
-    /// A callback function to process (sort/filter/skip/state) each directory
-    /// of entries before they are yielded. Modify the given array to
-    /// sort/filter entries. Use [`entry.read_children_path =
-    /// None`](struct.DirEntry.html#field.read_children_path) to yield a
-    /// directory entry but skip reading its contents. Use
-    /// [`entry.client_state`](struct.DirEntry.html#field.client_state)
-    /// to store custom state with an entry.
-    pub fn process_read_dir<F>(mut self, process_by: F) -> Self
+    /// Don't descend into directories with one of the given names, for example
+    /// `.git`, `node_modules` or `target`. The directories themselves are
+    /// still yielded.
+    pub fn skip_dirs<I, S>(mut self, names: I) -> Self
     where
-        F: Fn(Option<usize>, &Path, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
-            + Send
-            + Sync
-            + 'static,
+        I: IntoIterator<Item = S>,
+        S: Into<OsString>,
     {
-        self.options.process_read_dir = Some(Arc::new(process_by));
+        self.options.skip_dirs = Some(Arc::new(names.into_iter().map(Into::into).collect()));
         self
     }
-}
 
-fn process_dir_entry_result<C: ClientState>(
-    dir_entry_result: Result<DirEntry<C>>,
-    follow_links: bool,
-) -> Result<DirEntry<C>> {
-    match dir_entry_result {
-        Ok(mut dir_entry) => {
-            if follow_links && dir_entry.file_type.is_symlink() {
-                dir_entry = dir_entry.follow_symlink()?;
-            }
+    /// Drop directories whose name matches the predicate as soon as the name
+    /// is read, so that directories such as `node_modules` are neither
+    /// yielded, stat-ed nor descended into.
+    ///
+    /// The predicate is called with each name from the directory listing,
+    /// before the entry's metadata is read and before any other filter,
+    /// including
+    /// [`on_error`](struct.WalkDirGeneric.html#method.on_error),
+    /// [`respect_gitignore`](struct.WalkDirGeneric.html#method.respect_gitignore),
+    /// [`skip_dirs`](struct.WalkDirGeneric.html#method.skip_dirs) and
+    /// [`filter_entry`](struct.WalkDirGeneric.html#method.filter_entry).
+    /// Matching entries are only dropped if the listing says they are
+    /// directories, which is free on most platforms, so symbolic links to
+    /// directories aren't pruned. Walks started with
+    /// [`from_dir`](struct.WalkDirGeneric.html#method.from_dir) only have the
+    /// type after a stat, so there files with a matching name are dropped too.
+    /// Names are passed as read, without
+    /// [`normalize_names`](struct.WalkDirGeneric.html#method.normalize_names).
+    /// Roots are never pruned. With
+    /// [`with_reader`](struct.WalkDirGeneric.html#method.with_reader) the
+    /// predicate is applied to the entries the reader returns.
+    pub fn prune_dirs<F>(mut self, prune_dirs: F) -> Self
+    where
+        F: Fn(&OsStr) -> bool + Send + Sync + 'static,
+    {
+        self.options.prune_dirs = Some(Arc::new(prune_dirs));
+        self
+    }
 
-            if dir_entry.depth == 0 && dir_entry.file_type.is_symlink() {
-                // As a special case, if we are processing a root entry, then we
-                // always follow it even if it's a symlink and follow_links is
-                // false. We are careful to not let this change the semantics of
-                // the DirEntry however. Namely, the DirEntry should still
-                // respect the follow_links setting. When it's disabled, it
-                // should report itself as a symlink. When it's enabled, it
-                // should always report itself as the target.
-                let metadata = fs::metadata(dir_entry.path())
-                    .map_err(|err| Error::from_path(0, dir_entry.path().to_owned(), err))?;
-                if metadata.file_type().is_dir() {
-                    dir_entry.read_children_path = Some(Arc::from(dir_entry.path()));
-                }
-            }
+    /// Yield only files with one of the given extensions. Extensions are
+    /// matched ASCII case-insensitively and may be given with or without a
+    /// leading `.`.
+    ///
+    /// Directories are always yielded and descended into.
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let extensions = extensions
+            .into_iter()
+            .map(|extension| extension.as_ref().trim_start_matches('.').to_owned())
+            .collect();
+        self.options.extensions = Some(Arc::new(extensions));
+        self
+    }
 
-            Ok(dir_entry)
-        }
-        Err(err) => Err(err),
+    /// Yield only files modified after the given time.
+    ///
+    /// The modification time is read with [`DirEntry::metadata`], which
+    /// caches the metadata of every file checked. Entries whose metadata
+    /// can't be read are yielded as errors. Directories are always yielded
+    /// and descended into.
+    ///
+    /// [`DirEntry::metadata`]: struct.DirEntry.html#method.metadata
+    pub fn modified_after(mut self, time: SystemTime) -> Self {
+        self.options.modified_after = Some(time);
+        self
     }
-}
 
-impl<C: ClientState> IntoIterator for WalkDirGeneric<C> {
-    type IntoIter = DirEntryIter<C>;
-    type Item = Result<DirEntry<C>>;
+    /// Yield only files modified before the given time.
+    ///
+    /// See [`modified_after`](struct.WalkDirGeneric.html#method.modified_after).
+    pub fn modified_before(mut self, time: SystemTime) -> Self {
+        self.options.modified_before = Some(time);
+        self
+    }
+
+    /// Yield only files whose `metadata().len()` is at least `size` bytes.
+    ///
+    /// The size is read with [`DirEntry::metadata`], which caches the
+    /// metadata of every file checked. Entries whose metadata can't be read
+    /// are yielded as errors. Directories are always yielded and descended
+    /// into.
+    ///
+    /// [`DirEntry::metadata`]: struct.DirEntry.html#method.metadata
+    pub fn min_file_size(mut self, size: u64) -> Self {
+        self.options.min_file_size = Some(size);
+        self
+    }
+
+    /// Yield only files whose `metadata().len()` is at most `size` bytes.
+    ///
+    /// See [`min_file_size`](struct.WalkDirGeneric.html#method.min_file_size).
+    pub fn max_file_size(mut self, size: u64) -> Self {
+        self.options.max_file_size = Some(size);
+        self
+    }
+
+    /// Yield only files whose path relative to the root matches one of the
+    /// given shell style patterns, for example `**/*.log`.
+    ///
+    /// Patterns support `?`, `*`, `[...]` classes and `**` to match any
+    /// number of directories. Bytes that aren't valid UTF-8 are matched by
+    /// `?`, `*` and negated classes, but never by a literal character.
+    /// Directories are always yielded and descended into.
+    pub fn glob_include<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = patterns.into_iter().map(|pattern| pattern.as_ref().to_owned()).collect();
+        self.options.glob_include = Some(Arc::new(patterns));
+        self
+    }
+
+    /// Skip entries whose path relative to the root matches one of the given
+    /// shell style patterns, for example `target/**`. Excluded directories
+    /// are not descended into.
+    ///
+    /// See [`glob_include`](struct.WalkDirGeneric.html#method.glob_include)
+    /// for pattern syntax.
+    pub fn glob_exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = patterns.into_iter().map(|pattern| pattern.as_ref().to_owned()).collect();
+        self.options.glob_exclude = Some(Arc::new(patterns));
+        self
+    }
+
+    /// Skip entries whose path relative to the root matches `regex`.
+    /// Excluded directories are not descended into.
+    ///
+    /// The regex is searched for anywhere in the path, use `^` and `$` to
+    /// match all of it. Paths use the platform's separator and paths that
+    /// aren't valid UTF-8 are matched lossily. The root is never excluded.
+    /// Requires the `regex` feature.
+    #[cfg(feature = "regex")]
+    pub fn path_regex_exclude(mut self, regex: regex::Regex) -> Self {
+        self.options.path_regex_exclude = Some(regex);
+        self
+    }
+
+    /// Normalize file names to the given Unicode normalization form before
+    /// matching them against [`skip_dirs`], [`extensions`], [`glob_include`]
+    /// and [`glob_exclude`]. By default, names are matched as they are.
+    ///
+    /// The patterns are normalized too, so a pattern written in NFC matches
+    /// a name stored in NFD, as file names often are on macOS. Only the
+    /// comparison is affected, `file_name` and `path` are yielded unchanged so
+    /// they can still be used to open the entry. Names that aren't valid
+    /// UTF-8 are compared as they are. Requires the `unicode` feature.
+    ///
+    /// [`skip_dirs`]: struct.WalkDirGeneric.html#method.skip_dirs
+    /// [`extensions`]: struct.WalkDirGeneric.html#method.extensions
+    /// [`glob_include`]: struct.WalkDirGeneric.html#method.glob_include
+    /// [`glob_exclude`]: struct.WalkDirGeneric.html#method.glob_exclude
+    #[cfg(feature = "unicode")]
+    pub fn normalize_names(mut self, form: NormalizationForm) -> Self {
+        self.options.normalize_names = Some(form);
+        self
+    }
+
+    /// Yield only entries which satisfy the given predicate and skip
+    /// descending into directories that do not satisfy the given predicate.
+    ///
+    /// The predicate is applied to all entries, including the root. If the
+    /// predicate returns `false` for a directory then neither it nor any of
+    /// its descendents are yielded. Errors are always yielded.
+    ///
+    /// The predicate runs before
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// so rejected entries are never passed to that callback. It also runs
+    /// before the filters that read metadata, such as
+    /// [`min_file_size`](struct.WalkDirGeneric.html#method.min_file_size),
+    /// so entries it rejects are never stat'd. A predicate that calls
+    /// [`DirEntry::metadata`](struct.DirEntry.html#method.metadata) itself
+    /// doesn't benefit.
+    pub fn filter_entry<P>(mut self, predicate: P) -> Self
+    where
+        P: Fn(&DirEntry<C>) -> bool + Send + Sync + 'static,
+    {
+        self.options.filter_entry = Some(Arc::new(predicate));
+        self
+    }
+
+    /// Decide separately whether to yield each entry and whether to descend
+    /// into it, by returning an [`Action`](enum.Action.html).
+    ///
+    /// Like [`filter_entry`](struct.WalkDirGeneric.html#method.filter_entry)
+    /// the callback is applied to all entries, including the root, and runs
+    /// before [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir).
+    /// `Action::SkipSubtree` behaves like `filter_entry` returning `false`.
+    /// Errors are always yielded. Both callbacks can be set, entries rejected
+    /// by `filter_entry` aren't passed to this one.
+    pub fn filter_map_entry<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&DirEntry<C>) -> Action + Send + Sync + 'static,
+    {
+        self.options.filter_map_entry = Some(Arc::new(f));
+        self
+    }
+
+    /// Yield only files or only directories. By default, all entries are
+    /// yielded.
+    ///
+    /// Unlike [`filter_entry`](struct.WalkDirGeneric.html#method.filter_entry)
+    /// this never affects which directories are descended into, directories
+    /// are still read when only files are yielded. Errors are always yielded.
+    /// Entries are still passed to
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir).
+    pub fn file_type_filter(mut self, file_type_filter: FileTypeFilter) -> Self {
+        self.options.file_type_filter = file_type_filter;
+        self
+    }
+
+    /// Choose how errors are handled. By default errors are yielded.
+    ///
+    /// [`ErrorHandling::Skip`](enum.ErrorHandling.html#variant.Skip) drops
+    /// errors before they reach
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// so inaccessible directories are silently skipped. Directories at the
+    /// [`max_recursion_depth`](struct.WalkDirGeneric.html#method.max_recursion_depth)
+    /// are dropped too.
+    /// [`ErrorHandling::Abort`](enum.ErrorHandling.html#variant.Abort) ends
+    /// the walk after yielding the first error, either an `Err` result or an
+    /// entry with [`read_children_error`](struct.DirEntry.html#field.read_children_error)
+    /// set. With [`into_par_iter`](struct.WalkDirGeneric.html#method.into_par_iter)
+    /// other threads may still yield a few entries they already read.
+    pub fn on_error(mut self, on_error: ErrorHandling) -> Self {
+        self.options.on_error = on_error;
+        self
+    }
+
+    /// A callback function to process (sort/filter/skip/state) each directory
+    /// of entries before they are yielded. Modify the given array to
+    /// sort/filter entries. Use [`entry.read_children_path =
+    /// None`](struct.DirEntry.html#field.read_children_path) to yield a
+    /// directory entry but skip reading its contents. Use
+    /// [`entry.client_state`](struct.DirEntry.html#field.client_state)
+    /// to store custom state with an entry.
+    ///
+    /// The callback is given the fields of the directory's
+    /// [`ReadDirSpec`](struct.ReadDirSpec.html), so rules can depend on
+    /// which directory is being processed:
+    ///
+    /// 1. The depth of the directory that was read, or `None` when processing
+    ///    the roots. Its entries have a
+    ///    [`depth`](struct.DirEntry.html#method.depth) one greater, and a
+    ///    [`read_dir_spec_depth`](struct.DirEntry.html#method.read_dir_spec_depth)
+    ///    equal to it.
+    /// 2. The path of the directory that was read, or the parent of the root
+    ///    when processing the roots.
+    /// 3. The directory's `client_read_state`, inherited from its parent.
+    /// 4. The entries of the directory.
+    pub fn process_read_dir<F>(mut self, process_by: F) -> Self
+    where
+        F: Fn(Option<usize>, &Path, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.options.process_read_dir = Some(Arc::new(process_by));
+        self
+    }
+
+    /// Like [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir),
+    /// but the callback is also given mutable scratch state such as a reusable
+    /// buffer or hasher.
+    ///
+    /// Each thread that processes a directory creates its own state with
+    /// `init` the first time it needs one and reuses it for later calls, so
+    /// the callback has exclusive access to its state without any locking.
+    /// Replaces any previously set `process_read_dir` callback.
+    pub fn process_read_dir_with_state<S, I, F>(self, init: I, process_by: F) -> Self
+    where
+        S: Send + 'static,
+        I: Fn() -> S + Send + Sync + 'static,
+        F: Fn(&mut S, Option<usize>, &Path, &mut C::ReadDirState, &mut Vec<Result<DirEntry<C>>>)
+            + Send
+            + Sync
+            + 'static,
+    {
+        let states = ThreadState::new(init);
+        self.process_read_dir(move |depth, path, read_dir_state, children| {
+            states.with(|state| process_by(state, depth, path, read_dir_state, children))
+        })
+    }
+
+    /// A callback function called each time a directory is read, with the
+    /// directory's path and the number of entries found. Useful for reporting
+    /// progress.
+    ///
+    /// The count is taken before any filtering, except that hidden entries
+    /// skipped by [`skip_hidden`](struct.WalkDirGeneric.html#method.skip_hidden)
+    /// and special files skipped by `skip_special_files` aren't counted.
+    /// Directories that can't be read are not reported. The callback is called
+    /// from the threads reading directories.
+    pub fn on_read_dir<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Path, usize) + Send + Sync + 'static,
+    {
+        self.options.on_read_dir = Some(Arc::new(f));
+        self
+    }
+
+    /// A callback function to hash the contents of each file, available from
+    /// [`DirEntry::hash`](struct.DirEntry.html#method.hash). The callback is
+    /// given the file's path and returns its hash as bytes, or an error that
+    /// is stored on the entry instead.
+    ///
+    /// Files are hashed by the threads reading directories, after entries are
+    /// filtered and before
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// is called, so hashing runs in parallel with the walk instead of in a
+    /// second pass. Files in the same directory are hashed by the same thread.
+    /// Only entries where `file_type().is_file()` are hashed.
+    pub fn hash_with<H, F>(mut self, f: F) -> Self
+    where
+        H: AsRef<[u8]>,
+        F: Fn(&Path) -> io::Result<H> + Send + Sync + 'static,
+    {
+        self.options.hash_with =
+            Some(Arc::new(move |path| f(path).map(|hash| hash.as_ref().into())));
+        self
+    }
+
+    /// A callback function to compute the
+    /// [`client_state`](struct.DirEntry.html#structfield.client_state) of
+    /// each entry, for example data parsed from a file's header.
+    ///
+    /// The state is computed by the threads reading directories, after
+    /// entries are filtered and hashed and before
+    /// [`process_read_dir`](struct.WalkDirGeneric.html#method.process_read_dir)
+    /// is called, which can still change it. Unlike computing it while
+    /// iterating, this runs in parallel with the walk.
+    pub fn client_state_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&DirEntry<C>) -> C::DirEntryState + Send + Sync + 'static,
+    {
+        self.options.client_state_with = Some(Arc::new(f));
+        self
+    }
+
+    /// Read directories with `reader` instead of `fs::read_dir`, for example
+    /// to walk an archive or an in-memory tree. Defaults to
+    /// [`FsDirReader`](struct.FsDirReader.html).
+    ///
+    /// Only directories below the roots are read with `reader`, the roots
+    /// themselves must exist on the file system. Directories are always read
+    /// with `reader` when it is set, including with
+    /// [`from_dir`](struct.WalkDirGeneric.html#method.from_dir).
+    pub fn with_reader<R: DirReader<C>>(mut self, reader: R) -> Self {
+        self.options.dir_reader = Some(Arc::new(reader));
+        self
+    }
+
+    /// Walk in parallel, yielding entries in no particular order.
+    ///
+    /// Each directory is read by the rayon thread that consumes its parent's
+    /// entry, and entries are handed straight to the consumer without passing
+    /// through the ordered queue used by [`into_iter`]. Use this when you
+    /// process every entry independently (for example hashing files) and don't
+    /// care about order. [`contents_first`], [`skip_empty_dirs`] and
+    /// [`first_n`] are ignored.
+    ///
+    /// [`Parallelism`](enum.Parallelism.html) selects the thread pool the walk
+    /// runs in. `Parallelism::Serial` yields entries in the same order as
+    /// [`into_iter`] without using any thread pool.
+    ///
+    /// [`into_iter`]: struct.WalkDirGeneric.html#method.into_iter
+    /// [`contents_first`]: struct.WalkDirGeneric.html#method.contents_first
+    /// [`skip_empty_dirs`]: struct.WalkDirGeneric.html#method.skip_empty_dirs
+    /// [`first_n`]: struct.WalkDirGeneric.html#method.first_n
+    pub fn into_par_iter(self) -> ParDirEntryIter<C> {
+        let parallelism = self.options.parallelism.clone();
+        let min_depth = self.options.min_depth;
+        let abort_on_error = self.options.on_error == ErrorHandling::Abort;
+        let (root_entry_results, root_read_dir_state, core_read_dir_callback) = self.into_parts();
+        ParDirEntryIter::new(
+            root_entry_results,
+            parallelism,
+            min_depth,
+            abort_on_error,
+            root_read_dir_state,
+            core_read_dir_callback,
+        )
+    }
+
+    /// Walk as a `futures_core::Stream` that yields the same entries in the
+    /// same order as [`into_iter`].
+    ///
+    /// The walk is driven by [`into_iter`] on a dedicated thread and entries
+    /// are forwarded to the stream, so it doesn't block the async runtime and
+    /// doesn't depend on any particular runtime. The walk thread waits when
+    /// the stream falls behind, and stops when the stream is dropped.
+    /// Requires the `async` feature.
+    ///
+    /// [`into_iter`]: struct.WalkDirGeneric.html#method.into_iter
+    #[cfg(feature = "async")]
+    pub fn into_stream(self) -> DirEntryStream<C> {
+        DirEntryStream::new(self)
+    }
+
+    /// Walk in the same order as [`into_iter`], yielding the entries of each
+    /// directory together with the [`ReadDirSpec`] used to read it.
+    ///
+    /// Entries are grouped as they are read, which is cheaper than grouping
+    /// the entries of [`into_iter`] by parent and suits per directory work
+    /// such as computing folder sizes. The root entries aren't yielded, the
+    /// first item is the contents of the first root. If a directory can't be
+    /// read its entries are the error. Directories at [`max_depth`] aren't
+    /// read and aren't yielded, not even with empty contents.
+    /// [`contents_first`], [`skip_empty_dirs`] and [`first_n`] are ignored.
+    ///
+    /// [`into_iter`]: struct.WalkDirGeneric.html#method.into_iter
+    /// [`ReadDirSpec`]: struct.ReadDirSpec.html
+    /// [`max_depth`]: struct.WalkDirGeneric.html#method.max_depth
+    /// [`contents_first`]: struct.WalkDirGeneric.html#method.contents_first
+    /// [`skip_empty_dirs`]: struct.WalkDirGeneric.html#method.skip_empty_dirs
+    /// [`first_n`]: struct.WalkDirGeneric.html#method.first_n
+    pub fn into_dir_iter(self) -> DirIter<C> {
+        let parallelism = self.options.parallelism.clone();
+        let max_queued_entries = self.options.max_queued_entries;
+        let traversal_order = self.options.traversal_order;
+        let background = self.options.background;
+        let min_depth = self.options.min_depth;
+        let (root_entry_results, root_read_dir_state, core_read_dir_callback) = self.into_parts();
+        DirIter::new(
+            root_entry_results,
+            parallelism,
+            max_queued_entries,
+            traversal_order,
+            background,
+            min_depth,
+            root_read_dir_state,
+            core_read_dir_callback,
+        )
+    }
+
+    /// Walk serially, yielding the `std::fs::DirEntry` of each entry as
+    /// `fs::read_dir` lists it.
+    ///
+    /// Entries aren't converted to [`DirEntry`] and no per directory state is
+    /// kept, which makes shallow walks such as [`max_depth`] 1 cost about the
+    /// same as `fs::read_dir`. The roots aren't yielded, and each directory is
+    /// yielded before its contents in the order it is listed. Only the roots,
+    /// [`min_depth`], [`max_depth`] and [`skip_hidden`] apply, all other
+    /// options are ignored. Symbolic links below the roots are never
+    /// followed.
+    ///
+    /// [`DirEntry`]: struct.DirEntry.html
+    /// [`min_depth`]: struct.WalkDirGeneric.html#method.min_depth
+    /// [`max_depth`]: struct.WalkDirGeneric.html#method.max_depth
+    /// [`skip_hidden`]: struct.WalkDirGeneric.html#method.skip_hidden
+    pub fn into_std_iter(self) -> StdDirEntryIter {
+        StdDirEntryIter::new(
+            self.roots,
+            self.options.min_depth,
+            self.options.max_depth,
+            self.options.skip_hidden,
+        )
+    }
+
+    /// Walk serially, calling `f` with a borrow of each entry or error that is
+    /// only valid during the call.
+    ///
+    /// One [`DirEntry`] is overwritten in place for every entry below the
+    /// roots, reusing its path buffer, instead of creating and dropping an
+    /// entry each time. Apart from the file names listed by `fs::read_dir`,
+    /// only directories allocate, for the path used to read them. This makes
+    /// filter-and-discard loops cheaper than [`into_iter`], but entries can't
+    /// be kept or collected, clone what you need from them instead. Entries
+    /// are visited in the same order as [`into_iter`] with
+    /// `Parallelism::Serial`, each directory is read before it is visited so
+    /// [`child_count`] and `read_children_error` are set.
+    ///
+    /// Only the roots, [`sort`], [`include_root`], [`min_depth`],
+    /// [`max_depth`], [`first_n`], [`skip_hidden`], [`follow_root_links`],
+    /// [`on_error`], [`cancellation`] and [`time_budget`] apply, all other
+    /// options are ignored. Symbolic links below the roots are never
+    /// followed.
+    ///
+    /// Of the sort options only sorting by name, with `sort(true)` or
+    /// `sort_order(Sort::Name)`, is honoured. Other sort orders,
+    /// [`sort_by`], [`sort_by_key`] and [`sort_reverse`] are ignored, and
+    /// without sorting by name entries are visited in the order they are
+    /// listed.
+    ///
+    /// [`DirEntry`]: struct.DirEntry.html
+    /// [`into_iter`]: struct.WalkDirGeneric.html#method.into_iter
+    /// [`child_count`]: struct.DirEntry.html#method.child_count
+    /// [`sort`]: struct.WalkDirGeneric.html#method.sort
+    /// [`sort_by`]: struct.WalkDirGeneric.html#method.sort_by
+    /// [`sort_by_key`]: struct.WalkDirGeneric.html#method.sort_by_key
+    /// [`sort_reverse`]: struct.WalkDirGeneric.html#method.sort_reverse
+    /// [`include_root`]: struct.WalkDirGeneric.html#method.include_root
+    /// [`min_depth`]: struct.WalkDirGeneric.html#method.min_depth
+    /// [`max_depth`]: struct.WalkDirGeneric.html#method.max_depth
+    /// [`first_n`]: struct.WalkDirGeneric.html#method.first_n
+    /// [`skip_hidden`]: struct.WalkDirGeneric.html#method.skip_hidden
+    /// [`follow_root_links`]: struct.WalkDirGeneric.html#method.follow_root_links
+    /// [`on_error`]: struct.WalkDirGeneric.html#method.on_error
+    /// [`cancellation`]: struct.WalkDirGeneric.html#method.cancellation
+    /// [`time_budget`]: struct.WalkDirGeneric.html#method.time_budget
+    pub fn for_each<F>(self, f: F)
+    where
+        F: FnMut(std::result::Result<&DirEntry<C>, &Error>),
+    {
+        let options = LendOptions {
+            sort: self.options.sort == Some(Sort::Name),
+            include_root: self.options.include_root,
+            min_depth: self.options.min_depth,
+            max_depth: self.options.max_depth,
+            first_n: self.options.first_n,
+            skip_hidden: self.options.skip_hidden,
+            follow_root_links: self.options.follow_root_links,
+            skip_errors: self.options.on_error == ErrorHandling::Skip,
+            abort_on_error: self.options.on_error == ErrorHandling::Abort,
+            cancellation: self.options.cancellation,
+            deadline: self.options.time_budget.map(|time_budget| Instant::now() + time_budget),
+        };
+        EntryLender::new(options, f).walk(self.roots);
+    }
+
+    /// Walk in parallel and return the total size and number of files,
+    /// directories and symbolic links.
+    ///
+    /// Entries are visited with [`into_par_iter`], so all options that filter
+    /// entries apply. Metadata is only read for files. Errors below the root
+    /// are counted in [`WalkStats::errors`] and otherwise skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root itself can't be read, including when it
+    /// is a directory whose contents can't be listed.
+    ///
+    /// [`into_par_iter`]: struct.WalkDirGeneric.html#method.into_par_iter
+    /// [`WalkStats::errors`]: struct.WalkStats.html#structfield.errors
+    pub fn stats(self) -> Result<WalkStats> {
+        self.into_par_iter()
+            .map(|dir_entry_result| match dir_entry_result {
+                Ok(dir_entry) => match dir_entry.read_children_error {
+                    Some(err) if dir_entry.depth == 0 => Err(err),
+                    _ => Ok(WalkStats::of(&dir_entry)),
+                },
+                Err(err) if err.depth() == 0 => Err(err),
+                Err(_) => Ok(WalkStats { errors: 1, ..WalkStats::default() }),
+            })
+            .try_reduce(WalkStats::default, |a, b| Ok(a.merge(b)))
+    }
+
+    /// Walk in parallel and return the number of entries yielded.
+    ///
+    /// This is faster than `into_iter().count()` because entries are counted
+    /// by the rayon threads that read them, as with [`into_par_iter`], and
+    /// [`sort`] and [`hash_with`] are ignored. All options that filter entries
+    /// apply. [`skip_empty_dirs`] and [`first_n`] depend on the order entries
+    /// are yielded in, so when either is set entries are counted from
+    /// [`into_iter`] instead, which is no faster. Errors below the root are
+    /// skipped and not counted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root itself can't be read.
+    ///
+    /// [`into_par_iter`]: struct.WalkDirGeneric.html#method.into_par_iter
+    /// [`into_iter`]: struct.WalkDirGeneric.html#method.into_iter
+    /// [`sort`]: struct.WalkDirGeneric.html#method.sort
+    /// [`hash_with`]: struct.WalkDirGeneric.html#method.hash_with
+    /// [`skip_empty_dirs`]: struct.WalkDirGeneric.html#method.skip_empty_dirs
+    /// [`first_n`]: struct.WalkDirGeneric.html#method.first_n
+    pub fn count(mut self) -> Result<usize> {
+        self.options.sort = None;
+        self.options.sort_by = None;
+        self.options.sort_by_key = None;
+        self.options.hash_with = None;
+        if self.options.skip_empty_dirs || self.options.first_n != usize::MAX {
+            let mut count = 0;
+            for dir_entry_result in self {
+                match dir_entry_result {
+                    Ok(_) => count += 1,
+                    Err(err) if err.depth() == 0 => return Err(err),
+                    Err(_) => {}
+                }
+            }
+            return Ok(count);
+        }
+        self.into_par_iter()
+            .map(|dir_entry_result| match dir_entry_result {
+                Ok(_) => Ok(1),
+                Err(err) if err.depth() == 0 => Err(err),
+                Err(_) => Ok(0),
+            })
+            .try_reduce(|| 0, |a, b| Ok(a + b))
+    }
+
+    /// Walk with [`contents_first`] and remove every entry, files before the
+    /// directories that contain them, returning all errors encountered.
+    ///
+    /// Symbolic links are never followed, so links are removed rather than
+    /// what they point to. A root that is a link, even when given with a
+    /// trailing separator, is removed itself, or left alone when
+    /// [`min_depth`] is set. Roots are walked as given, so [`absolute`],
+    /// [`with_reader`] and the roots' depths from [`resume`] are ignored.
+    /// Hidden entries are removed. Other options that filter entries still
+    /// apply, and a directory that still has entries left in it fails to be
+    /// removed. Set [`min_depth`] to 1 to empty the root without removing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the walk's errors and the errors from removing entries, in the
+    /// order they happened. Removal continues past errors.
+    ///
+    /// [`contents_first`]: struct.WalkDirGeneric.html#method.contents_first
+    /// [`min_depth`]: struct.WalkDirGeneric.html#method.min_depth
+    /// [`absolute`]: struct.WalkDirGeneric.html#method.absolute
+    /// [`with_reader`]: struct.WalkDirGeneric.html#method.with_reader
+    /// [`resume`]: struct.WalkDirGeneric.html#method.resume
+    pub fn remove_all(mut self) -> std::result::Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+
+        // A root given as `link/` would be walked through the link, so links
+        // are found without the trailing separator and never walked
+        let roots = std::mem::take(&mut self.roots);
+        for root in roots {
+            let root: PathBuf = root.components().collect();
+            match fs::symlink_metadata(&root) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    if self.options.min_depth > 0 {
+                        continue;
+                    }
+                    if let Err(err) = remove_symlink(&root, &metadata.file_type()) {
+                        errors.push(Error::from_path(0, root, err));
+                    }
+                }
+                _ => self.roots.push(root),
+            }
+        }
+        self.root_depths.clear();
+        self.root_indices.clear();
+        self.root_actions.clear();
+        self.options.absolute = false;
+        self.options.dir_reader = None;
+        #[cfg(windows)]
+        {
+            self.options.windows_long_paths = false;
+        }
+        if self.roots.is_empty() {
+            return if errors.is_empty() { Ok(()) } else { Err(errors) };
+        }
+
+        let walk_dir = self
+            .contents_first(true)
+            .skip_empty_dirs(false)
+            .unordered_first(false)
+            .follow_links(false)
+            .follow_root_links(false)
+            .skip_hidden(false);
+        for dir_entry_result in walk_dir {
+            let dir_entry = match dir_entry_result {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            let file_type = dir_entry.file_type();
+            let removed = if file_type.is_symlink() {
+                remove_symlink(dir_entry.path(), &file_type)
+            } else if file_type.is_dir() {
+                fs::remove_dir(dir_entry.path())
+            } else {
+                fs::remove_file(dir_entry.path())
+            };
+            if let Err(err) = removed {
+                errors.push(Error::from_entry(&dir_entry, err));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Count the entries with [`count`] and then start the walk, returning
+    /// the count with the iterator so progress can be shown as `x / total`.
+    ///
+    /// This walks the tree twice. The first pass is the cheaper parallel
+    /// [`count`], but still reads every directory and calls callbacks such as
+    /// [`process_read_dir`] and [`filter_entry`]. The count is the number of
+    /// entries the iterator yields, not counting errors, as long as the tree
+    /// doesn't change between the passes and the callbacks decide the same
+    /// way both times.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root itself can't be read.
+    ///
+    /// [`count`]: struct.WalkDirGeneric.html#method.count
+    /// [`process_read_dir`]: struct.WalkDirGeneric.html#method.process_read_dir
+    /// [`filter_entry`]: struct.WalkDirGeneric.html#method.filter_entry
+    pub fn with_precount(self) -> Result<(usize, DirEntryIter<C>)> {
+        let count = self.clone().count()?;
+        Ok((count, self.into_iter()))
+    }
+}
+
+impl WalkStats {
+    fn of<C: ClientState>(dir_entry: &DirEntry<C>) -> WalkStats {
+        let mut stats = WalkStats::default();
+        if dir_entry.path_is_symlink() {
+            stats.symlinks += 1;
+        }
+        if dir_entry.read_children_error.is_some() {
+            stats.errors += 1;
+        }
+        if dir_entry.is_dir() {
+            stats.dirs += 1;
+        } else if dir_entry.is_file() {
+            stats.files += 1;
+            match dir_entry.metadata() {
+                Ok(metadata) => stats.total_bytes += metadata.len(),
+                Err(_) => stats.errors += 1,
+            }
+        }
+        stats
+    }
+
+    fn merge(self, other: WalkStats) -> WalkStats {
+        WalkStats {
+            total_bytes: self.total_bytes + other.total_bytes,
+            files: self.files + other.files,
+            dirs: self.dirs + other.dirs,
+            symlinks: self.symlinks + other.symlinks,
+            errors: self.errors + other.errors,
+        }
+    }
+}
+
+fn process_dir_entry_result<C: ClientState>(
+    dir_entry_result: Result<DirEntry<C>>,
+    follow_links: bool,
+) -> Result<DirEntry<C>> {
+    match dir_entry_result {
+        Ok(mut dir_entry) => {
+            if follow_links && dir_entry.file_type.is_symlink() {
+                dir_entry = dir_entry.follow_symlink()?;
+            }
+
+            if dir_entry.depth == 0 && dir_entry.file_type.is_symlink() {
+                // As a special case, if we are processing a root entry, then we
+                // always follow it even if it's a symlink and follow_links is
+                // false. We are careful to not let this change the semantics of
+                // the DirEntry however. Namely, the DirEntry should still
+                // respect the follow_links setting. When it's disabled, it
+                // should report itself as a symlink. When it's enabled, it
+                // should always report itself as the target.
+                let metadata = fs::metadata(dir_entry.path())
+                    .map_err(|err| Error::from_path(0, dir_entry.path().to_owned(), err))?;
+                if metadata.file_type().is_dir() {
+                    dir_entry.read_children_path = Some(Arc::from(dir_entry.path()));
+                }
+            }
+
+            Ok(dir_entry)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Entries of a directory as they are read.
+enum DirEntryResults<C: ClientState> {
+    // Read with `fs::read_dir`
+    ReadDir {
+        fs_read_dir: fs::ReadDir,
+        depth: usize,
+        path: Arc<Path>,
+        follow_link_ancestors: Arc<Vec<Ancestor>>,
+        prune_dirs: Option<Arc<PruneDirsFunction>>,
+        preload_metadata: bool,
+    },
+    // Read relative to the file descriptor `dir`
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    ReadDirAt {
+        names: DirNames,
+        dir: Arc<OwnedFd>,
+        depth: usize,
+        path: Arc<Path>,
+        prune_dirs: Option<Arc<PruneDirsFunction>>,
+        preload_metadata: bool,
+    },
+    // Returned by a `DirReader`
+    Read(std::vec::IntoIter<Result<DirEntry<C>>>),
+}
+
+impl<C: ClientState> Iterator for DirEntryResults<C> {
+    type Item = Result<DirEntry<C>>;
+
+    fn next(&mut self) -> Option<Result<DirEntry<C>>> {
+        match self {
+            DirEntryResults::ReadDir {
+                fs_read_dir,
+                depth,
+                path,
+                follow_link_ancestors,
+                prune_dirs,
+                preload_metadata,
+            } => loop {
+                let fs_dir_entry = match fs_read_dir.next()? {
+                    Ok(fs_dir_entry) => fs_dir_entry,
+                    Err(err) => {
+                        return Some(Err(Error::from_path(*depth + 1, path.to_path_buf(), err)))
+                    }
+                };
+                if let Some(prune_dirs) = prune_dirs.as_ref() {
+                    // The type comes with the listing on most platforms
+                    if prune_dirs(&fs_dir_entry.file_name())
+                        && fs_dir_entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+                    {
+                        continue;
+                    }
+                }
+                return Some(DirEntry::from_entry(
+                    *depth + 1,
+                    path.clone(),
+                    &fs_dir_entry,
+                    follow_link_ancestors.clone(),
+                    *preload_metadata,
+                ));
+            },
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            DirEntryResults::ReadDirAt {
+                names,
+                dir,
+                depth,
+                path,
+                prune_dirs,
+                preload_metadata,
+            } => loop {
+                let dir_name = match names.next()? {
+                    Ok(dir_name) => dir_name,
+                    Err(err) => {
+                        return Some(Err(Error::from_path(*depth + 1, path.to_path_buf(), err)))
+                    }
+                };
+                if prune_dirs.as_ref().is_some_and(|prune_dirs| prune_dirs(&dir_name.name)) {
+                    continue;
+                }
+                return Some(DirEntry::from_fd(
+                    *depth + 1,
+                    path.clone(),
+                    dir,
+                    dir_name,
+                    *preload_metadata,
+                ));
+            },
+            DirEntryResults::Read(dir_entry_results) => dir_entry_results.next(),
+        }
+    }
+}
+
+// Entries of the directory at `path`, read at `depth`. Directories matched
+// by `prune_dirs` are dropped before their entries are created. With
+// `preload_metadata` each entry's metadata is read as it is listed.
+fn read_dir<C: ClientState>(
+    depth: usize,
+    path: &Arc<Path>,
+    follow_link_ancestors: &Arc<Vec<Ancestor>>,
+    prune_dirs: Option<&Arc<PruneDirsFunction>>,
+    preload_metadata: bool,
+) -> Result<DirEntryResults<C>> {
+    let fs_read_dir =
+        fs::read_dir(path).map_err(|err| Error::from_path(depth, path.to_path_buf(), err))?;
+    Ok(DirEntryResults::ReadDir {
+        fs_read_dir,
+        depth,
+        path: path.clone(),
+        follow_link_ancestors: follow_link_ancestors.clone(),
+        prune_dirs: prune_dirs.cloned(),
+        preload_metadata,
+    })
+}
+
+// Entries of the directory opened by `open_at`, read at `depth`. `path` is
+// only used to name the entries.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_dir_at<C: ClientState>(
+    depth: usize,
+    path: &Arc<Path>,
+    open_at: &OpenAt,
+    prune_dirs: Option<&Arc<PruneDirsFunction>>,
+    preload_metadata: bool,
+) -> Result<DirEntryResults<C>> {
+    let dir = open_at.open().map_err(|err| Error::from_path(depth, path.to_path_buf(), err))?;
+    let names = DirNames::read_from(&dir)
+        .map_err(|err| Error::from_path(depth, path.to_path_buf(), err))?;
+    Ok(DirEntryResults::ReadDirAt {
+        names,
+        dir,
+        depth,
+        path: path.clone(),
+        prune_dirs: prune_dirs.cloned(),
+        preload_metadata,
+    })
+}
+
+// Keep the first `n` entries and every error, so that the limit never hides
+// an error.
+fn truncate_entries<C: ClientState>(n: usize, dir_entry_results: &mut Vec<Result<DirEntry<C>>>) {
+    let mut kept = 0;
+    dir_entry_results.retain(|dir_entry_result| {
+        if dir_entry_result.is_err() {
+            return true;
+        }
+        kept += 1;
+        kept <= n
+    });
+}
+
+// Directories at `max_recursion_depth` that would be read are replaced by an
+// error.
+fn check_recursion_depth<C: ClientState>(
+    max_recursion_depth: usize,
+    max_depth: usize,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for dir_entry_result in dir_entry_results.iter_mut() {
+        if let Ok(dir_entry) = dir_entry_result {
+            if dir_entry.read_children_path.is_some()
+                && dir_entry.depth >= max_recursion_depth
+                && dir_entry.depth < max_depth
+            {
+                *dir_entry_result = Err(Error::from_too_deep(dir_entry));
+            }
+        }
+    }
+}
+
+// Number the entries that are yielded in the order they are yielded.
+fn number_siblings<C: ClientState>(dir_entry_results: &mut [Result<DirEntry<C>>]) {
+    let mut siblings: Vec<_> =
+        dir_entry_results.iter_mut().flatten().filter(|dir_entry| dir_entry.yield_entry).collect();
+    let sibling_count = siblings.len();
+    for (sibling_index, dir_entry) in siblings.iter_mut().enumerate() {
+        dir_entry.sibling_index = sibling_index;
+        dir_entry.last_sibling = sibling_index + 1 == sibling_count;
+    }
+}
+
+// Directories at `max_depth` are never read, so no `ReadDirSpec` is created
+// for them.
+fn mark_contents_unread<C: ClientState>(
+    max_depth: usize,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for dir_entry in dir_entry_results.iter_mut().flatten() {
+        if dir_entry.depth >= max_depth {
+            dir_entry.contents_unread = true;
+        }
+    }
+}
+
+fn skip_other_file_systems<C: ClientState>(
+    parallel: bool,
+    device: &DeviceId,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for_each_dir_entry(parallel, dir_entry_results, |dir_entry| {
+        if let Some(read_children_path) = dir_entry.read_children_path.as_ref() {
+            if let Ok(read_children_device) = DeviceId::of(read_children_path) {
+                if read_children_device != *device {
+                    dir_entry.read_children_path = None;
+                }
+            }
+        }
+    });
+}
+
+fn retain_extensions<C: ClientState>(
+    extensions: &[String],
+    normalize_name: Option<NormalizeFunction>,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    dir_entry_results.retain(|dir_entry_result| match dir_entry_result {
+        Ok(dir_entry) if dir_entry.file_type.is_dir() => true,
+        Ok(dir_entry) => {
+            let extension =
+                dir_entry.path.extension().map(|extension| normalized(normalize_name, extension));
+            extension.is_some_and(|extension| {
+                let extension = extension.as_encoded_bytes();
+                extensions.iter().any(|each| each.as_bytes().eq_ignore_ascii_case(extension))
+            })
+        }
+        Err(_) => true,
+    });
+}
+
+// Keep directories and the files for which `keep` returns `true`. Files
+// whose metadata can't be read are replaced by the error.
+fn retain_files_by_metadata<C, F>(
+    parallel: bool,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+    keep: F,
+) where
+    C: ClientState,
+    F: Fn(&DirEntry<C>, &fs::Metadata) -> Result<bool> + Send + Sync,
+{
+    retain_dir_entry_results(parallel, dir_entry_results, |dir_entry_result| {
+        let dir_entry = match dir_entry_result {
+            Ok(dir_entry) if !dir_entry.file_type.is_dir() => dir_entry,
+            _ => return true,
+        };
+        match dir_entry.metadata().and_then(|metadata| keep(dir_entry, &metadata)) {
+            Ok(keep) => keep,
+            Err(err) => {
+                *dir_entry_result = Err(err);
+                true
+            }
+        }
+    });
+}
+
+fn retain_modified<C: ClientState>(
+    parallel: bool,
+    after: Option<SystemTime>,
+    before: Option<SystemTime>,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    retain_files_by_metadata(parallel, dir_entry_results, |dir_entry, metadata| {
+        let modified = metadata.modified().map_err(|err| Error::from_entry(dir_entry, err))?;
+        Ok(after.map_or(true, |after| modified > after)
+            && before.map_or(true, |before| modified < before))
+    });
+}
+
+fn retain_file_size<C: ClientState>(
+    parallel: bool,
+    min: Option<u64>,
+    max: Option<u64>,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    retain_files_by_metadata(parallel, dir_entry_results, |_, metadata| {
+        Ok(min.map_or(true, |min| metadata.len() >= min)
+            && max.map_or(true, |max| metadata.len() <= max))
+    });
+}
+
+fn retain_globs<C: ClientState>(
+    include: Option<&[Glob]>,
+    exclude: Option<&[Glob]>,
+    normalize_name: Option<NormalizeFunction>,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    dir_entry_results.retain(|dir_entry_result| match dir_entry_result {
+        Ok(dir_entry) => {
+            let path = normalized(normalize_name, dir_entry.relative_path().as_os_str());
+            let path = Path::new(&path);
+            if exclude.is_some_and(|exclude| exclude.iter().any(|glob| glob.is_match(path))) {
+                return false;
+            }
+            dir_entry.file_type.is_dir()
+                || include.map_or(true, |include| include.iter().any(|glob| glob.is_match(path)))
+        }
+        Err(_) => true,
+    });
+}
+
+// Sockets, fifos, devices and other files that aren't regular files,
+// directories or symlinks.
+#[cfg(unix)]
+fn is_special_file<C: ClientState>(dir_entry_result: &Result<DirEntry<C>>) -> bool {
+    dir_entry_result.as_ref().is_ok_and(|dir_entry| {
+        let file_type = dir_entry.file_type;
+        !file_type.is_file() && !file_type.is_dir() && !file_type.is_symlink()
+    })
+}
+
+// Remove the symbolic link at `path`, not what it points to.
+fn remove_symlink(path: &Path, file_type: &fs::FileType) -> io::Result<()> {
+    #[cfg(windows)]
+    if std::os::windows::fs::FileTypeExt::is_symlink_dir(file_type) {
+        return fs::remove_dir(path);
+    }
+    #[cfg(not(windows))]
+    let _ = file_type;
+    fs::remove_file(path)
+}
+
+// Remove redundant separators and `.` components so that entry paths don't
+// depend on how the root was written. A trailing separator makes a symlink
+// resolve to the directory it points to, so symlinks keep a single one.
+fn normalize_root(root: &Path) -> PathBuf {
+    let normalized: PathBuf = root.components().collect();
+    if normalized.as_os_str() != root.as_os_str()
+        && fs::symlink_metadata(&normalized).is_ok_and(|metadata| metadata.file_type().is_symlink())
+    {
+        return normalized.join("");
+    }
+    normalized
+}
+
+fn apply_file_type_filter<C: ClientState>(
+    file_type_filter: FileTypeFilter,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for dir_entry in dir_entry_results.iter_mut().flatten() {
+        dir_entry.yield_entry &= match file_type_filter {
+            FileTypeFilter::All => true,
+            FileTypeFilter::Files => dir_entry.file_type.is_file(),
+            FileTypeFilter::Dirs => dir_entry.file_type.is_dir(),
+        };
+    }
+}
+
+#[cfg(jwalk_xattr)]
+fn read_dir_entry_xattrs<C: ClientState>(
+    parallel: bool,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for_each_dir_entry(parallel, dir_entry_results, DirEntry::read_xattrs);
+}
+
+fn hash_dir_entry_results<C: ClientState>(
+    parallel: bool,
+    hash_with: &HashFunction,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for_each_dir_entry(parallel, dir_entry_results, |dir_entry| {
+        if dir_entry.file_type.is_file() {
+            let hash = hash_with(&dir_entry.path);
+            dir_entry.set_hash(hash);
+        }
+    });
+}
+
+fn set_client_states<C: ClientState>(
+    parallel: bool,
+    client_state_with: &ClientStateFunction<C>,
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+) {
+    for_each_dir_entry(parallel, dir_entry_results, |dir_entry| {
+        dir_entry.client_state = client_state_with(dir_entry);
+    });
+}
+
+fn filter_dir_entry_results<C: ClientState>(
+    parallel: bool,
+    filter_entry: &FilterEntryFunction<C>,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    retain_dir_entry_results(parallel, dir_entry_results, |dir_entry_result| {
+        match dir_entry_result {
+            Ok(dir_entry) => filter_entry(dir_entry),
+            Err(_) => true,
+        }
+    });
+}
+
+// Call `f` on each entry, on the current thread pool when `parallel` is set.
+fn for_each_dir_entry<C, F>(parallel: bool, dir_entry_results: &mut [Result<DirEntry<C>>], f: F)
+where
+    C: ClientState,
+    F: Fn(&mut DirEntry<C>) + Send + Sync,
+{
+    if parallel {
+        dir_entry_results.par_iter_mut().flatten().for_each(f);
+    } else {
+        dir_entry_results.iter_mut().flatten().for_each(f);
+    }
+}
+
+// Like `Vec::retain_mut`, with `keep` called on the current thread pool when
+// `parallel` is set. Order is preserved either way.
+fn retain_dir_entry_results<C, F>(
+    parallel: bool,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+    keep: F,
+) where
+    C: ClientState,
+    F: Fn(&mut Result<DirEntry<C>>) -> bool + Send + Sync,
+{
+    if parallel {
+        let keep: Vec<bool> = dir_entry_results.par_iter_mut().map(keep).collect();
+        let mut keep = keep.into_iter();
+        dir_entry_results.retain(|_| keep.next().unwrap_or(true));
+    } else {
+        dir_entry_results.retain_mut(keep);
+    }
+}
+
+fn apply_entry_actions<C: ClientState>(
+    parallel: bool,
+    filter_map_entry: &FilterMapEntryFunction<C>,
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+) {
+    retain_dir_entry_results(parallel, dir_entry_results, |dir_entry_result| {
+        match dir_entry_result {
+            Ok(dir_entry) => match filter_map_entry(dir_entry) {
+                Action::Keep => true,
+                Action::Skip => {
+                    dir_entry.yield_entry = false;
+                    true
+                }
+                Action::SkipSubtree => false,
+                Action::SkipContents => {
+                    dir_entry.read_children_path = None;
+                    true
+                }
+            },
+            Err(_) => true,
+        }
+    });
+}
+
+impl<C: ClientState> IntoIterator for WalkDirGeneric<C> {
+    type IntoIter = DirEntryIter<C>;
+    type Item = Result<DirEntry<C>>;
 
     fn into_iter(self) -> DirEntryIter<C> {
+        self.into_iter_with_capacity(0)
+    }
+}
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    /// Like `into_iter`, with a hint for how many read directories the walk's
+    /// queues should have room for up front.
+    ///
+    /// Directories that are read out of order, and with
+    /// `Parallelism::Serial` directories waiting to be read, are held in
+    /// buffers that otherwise start empty and grow as needed. Presizing them
+    /// avoids reallocating on trees with many directories. The hint doesn't
+    /// limit the walk, see
+    /// [`max_queued_entries`](struct.WalkDirGeneric.html#method.max_queued_entries)
+    /// for that.
+    pub fn into_iter_with_capacity(self, capacity: usize) -> DirEntryIter<C> {
+        let parallelism = self.options.parallelism.clone();
+        let max_queued_entries = self.options.max_queued_entries;
+        let traversal_order = self.options.traversal_order;
+        let unordered_first = self.options.unordered_first;
+        let background = self.options.background;
+        let min_depth = self.options.min_depth;
+        let contents_first = self.options.contents_first;
+        let skip_empty_dirs = self.options.skip_empty_dirs;
+        let first_n = self.options.first_n;
+        let abort_on_error = self.options.on_error == ErrorHandling::Abort;
+        let (root_entry_results, root_read_dir_state, core_read_dir_callback) = self.into_parts();
+        DirEntryIter::new(
+            root_entry_results,
+            parallelism,
+            max_queued_entries,
+            traversal_order,
+            unordered_first,
+            background,
+            capacity,
+            min_depth,
+            contents_first,
+            skip_empty_dirs,
+            first_n,
+            abort_on_error,
+            root_read_dir_state,
+            core_read_dir_callback,
+        )
+    }
+}
+
+type WalkParts<C> =
+    (Vec<Result<DirEntry<C>>>, <C as ClientState>::ReadDirState, Arc<ReadDirCallback<C>>);
+
+impl<C: ClientState> WalkDirGeneric<C> {
+    // Root entry results, root read dir state and the callback that reads
+    // every other directory. Shared by `into_iter` and `into_par_iter`.
+    fn into_parts(self) -> WalkParts<C> {
         let sort = self.options.sort;
+        let sort_by = self.options.sort_by.clone();
+        let sort_by_key = self.options.sort_by_key.clone();
+        let sort_reverse = self.options.sort_reverse;
+        let sort_errors_last = self.options.sort_errors_last;
         let max_depth = self.options.max_depth;
-        let min_depth = self.options.min_depth;
-        let parallelism = self.options.parallelism;
+        let max_recursion_depth = self.options.max_recursion_depth;
+        let max_entries_per_dir = self.options.max_entries_per_dir;
+        let include_root = self.options.include_root;
+        let cancellation = self.options.cancellation.clone();
+        let deadline = self.options.time_budget.map(|time_budget| Instant::now() + time_budget);
+        let read_dir_batch_size = self.options.read_dir_batch_size.max(1);
+        // Serial walks don't use a thread pool, and with a queue limit a
+        // thread splitting work could steal a read that blocks on the limit
+        let par_entries_threshold = match self.options.parallelism {
+            Parallelism::Serial => usize::MAX,
+            _ if self.options.max_queued_entries != usize::MAX => usize::MAX,
+            _ => self.options.par_entries_threshold,
+        };
+        let detect_changes = self.options.detect_changes;
+        let read_limit = (self.options.max_concurrent_reads != usize::MAX)
+            .then(|| Semaphore::new(self.options.max_concurrent_reads.max(1)));
         let skip_hidden = self.options.skip_hidden;
+        let hidden_predicate = self.options.hidden_predicate.clone();
+        #[cfg(unix)]
+        let skip_special_files = self.options.skip_special_files;
         let follow_links = self.options.follow_links;
+        let follow_root_links = self.options.follow_root_links;
+        let absolute = self.options.absolute;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let follow_links = follow_links && self.root_dir.is_none();
+        let respect_gitignore = self.options.respect_gitignore;
+        let same_file_system = self.options.same_file_system;
+        let skip_dirs = self.options.skip_dirs.clone();
+        let extensions = self.options.extensions.clone();
+        let modified_after = self.options.modified_after;
+        let modified_before = self.options.modified_before;
+        let min_file_size = self.options.min_file_size;
+        let max_file_size = self.options.max_file_size;
+        #[cfg(feature = "unicode")]
+        let normalize_name =
+            self.options.normalize_names.map(NormalizationForm::normalize_function);
+        #[cfg(not(feature = "unicode"))]
+        let normalize_name: Option<NormalizeFunction> = None;
+        let skip_dirs = skip_dirs.map(|skip_dirs| match normalize_name {
+            Some(normalize_name) => {
+                Arc::new(skip_dirs.iter().map(|name| normalize_name(name).into_owned()).collect())
+            }
+            None => skip_dirs,
+        });
+        let extensions = extensions.map(|extensions| match normalize_name {
+            Some(normalize_name) => Arc::new(
+                extensions
+                    .iter()
+                    .map(|extension| {
+                        normalize_name(OsStr::new(extension)).to_string_lossy().into_owned()
+                    })
+                    .collect(),
+            ),
+            None => extensions,
+        });
+        let compile_globs = |patterns: &Option<Arc<Vec<String>>>| {
+            patterns.as_ref().map(|patterns| {
+                let globs = patterns
+                    .iter()
+                    .map(|pattern| {
+                        Glob::new(
+                            &normalized(normalize_name, OsStr::new(pattern)).to_string_lossy(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                Arc::new(globs)
+            })
+        };
+        let glob_include = compile_globs(&self.options.glob_include);
+        let glob_exclude = compile_globs(&self.options.glob_exclude);
+        #[cfg(feature = "regex")]
+        let path_regex_exclude = self.options.path_regex_exclude.clone();
+        let filter_entry = self.options.filter_entry.clone();
+        let filter_map_entry = self.options.filter_map_entry.clone();
+        let file_type_filter = self.options.file_type_filter;
+        #[cfg(jwalk_xattr)]
+        let read_xattrs = self.options.read_xattrs;
+        let preload_metadata = self.options.preload_metadata;
+        #[cfg(windows)]
+        let windows_long_paths = self.options.windows_long_paths;
+        let skip_errors = self.options.on_error == ErrorHandling::Skip;
         let process_read_dir = self.options.process_read_dir.clone();
+        let on_read_dir = self.options.on_read_dir.clone();
+        let hash_with = self.options.hash_with.clone();
+        let client_state_with = self.options.client_state_with.clone();
+        let dir_reader = self.options.dir_reader.clone();
+        let prune_dirs = self.options.prune_dirs.clone();
         let mut root_read_dir_state = self.options.root_read_dir_state;
-        let follow_link_ancestors = if follow_links {
-            Arc::new(vec![Arc::from(self.root.clone()) as Arc<Path>])
-        } else {
-            Arc::new(vec![])
-        };
-
-        let root_entry = DirEntry::from_path(0, &self.root, false, follow_link_ancestors);
-        let root_parent_path =
-            root_entry.as_ref().map(|root| root.parent_path().to_owned()).unwrap_or_default();
-        let mut root_entry_results = vec![process_dir_entry_result(root_entry, follow_links)];
-        if let Some(process_read_dir) = process_read_dir.as_ref() {
-            process_read_dir(
-                None,
-                &root_parent_path,
-                &mut root_read_dir_state,
-                &mut root_entry_results,
-            );
+        let mut root_entry_results = Vec::with_capacity(self.roots.len());
+        for (index, root) in self.roots.iter().enumerate() {
+            let depth = self.root_depths.get(index).copied().unwrap_or(0);
+            let root_index = self.root_indices.get(index).copied().unwrap_or(index);
+            let (yield_root, read_root_children) =
+                self.root_actions.get(index).copied().unwrap_or((true, true));
+            let root = if absolute {
+                fs::canonicalize(root).map_err(|err| Error::from_path(depth, root.clone(), err))
+            } else {
+                Ok(root.clone())
+            };
+            #[cfg(windows)]
+            let root =
+                root.map(|root| if windows_long_paths { verbatim_path(&root) } else { root });
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            let root_dir = self.root_dir.as_ref();
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            let root_entry = root.and_then(|root| match root_dir {
+                Some(root_dir) => DirEntry::from_root_fd(&root, root_dir),
+                None => DirEntry::from_path(depth, &root, false, Arc::new(Vec::new())),
+            });
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            let root_entry = root
+                .and_then(|root| DirEntry::from_path(depth, &root, false, Arc::new(Vec::new())));
+            let root_entry = root_entry.map(|mut root_entry| {
+                root_entry.root_index = root_index;
+                root_entry
+            });
+            let root_parent_path =
+                root_entry.as_ref().map(|root| root.parent_path().to_owned()).unwrap_or_default();
+            let mut root_entry_result = process_dir_entry_result(root_entry, follow_links);
+            if let Ok(root_entry) = root_entry_result.as_mut() {
+                if !follow_root_links && root_entry.file_type.is_symlink() {
+                    root_entry.read_children_path = None;
+                }
+                // A resumed entry keeps what the original walk decided for it
+                root_entry.yield_entry = yield_root;
+                if !read_root_children {
+                    root_entry.read_children_path = None;
+                }
+            }
+            let mut dir_entry_results = vec![root_entry_result];
+            if skip_errors {
+                dir_entry_results.retain(Result::is_ok);
+            }
+            #[cfg(unix)]
+            if skip_special_files {
+                dir_entry_results.retain(|dir_entry_result| !is_special_file(dir_entry_result));
+            }
+            if let Some(extensions) = extensions.as_ref() {
+                retain_extensions(extensions, normalize_name, &mut dir_entry_results);
+            }
+            if let Some(filter_entry) = filter_entry.as_ref() {
+                filter_dir_entry_results(false, filter_entry.as_ref(), &mut dir_entry_results);
+            }
+            if let Some(filter_map_entry) = filter_map_entry.as_ref() {
+                apply_entry_actions(false, filter_map_entry.as_ref(), &mut dir_entry_results);
+            }
+            if modified_after.is_some() || modified_before.is_some() {
+                retain_modified(false, modified_after, modified_before, &mut dir_entry_results);
+            }
+            if min_file_size.is_some() || max_file_size.is_some() {
+                retain_file_size(false, min_file_size, max_file_size, &mut dir_entry_results);
+            }
+            #[cfg(jwalk_xattr)]
+            if read_xattrs {
+                read_dir_entry_xattrs(false, &mut dir_entry_results);
+            }
+            if let Some(hash_with) = hash_with.as_ref() {
+                hash_dir_entry_results(false, hash_with.as_ref(), &mut dir_entry_results);
+            }
+            if let Some(client_state_with) = client_state_with.as_ref() {
+                set_client_states(false, client_state_with.as_ref(), &mut dir_entry_results);
+            }
+            if let Some(process_read_dir) = process_read_dir.as_ref() {
+                process_read_dir(
+                    None,
+                    &root_parent_path,
+                    &mut root_read_dir_state,
+                    &mut dir_entry_results,
+                );
+            }
+            if max_recursion_depth < max_depth {
+                check_recursion_depth(max_recursion_depth, max_depth, &mut dir_entry_results);
+                if skip_errors {
+                    dir_entry_results.retain(Result::is_ok);
+                }
+            }
+            mark_contents_unread(max_depth, &mut dir_entry_results);
+            if file_type_filter != FileTypeFilter::All {
+                apply_file_type_filter(file_type_filter, &mut dir_entry_results);
+            }
+            if !include_root && depth == 0 {
+                for dir_entry in dir_entry_results.iter_mut().flatten() {
+                    dir_entry.yield_entry = false;
+                }
+            }
+            root_entry_results.append(&mut dir_entry_results);
         }
+        number_siblings(&mut root_entry_results);
 
-        DirEntryIter::new(
-            root_entry_results,
-            parallelism,
-            min_depth,
-            root_read_dir_state.clone(),
-            Arc::new(move |read_dir_spec| {
-                let ReadDirSpec { path, depth, mut client_read_state, mut follow_link_ancestors } =
-                    read_dir_spec;
+        let core_read_dir_callback: Arc<ReadDirCallback<C>> = Arc::new(move |read_dir_spec| {
+            let ReadDirSpec {
+                path,
+                depth,
+                mut client_read_state,
+                mut follow_link_ancestors,
+                gitignore,
+                root_index,
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                open_at,
+            } = read_dir_spec;
 
-                let read_dir_depth = depth;
-                let read_dir_contents_depth = depth + 1;
+            let read_dir_depth = depth;
+            let read_dir_contents_depth = depth + 1;
 
-                if read_dir_contents_depth > max_depth {
-                    return Ok(ReadDir::new(client_read_state, Vec::new()));
+            if read_dir_contents_depth > max_depth
+                || cancellation
+                    .as_ref()
+                    .is_some_and(|cancellation| cancellation.load(AtomicOrdering::Relaxed))
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Ok(ReadDir::unread(client_read_state));
+            }
+
+            follow_link_ancestors = if follow_links {
+                let ancestor = Ancestor::new(path.clone())
+                    .map_err(|err| Error::from_path(read_dir_depth, path.to_path_buf(), err))?;
+                let mut ancestors = Vec::with_capacity(follow_link_ancestors.len() + 1);
+                ancestors.extend(follow_link_ancestors.iter().cloned());
+                ancestors.push(ancestor);
+                Arc::new(ancestors)
+            } else {
+                follow_link_ancestors
+            };
+
+            let read_permit = read_limit.as_ref().map(Semaphore::acquire);
+            let modified_time =
+                || fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+            // A reader's directories need not exist on disk
+            let modified_before_read =
+                (detect_changes && dir_reader.is_none()).then(modified_time);
+            let mut read_dir: DirEntryResults<C> = match dir_reader.as_ref() {
+                Some(dir_reader) => {
+                    let read_dir_spec = ReadDirSpec {
+                        depth: read_dir_depth,
+                        path: path.clone(),
+                        client_read_state: client_read_state.clone(),
+                        follow_link_ancestors: follow_link_ancestors.clone(),
+                        gitignore: gitignore.clone(),
+                        root_index,
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        open_at,
+                    };
+                    let mut dir_entry_results = dir_reader.read_dir(&read_dir_spec)?;
+                    if let Some(prune_dirs) = prune_dirs.as_ref() {
+                        dir_entry_results.retain(|dir_entry_result| match dir_entry_result {
+                            Ok(dir_entry) => {
+                                !(dir_entry.file_type.is_dir() && prune_dirs(&dir_entry.file_name))
+                            }
+                            Err(_) => true,
+                        });
+                    }
+                    DirEntryResults::Read(dir_entry_results.into_iter())
                 }
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                None => match open_at {
+                    Some(open_at) => read_dir_at(
+                        read_dir_depth,
+                        &path,
+                        &open_at,
+                        prune_dirs.as_ref(),
+                        preload_metadata,
+                    )?,
+                    None => read_dir(
+                        read_dir_depth,
+                        &path,
+                        &follow_link_ancestors,
+                        prune_dirs.as_ref(),
+                        preload_metadata,
+                    )?,
+                },
+                #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                None => read_dir(
+                    read_dir_depth,
+                    &path,
+                    &follow_link_ancestors,
+                    prune_dirs.as_ref(),
+                    preload_metadata,
+                )?,
+            };
 
-                follow_link_ancestors = if follow_links {
-                    let mut ancestors = Vec::with_capacity(follow_link_ancestors.len() + 1);
-                    ancestors.extend(follow_link_ancestors.iter().cloned());
-                    ancestors.push(path.clone());
-                    Arc::new(ancestors)
-                } else {
-                    follow_link_ancestors
-                };
+            let gitignore =
+                if respect_gitignore { Gitignore::read(&path, gitignore) } else { None };
+            // Directories on other file systems are never read, so the
+            // directory being read is always on the root's file system
+            let device = if same_file_system { DeviceId::of(&path).ok() } else { None };
 
-                let mut dir_entry_results: Vec<_> = fs::read_dir(path.as_ref())
-                    .map_err(|err| Error::from_path(0, path.to_path_buf(), err))?
-                    .filter_map(|dir_entry_result| {
-                        let fs_dir_entry = match dir_entry_result {
-                            Ok(fs_dir_entry) => fs_dir_entry,
-                            Err(err) =>
-                                return Some(Err(Error::from_io(read_dir_contents_depth, err))),
-                        };
+            // Filters that don't depend on other entries are applied to each
+            // batch, so that filtered entries don't accumulate
+            let filter_batch = |dir_entry_results: &mut Vec<Result<DirEntry<C>>>| {
+                // A thread holding a read permit could pick up another read
+                // while waiting for its entries and block on the permit
+                let parallel =
+                    read_limit.is_none() && dir_entry_results.len() >= par_entries_threshold;
+
+                if skip_errors {
+                    dir_entry_results.retain(Result::is_ok);
+                }
+
+                if let Some(gitignore) = gitignore.as_ref() {
+                    dir_entry_results.retain(|dir_entry_result| match dir_entry_result {
+                        Ok(dir_entry) => {
+                            !gitignore.is_ignored(&dir_entry.path, dir_entry.file_type.is_dir())
+                        }
+                        Err(_) => true,
+                    });
+                    for dir_entry in dir_entry_results.iter_mut().flatten() {
+                        dir_entry.gitignore = Some(gitignore.clone());
+                    }
+                }
+
+                if let Some(skip_dirs) = skip_dirs.as_ref() {
+                    for dir_entry in dir_entry_results.iter_mut().flatten() {
+                        if skip_dirs.contains(&*normalized(normalize_name, &dir_entry.file_name)) {
+                            dir_entry.read_children_path = None;
+                        }
+                    }
+                }
+
+                if let Some(extensions) = extensions.as_ref() {
+                    retain_extensions(extensions, normalize_name, dir_entry_results);
+                }
+
+                if glob_include.is_some() || glob_exclude.is_some() {
+                    retain_globs(
+                        glob_include.as_deref().map(Vec::as_slice),
+                        glob_exclude.as_deref().map(Vec::as_slice),
+                        normalize_name,
+                        dir_entry_results,
+                    );
+                }
+
+                #[cfg(feature = "regex")]
+                if let Some(path_regex_exclude) = path_regex_exclude.as_ref() {
+                    dir_entry_results.retain(|dir_entry_result| match dir_entry_result {
+                        Ok(dir_entry) => !path_regex_exclude
+                            .is_match(&dir_entry.relative_path().to_string_lossy()),
+                        Err(_) => true,
+                    });
+                }
+
+                if let Some(filter_entry) = filter_entry.as_ref() {
+                    filter_dir_entry_results(parallel, filter_entry.as_ref(), dir_entry_results);
+                }
+
+                if let Some(filter_map_entry) = filter_map_entry.as_ref() {
+                    apply_entry_actions(parallel, filter_map_entry.as_ref(), dir_entry_results);
+                }
+
+                // Filters that read metadata run last, so entries already
+                // rejected by name are never stat'd
+                if let Some(device) = device.as_ref() {
+                    skip_other_file_systems(parallel, device, dir_entry_results);
+                }
+
+                if modified_after.is_some() || modified_before.is_some() {
+                    retain_modified(parallel, modified_after, modified_before, dir_entry_results);
+                }
 
-                        let dir_entry = match DirEntry::from_entry(
-                            read_dir_contents_depth,
-                            path.clone(),
-                            &fs_dir_entry,
-                            follow_link_ancestors.clone(),
-                        ) {
+                if min_file_size.is_some() || max_file_size.is_some() {
+                    retain_file_size(parallel, min_file_size, max_file_size, dir_entry_results);
+                }
+            };
+
+            let mut dir_entry_results = Vec::new();
+            let mut read_count = 0;
+            loop {
+                let mut batch_read_count = 0;
+                let mut batch: Vec<_> = read_dir
+                    .by_ref()
+                    .take(read_dir_batch_size)
+                    .inspect(|_| batch_read_count += 1)
+                    .filter_map(|dir_entry_result| {
+                        let mut dir_entry = match dir_entry_result {
                             Ok(dir_entry) => dir_entry,
                             Err(err) => return Some(Err(err)),
                         };
+                        dir_entry.root_index = root_index;
+
+                        if skip_hidden {
+                            let hidden = match hidden_predicate.as_ref() {
+                                Some(hidden_predicate) => hidden_predicate(&dir_entry),
+                                None => is_hidden(&dir_entry),
+                            };
+                            if hidden {
+                                return None;
+                            }
+                        }
+
+                        let dir_entry_result =
+                            process_dir_entry_result(Ok(dir_entry), follow_links);
 
-                        if skip_hidden && is_hidden(&dir_entry.file_name) {
+                        #[cfg(unix)]
+                        if skip_special_files && is_special_file(&dir_entry_result) {
                             return None;
                         }
 
-                        Some(process_dir_entry_result(Ok(dir_entry), follow_links))
+                        Some(dir_entry_result)
                     })
                     .collect();
 
-                if sort {
-                    dir_entry_results.sort_by(|a, b| match (a, b) {
-                        (Ok(a), Ok(b)) => a.file_name.cmp(&b.file_name),
-                        (Ok(_), Err(_)) => Ordering::Less,
-                        (Err(_), Ok(_)) => Ordering::Greater,
-                        (Err(_), Err(_)) => Ordering::Equal,
-                    });
+                read_count += batch.len();
+                filter_batch(&mut batch);
+                dir_entry_results.append(&mut batch);
+
+                if batch_read_count < read_dir_batch_size {
+                    break;
                 }
+            }
+            if let Some(modified_before_read) = modified_before_read {
+                // Also flagged when the modification time can't be read
+                if modified_before_read.is_none() || modified_before_read != modified_time() {
+                    for dir_entry in dir_entry_results.iter_mut().flatten() {
+                        dir_entry.possibly_stale = true;
+                    }
+                }
+            }
+            drop(read_permit);
 
-                if let Some(process_read_dir) = process_read_dir.as_ref() {
-                    process_read_dir(
-                        Some(read_dir_depth),
-                        path.as_ref(),
-                        &mut client_read_state,
-                        &mut dir_entry_results,
-                    );
+            if let Some(on_read_dir) = on_read_dir.as_ref() {
+                on_read_dir(&path, read_count);
+            }
+
+            let parallel = dir_entry_results.len() >= par_entries_threshold;
+            if parallel && sort == Some(Sort::Size) {
+                // Cached for the comparisons
+                for_each_dir_entry(parallel, &mut dir_entry_results, |dir_entry| {
+                    let _ = dir_entry.metadata();
+                });
+            }
+
+            if let Some(sort_by) = sort_by.as_ref() {
+                sort_dir_entry_results_by(&mut dir_entry_results, |a, b| {
+                    let ordering = sort_by(a, b);
+                    if sort_reverse {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                });
+            } else if let Some(sort_by_key) = sort_by_key.as_ref() {
+                sort_by_key(sort_reverse, &mut dir_entry_results);
+            } else if let Some(sort) = sort {
+                let errors_dir = if sort_errors_last { None } else { Some(path.as_ref()) };
+                sort.perform_sort(sort_reverse, errors_dir, &mut dir_entry_results);
+            }
+
+            truncate_entries(max_entries_per_dir, &mut dir_entry_results);
+
+            #[cfg(jwalk_xattr)]
+            if read_xattrs {
+                read_dir_entry_xattrs(parallel, &mut dir_entry_results);
+            }
+
+            if let Some(hash_with) = hash_with.as_ref() {
+                hash_dir_entry_results(parallel, hash_with.as_ref(), &mut dir_entry_results);
+            }
+
+            if let Some(client_state_with) = client_state_with.as_ref() {
+                set_client_states(parallel, client_state_with.as_ref(), &mut dir_entry_results);
+            }
+
+            if let Some(process_read_dir) = process_read_dir.as_ref() {
+                process_read_dir(
+                    Some(read_dir_depth),
+                    path.as_ref(),
+                    &mut client_read_state,
+                    &mut dir_entry_results,
+                );
+            }
+
+            if max_recursion_depth < max_depth {
+                check_recursion_depth(max_recursion_depth, max_depth, &mut dir_entry_results);
+                if skip_errors {
+                    dir_entry_results.retain(Result::is_ok);
                 }
+            }
 
-                Ok(ReadDir::new(client_read_state, dir_entry_results))
-            }),
-        )
+            mark_contents_unread(max_depth, &mut dir_entry_results);
+
+            if file_type_filter != FileTypeFilter::All {
+                apply_file_type_filter(file_type_filter, &mut dir_entry_results);
+            }
+
+            number_siblings(&mut dir_entry_results);
+
+            Ok(ReadDir::new(client_read_state, dir_entry_results))
+        });
+
+        // Directories that can't be read are yielded as if they weren't read
+        let core_read_dir_callback: Arc<ReadDirCallback<C>> = if skip_errors {
+            Arc::new(move |read_dir_spec: ReadDirSpec<C>| {
+                let client_read_state = read_dir_spec.client_read_state.clone();
+                core_read_dir_callback(read_dir_spec)
+                    .or_else(|_| Ok(ReadDir::unread(client_read_state)))
+            })
+        } else {
+            core_read_dir_callback
+        };
+
+        (root_entry_results, root_read_dir_state, core_read_dir_callback)
+    }
+}
+
+impl<C: ClientState> Clone for WalkDirGeneric<C> {
+    fn clone(&self) -> WalkDirGeneric<C> {
+        WalkDirGeneric {
+            roots: self.roots.clone(),
+            root_depths: self.root_depths.clone(),
+            root_indices: self.root_indices.clone(),
+            root_actions: self.root_actions.clone(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            root_dir: self.root_dir.clone(),
+            options: self.options.clone(),
+        }
+    }
+}
+
+impl<C: ClientState> WalkOptionsGeneric<C> {
+    /// Create default options, the same as those of
+    /// [`WalkDirGeneric::new`](struct.WalkDirGeneric.html#method.new).
+    pub fn new() -> Self {
+        WalkDirGeneric::from_roots(std::iter::empty::<PathBuf>()).options()
+    }
+
+    /// Set options with the `WalkDirGeneric` builder methods.
+    pub fn configure<F>(self, configure: F) -> Self
+    where
+        F: FnOnce(WalkDirGeneric<C>) -> WalkDirGeneric<C>,
+    {
+        let mut walk_dir = WalkDirGeneric::from_roots(std::iter::empty::<PathBuf>());
+        walk_dir.options = self.options;
+        configure(walk_dir).options()
+    }
+
+    /// Create a builder for a walk of `root` with these options. Same as
+    /// [`walk_dir`](fn.walk_dir.html).
+    pub fn walk_dir<P: AsRef<Path>>(&self, root: P) -> WalkDirGeneric<C> {
+        let mut walk_dir = WalkDirGeneric::new(root);
+        walk_dir.options = self.options.clone();
+        walk_dir
+    }
+}
+
+impl<C: ClientState> Default for WalkOptionsGeneric<C> {
+    fn default() -> Self {
+        WalkOptionsGeneric::new()
+    }
+}
+
+impl<C: ClientState> Clone for WalkOptionsGeneric<C> {
+    fn clone(&self) -> WalkOptionsGeneric<C> {
+        WalkOptionsGeneric { options: self.options.clone() }
     }
 }
 
+/// Create a builder for a walk of `root` with the given options, see
+/// [`WalkOptionsGeneric`](struct.WalkOptionsGeneric.html).
+pub fn walk_dir<C: ClientState, P: AsRef<Path>>(
+    options: &WalkOptionsGeneric<C>,
+    root: P,
+) -> WalkDirGeneric<C> {
+    options.walk_dir(root)
+}
+
 impl<C: ClientState> Clone for WalkDirOptions<C> {
     fn clone(&self) -> WalkDirOptions<C> {
         WalkDirOptions {
-            sort: false,
+            sort: self.sort,
+            sort_by: self.sort_by.clone(),
+            sort_by_key: self.sort_by_key.clone(),
+            sort_reverse: self.sort_reverse,
+            sort_errors_last: self.sort_errors_last,
+            include_root: self.include_root,
             min_depth: self.min_depth,
             max_depth: self.max_depth,
+            max_recursion_depth: self.max_recursion_depth,
+            first_n: self.first_n,
+            max_entries_per_dir: self.max_entries_per_dir,
             skip_hidden: self.skip_hidden,
+            hidden_predicate: self.hidden_predicate.clone(),
+            #[cfg(unix)]
+            skip_special_files: self.skip_special_files,
             follow_links: self.follow_links,
+            follow_root_links: self.follow_root_links,
+            absolute: self.absolute,
+            contents_first: self.contents_first,
+            skip_empty_dirs: self.skip_empty_dirs,
+            same_file_system: self.same_file_system,
+            respect_gitignore: self.respect_gitignore,
+            #[cfg(jwalk_xattr)]
+            read_xattrs: self.read_xattrs,
+            preload_metadata: self.preload_metadata,
+            #[cfg(windows)]
+            windows_long_paths: self.windows_long_paths,
             parallelism: self.parallelism.clone(),
+            max_queued_entries: self.max_queued_entries,
+            max_concurrent_reads: self.max_concurrent_reads,
+            read_dir_batch_size: self.read_dir_batch_size,
+            par_entries_threshold: self.par_entries_threshold,
+            traversal_order: self.traversal_order,
+            unordered_first: self.unordered_first,
+            background: self.background,
+            detect_changes: self.detect_changes,
+            cancellation: self.cancellation.clone(),
+            time_budget: self.time_budget,
             root_read_dir_state: self.root_read_dir_state.clone(),
+            skip_dirs: self.skip_dirs.clone(),
+            prune_dirs: self.prune_dirs.clone(),
+            extensions: self.extensions.clone(),
+            modified_after: self.modified_after,
+            modified_before: self.modified_before,
+            min_file_size: self.min_file_size,
+            max_file_size: self.max_file_size,
+            glob_include: self.glob_include.clone(),
+            glob_exclude: self.glob_exclude.clone(),
+            #[cfg(feature = "regex")]
+            path_regex_exclude: self.path_regex_exclude.clone(),
+            #[cfg(feature = "unicode")]
+            normalize_names: self.normalize_names,
+            filter_entry: self.filter_entry.clone(),
+            filter_map_entry: self.filter_map_entry.clone(),
+            file_type_filter: self.file_type_filter,
+            on_error: self.on_error,
             process_read_dir: self.process_read_dir.clone(),
+            on_read_dir: self.on_read_dir.clone(),
+            hash_with: self.hash_with.clone(),
+            client_state_with: self.client_state_with.clone(),
+            dir_reader: self.dir_reader.clone(),
         }
     }
 }
@@ -486,11 +3146,7 @@ impl Parallelism {
             Parallelism::Serial => op(),
             Parallelism::RayonDefaultPool => rayon::spawn(op),
             Parallelism::RayonNewPool(num_threads) => {
-                let mut thread_pool = ThreadPoolBuilder::new();
-                if *num_threads > 0 {
-                    thread_pool = thread_pool.num_threads(*num_threads);
-                }
-                if let Ok(thread_pool) = thread_pool.build() {
+                if let Some(thread_pool) = Parallelism::build_thread_pool(*num_threads) {
                     thread_pool.install(op);
                 } else {
                     rayon::spawn(op);
@@ -499,10 +3155,273 @@ impl Parallelism {
             Parallelism::RayonExistingPool(thread_pool) => thread_pool.install(op),
         }
     }
+
+    pub(crate) fn build_thread_pool(num_threads: usize) -> Option<ThreadPool> {
+        let mut thread_pool = ThreadPoolBuilder::new();
+        if num_threads > 0 {
+            thread_pool = thread_pool.num_threads(num_threads);
+        }
+        thread_pool.build().ok()
+    }
 }
 
-fn is_hidden(file_name: &OsStr) -> bool {
-    file_name.to_str().map(|s| s.starts_with('.')).unwrap_or(false)
+impl Sort {
+    // Errors for entries of `errors_dir` are sorted by name when it is set.
+    fn perform_sort<C: ClientState>(
+        &self,
+        reverse: bool,
+        errors_dir: Option<&Path>,
+        dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+    ) {
+        match self {
+            Sort::Name => {
+                sort_dir_entry_results_by_name(dir_entry_results, reverse, errors_dir, OsStr::cmp)
+            }
+            Sort::NameNatural => sort_dir_entry_results_by_name(
+                dir_entry_results,
+                reverse,
+                errors_dir,
+                compare_natural,
+            ),
+            Sort::NameCaseInsensitive => sort_dir_entry_results_by_name(
+                dir_entry_results,
+                reverse,
+                errors_dir,
+                compare_case_insensitive,
+            ),
+            Sort::Size => sort_dir_entry_results_by_key(dir_entry_results, reverse, |dir_entry| {
+                dir_entry.metadata().ok().map(|metadata| metadata.len())
+            }),
+            #[cfg(unix)]
+            Sort::Inode => sort_dir_entry_results_by_key(dir_entry_results, reverse, |dir_entry| {
+                Some(dir_entry.ino())
+            }),
+            Sort::DirsFirst => {
+                sort_dir_entry_results_by_key(dir_entry_results, reverse, |dir_entry| {
+                    Some(!dir_entry.file_type.is_dir())
+                })
+            }
+            Sort::FilesFirst => {
+                sort_dir_entry_results_by_key(dir_entry_results, reverse, |dir_entry| {
+                    Some(dir_entry.file_type.is_dir())
+                })
+            }
+        }
+    }
+}
+
+// Sort entries with `compare`, errors last.
+fn sort_dir_entry_results_by<C, F>(dir_entry_results: &mut [Result<DirEntry<C>>], compare: F)
+where
+    C: ClientState,
+    F: Fn(&DirEntry<C>, &DirEntry<C>) -> Ordering,
+{
+    dir_entry_results.sort_by(|a, b| match (a, b) {
+        (Ok(a), Ok(b)) => compare(a, b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => Ordering::Equal,
+    });
+}
+
+// Sort entries by file name with `compare`. Errors whose path is in
+// `errors_dir` are sorted by the file name of their path, other errors are
+// placed last regardless of `reverse`.
+fn sort_dir_entry_results_by_name<C, F>(
+    dir_entry_results: &mut [Result<DirEntry<C>>],
+    reverse: bool,
+    errors_dir: Option<&Path>,
+    compare: F,
+) where
+    C: ClientState,
+    F: Fn(&OsStr, &OsStr) -> Ordering,
+{
+    dir_entry_results.sort_by(|a, b| {
+        match (sort_file_name(a, errors_dir), sort_file_name(b, errors_dir)) {
+            (Some(a), Some(b)) if reverse => compare(b, a),
+            (Some(a), Some(b)) => compare(a, b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    });
+}
+
+fn sort_file_name<'a, C: ClientState>(
+    dir_entry_result: &'a Result<DirEntry<C>>,
+    errors_dir: Option<&Path>,
+) -> Option<&'a OsStr> {
+    match dir_entry_result {
+        Ok(dir_entry) => Some(&dir_entry.file_name),
+        Err(err) => errors_dir
+            .and_then(|errors_dir| err.path().filter(|path| path.parent() == Some(errors_dir)))
+            .and_then(Path::file_name),
+    }
+}
+
+// Sort entries by a key that is computed once per entry. Entries without a
+// key and errors are placed last regardless of `reverse`.
+fn sort_dir_entry_results_by_key<C, K, F>(
+    dir_entry_results: &mut Vec<Result<DirEntry<C>>>,
+    reverse: bool,
+    key: F,
+) where
+    C: ClientState,
+    K: Ord,
+    F: Fn(&DirEntry<C>) -> Option<K>,
+{
+    let mut keyed: Vec<_> = dir_entry_results
+        .drain(..)
+        .map(|dir_entry_result| (dir_entry_result.as_ref().ok().and_then(&key), dir_entry_result))
+        .collect();
+
+    // Equal keys are ordered by file name so that ties don't depend on the
+    // order the directory was listed in
+    keyed.sort_by(|(a_key, a), (b_key, b)| match (a, b) {
+        (Ok(a), Ok(b)) => match (a_key, b_key) {
+            (Some(a_key), Some(b_key)) => {
+                let ordering = a_key.cmp(b_key).then_with(|| a.file_name.cmp(&b.file_name));
+                if reverse {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.file_name.cmp(&b.file_name),
+        },
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => Ordering::Equal,
+    });
+
+    dir_entry_results.extend(keyed.into_iter().map(|(_, dir_entry_result)| dir_entry_result));
+}
+
+// Normalizes a file name for matching against patterns, see
+// `WalkDirGeneric::normalize_names`.
+type NormalizeFunction = fn(&OsStr) -> Cow<'_, OsStr>;
+
+fn normalized(normalize_name: Option<NormalizeFunction>, name: &OsStr) -> Cow<'_, OsStr> {
+    match normalize_name {
+        Some(normalize_name) => normalize_name(name),
+        None => Cow::Borrowed(name),
+    }
+}
+
+#[cfg(feature = "unicode")]
+impl NormalizationForm {
+    fn normalize_function(self) -> NormalizeFunction {
+        use unicode_normalization::{is_nfc, is_nfd, UnicodeNormalization};
+        match self {
+            NormalizationForm::Nfc => |name| match name.to_str() {
+                Some(name) if !is_nfc(name) => Cow::Owned(name.nfc().collect::<String>().into()),
+                _ => Cow::Borrowed(name),
+            },
+            NormalizationForm::Nfd => |name| match name.to_str() {
+                Some(name) if !is_nfd(name) => Cow::Owned(name.nfd().collect::<String>().into()),
+                _ => Cow::Borrowed(name),
+            },
+        }
+    }
+}
+
+fn compare_case_insensitive(a: &OsStr, b: &OsStr) -> Ordering {
+    let (a_lossy, b_lossy) = (a.to_string_lossy(), b.to_string_lossy());
+    a_lossy
+        .chars()
+        .flat_map(char::to_lowercase)
+        .cmp(b_lossy.chars().flat_map(char::to_lowercase))
+        .then_with(|| a.cmp(b))
+}
+
+// Compare names treating runs of ASCII digits as numbers. Names that are equal
+// apart from leading zeros fall back to a plain comparison.
+fn compare_natural(a: &OsStr, b: &OsStr) -> Ordering {
+    compare_natural_bytes(a.as_encoded_bytes(), b.as_encoded_bytes()).then_with(|| a.cmp(b))
+}
+
+fn compare_natural_bytes(a: &[u8], b: &[u8]) -> Ordering {
+    fn digits_end(bytes: &[u8], start: usize) -> usize {
+        bytes[start..].iter().position(|b| !b.is_ascii_digit()).map_or(bytes.len(), |i| start + i)
+    }
+
+    fn trim_zeros(digits: &[u8]) -> &[u8] {
+        let first_non_zero = digits.iter().position(|&b| b != b'0').unwrap_or(digits.len());
+        &digits[first_non_zero..]
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let ordering = if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let (a_end, b_end) = (digits_end(a, i), digits_end(b, j));
+            let (a_digits, b_digits) = (trim_zeros(&a[i..a_end]), trim_zeros(&b[j..b_end]));
+            i = a_end;
+            j = b_end;
+            a_digits.len().cmp(&b_digits.len()).then_with(|| a_digits.cmp(b_digits))
+        } else {
+            let ordering = a[i].cmp(&b[j]);
+            i += 1;
+            j += 1;
+            ordering
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j))
+}
+
+fn is_hidden<C: ClientState>(dir_entry: &DirEntry<C>) -> bool {
+    // Compare bytes so that names that aren't valid UTF-8 can be hidden too
+    dir_entry.file_name.as_encoded_bytes().starts_with(b".") || has_hidden_attribute(dir_entry)
+}
+
+#[cfg(windows)]
+fn has_hidden_attribute<C: ClientState>(dir_entry: &DirEntry<C>) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    // Metadata is already cached from the directory listing on Windows
+    dir_entry
+        .metadata()
+        .is_ok_and(|metadata| metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}
+
+#[cfg(not(windows))]
+fn has_hidden_attribute<C: ClientState>(_dir_entry: &DirEntry<C>) -> bool {
+    false
+}
+
+// The root with the extended-length prefix, so that child paths joined onto
+// it aren't limited to MAX_PATH.
+#[cfg(windows)]
+fn verbatim_path(path: &Path) -> PathBuf {
+    use std::path::{Component, Prefix};
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_owned();
+    };
+    let mut components = absolute.components();
+    let mut verbatim = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(disk) => PathBuf::from(format!(r"\\?\{}:\", disk as char)),
+            Prefix::UNC(server, share) => {
+                let mut verbatim = PathBuf::from(r"\\?\UNC\");
+                verbatim.push(server);
+                verbatim.push(share);
+                verbatim
+            }
+            // Already verbatim, or a device path
+            _ => return absolute,
+        },
+        _ => return absolute,
+    };
+    for component in components {
+        if let Component::Normal(name) = component {
+            verbatim.push(name);
+        }
+    }
+    verbatim
 }
 
 impl<B, E> ClientState for (B, E)
@@ -4,7 +4,9 @@ use rayon::prelude::*;
 use std::env;
 use std::fs;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 mod util;
 
@@ -587,6 +589,30 @@ fn sym_loop_detect() {
     assert!(err.io_error().is_none());
 }
 
+#[cfg(unix)]
+#[test]
+fn sym_loop_detect_relative_link() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b/c");
+    std::os::unix::fs::symlink("../..", dir.join("a/b/c/a-link")).unwrap();
+
+    let wd = WalkDir::new(dir.path()).follow_links(true);
+    let r = dir.run_recursive(wd);
+
+    let (ents, errs) = (r.ents(), r.errs());
+    assert_eq!(4, ents.len());
+    assert_eq!(1, errs.len());
+
+    let err = &errs[0];
+
+    let expected = dir.join("a/b/c/a-link");
+    assert_eq!(Some(&*expected), err.path());
+
+    let expected = dir.join("a");
+    assert_eq!(Some(&*expected), err.loop_ancestor());
+    assert!(err.io_error().is_none());
+}
+
 #[test]
 fn sym_self_loop_no_error() {
     let dir = Dir::tmp();
@@ -654,6 +680,27 @@ fn sym_dir_self_loop_io_error() {
     assert!(err.io_error().is_some());
 }
 
+#[test]
+fn sym_dangling_follow_io_error() {
+    let dir = Dir::tmp();
+    dir.symlink_dir("missing", "a");
+
+    let wd = WalkDir::new(dir.path()).follow_links(true);
+    let r = dir.run_recursive(wd);
+
+    let (ents, errs) = (r.ents(), r.errs());
+    assert_eq!(1, ents.len());
+    assert_eq!(1, errs.len());
+
+    let err = &errs[0];
+
+    let expected = dir.join("a");
+    assert_eq!(Some(&*expected), err.path());
+    assert_eq!(1, err.depth());
+    assert!(err.loop_ancestor().is_none());
+    assert!(err.io_error().is_some());
+}
+
 #[test]
 fn min_depth_1() {
     let dir = Dir::tmp();
@@ -763,7 +810,6 @@ fn min_max_depth_diff_1() {
     assert_eq!(expected, r.paths());
 }
 
-/*
 #[test]
 fn contents_first() {
     let dir = Dir::tmp();
@@ -773,13 +819,11 @@ fn contents_first() {
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
-    let expected = vec![
-        dir.join("a"),
-        dir.path().to_path_buf(),
-    ];
+    let expected = vec![dir.join("a"), dir.path().to_path_buf()];
     assert_eq!(expected, r.paths());
 }
 
+/*
 #[test]
 fn skip_current_dir() {
     let dir = Dir::tmp();
@@ -805,6 +849,7 @@ fn skip_current_dir() {
     ];
     assert_eq!(expected, paths);
 }
+*/
 
 #[test]
 fn filter_entry() {
@@ -812,9 +857,7 @@ fn filter_entry() {
     dir.mkdirp("foo/bar/baz/abc");
     dir.mkdirp("quux");
 
-    let wd = WalkDir::new(dir.path())
-        .into_iter()
-        .filter_entry(|ent| ent.file_name() != "baz");
+    let wd = WalkDir::new(dir.path()).sort(true).filter_entry(|ent| ent.file_name() != "baz");
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
@@ -824,9 +867,8 @@ fn filter_entry() {
         dir.join("foo").join("bar"),
         dir.join("quux"),
     ];
-    assert_eq!(expected, r.sorted_paths());
+    assert_eq!(expected, r.paths());
 }
-*/
 
 #[test]
 fn sort() {
@@ -849,8 +891,6 @@ fn sort() {
     assert_eq!(expected, r.paths());
 }
 
-/*
-
 #[cfg(target_os = "linux")]
 #[test]
 fn same_file_system() {
@@ -868,32 +908,23 @@ fn same_file_system() {
     dir.symlink_dir("/sys", "sys-link");
 
     // First, do a sanity check that things work without following symlinks.
-    let wd = WalkDir::new(dir.path());
+    let wd = WalkDir::new(dir.path()).sort(true);
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
-    let expected = vec![
-        dir.path().to_path_buf(),
-        dir.join("a"),
-        dir.join("sys-link"),
-    ];
-    assert_eq!(expected, r.sorted_paths());
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("sys-link")];
+    assert_eq!(expected, r.paths());
 
     // ... now follow symlinks and ensure we don't descend into /sys.
-    let wd = WalkDir::new(dir.path())
-        .same_file_system(true)
-        .follow_links(true);
+    let wd = WalkDir::new(dir.path()).sort(true).same_file_system(true).follow_links(true);
     let r = dir.run_recursive(wd);
     r.assert_no_errors();
 
-    let expected = vec![
-        dir.path().to_path_buf(),
-        dir.join("a"),
-        dir.join("sys-link"),
-    ];
-    assert_eq!(expected, r.sorted_paths());
+    let expected = vec![dir.path().to_path_buf(), dir.join("a"), dir.join("sys-link")];
+    assert_eq!(expected, r.paths());
 }
 
+/*
 // Tests that skip_current_dir doesn't destroy internal invariants.
 //
 // See: https://github.com/BurntSushi/walkdir/issues/118
@@ -928,14 +959,11 @@ fn test_dir() -> (PathBuf, tempfile::TempDir) {
 }
 
 fn local_paths(walk_dir: WalkDir) -> Vec<String> {
-    let root = walk_dir.root().to_owned();
     walk_dir
         .into_iter()
         .map(|each_result| {
             let each_entry = each_result.unwrap();
-            let path = each_entry.path().to_path_buf();
-            let path = path.strip_prefix(&root).unwrap().to_path_buf();
-            let mut path_string = path.to_str().unwrap().to_string();
+            let mut path_string = each_entry.relative_path().to_str().unwrap().to_string();
             path_string.push_str(&format!(" ({})", each_entry.depth));
             path_string
         })
@@ -1002,183 +1030,2884 @@ fn walk_rayon_global() {
 }
 
 #[test]
-fn walk_rayon_no_lockup() {
-    // Without jwalk_par_bridge this locks
-    let pool = std::sync::Arc::new(rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap());
-    let _: Vec<_> = WalkDir::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")))
-        .parallelism(Parallelism::RayonExistingPool(pool))
-        .process_read_dir(|_, _, _, dir_entry_results| {
-            for dir_entry_result in dir_entry_results {
-                let _ = dir_entry_result.as_ref().map(|dir_entry| dir_entry.metadata());
-            }
-        })
-        .sort(true)
-        .into_iter()
-        .collect();
+fn sort_reverse() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(test_dir).sort(true).sort_reverse(true));
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "c.txt (1)",
+            "b.txt (1)",
+            "a.txt (1)",
+        ]
+    );
 }
 
 #[test]
-fn combine_rayon_no_lockup() {
-    WalkDir::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")))
-        .parallelism(Parallelism::RayonNewPool(0)) // lockup if don't use separate rayon pool
-        .sort(true)
-        .into_iter()
-        .par_bridge()
-        .filter_map(|dir_entry_result| {
-            let dir_entry = dir_entry_result.ok()?;
-            if dir_entry.file_type().is_file() {
-                let path = dir_entry.path();
-                let text = std::fs::read_to_string(path).ok()?;
-                if text.contains("hello world") {
-                    return Some(true);
-                }
-            }
-            None
-        })
-        .count();
+fn sort_size_ties_by_name() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["c", "a", "d", "b"]);
+    fs::write(dir.join("big"), "contents").unwrap();
+    let names = |reverse| {
+        WalkDir::new(dir.path())
+            .sort_order(Sort::Size)
+            .sort_reverse(reverse)
+            .min_depth(1)
+            .into_iter()
+            .map(|each| each.unwrap().file_name.into_string().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(false), vec!["a", "b", "c", "d", "big"]);
+    assert_eq!(names(true), vec!["big", "d", "c", "b", "a"]);
 }
 
 #[test]
-fn see_hidden_files() {
-    let (test_dir, _temp_dir) = test_dir();
-    let paths = local_paths(WalkDir::new(test_dir).skip_hidden(false).sort(true));
-    assert!(paths.contains(&"group 2/.hidden_file.txt (2)".to_string()));
+fn sort_dirs_first() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["c", "a"]);
+    dir.mkdirp("d");
+    dir.mkdirp("b");
+    let names = |sort, reverse| {
+        WalkDir::new(dir.path())
+            .sort_order(sort)
+            .sort_reverse(reverse)
+            .min_depth(1)
+            .into_iter()
+            .map(|each| each.unwrap().file_name.into_string().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(Sort::DirsFirst, false), vec!["b", "d", "a", "c"]);
+    assert_eq!(names(Sort::DirsFirst, true), vec!["c", "a", "d", "b"]);
+    assert_eq!(names(Sort::FilesFirst, false), vec!["a", "c", "b", "d"]);
+    assert_eq!(names(Sort::FilesFirst, true), vec!["d", "b", "c", "a"]);
 }
 
 #[test]
-fn walk_file() {
-    let (test_dir, _temp_dir) = test_dir();
-    let walk_dir = WalkDir::new(test_dir.join("a.txt"));
-    let mut iter = walk_dir.into_iter();
-    assert!(iter.next().unwrap().unwrap().file_name.to_str().unwrap() == "a.txt");
-    assert!(iter.next().is_none());
+fn sort_by_key() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["img10", "img2", "b1", "a1", "img1"]);
+    let names = |reverse| {
+        WalkDir::new(dir.path())
+            .sort_by_key(|each| {
+                each.file_name
+                    .to_str()
+                    .unwrap()
+                    .trim_start_matches(char::is_alphabetic)
+                    .parse::<u32>()
+                    .unwrap()
+            })
+            .sort_reverse(reverse)
+            .min_depth(1)
+            .into_iter()
+            .map(|each| each.unwrap().file_name.into_string().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(false), vec!["a1", "b1", "img1", "img2", "img10"]);
+    assert_eq!(names(true), vec!["img10", "img2", "img1", "b1", "a1"]);
+
+    // Keys are computed once per entry
+    let key_count = Arc::new(AtomicUsize::new(0));
+    let walk_key_count = key_count.clone();
+    let _: Vec<_> = WalkDir::new(dir.path())
+        .sort_by_key(move |each| {
+            walk_key_count.fetch_add(1, AtomicOrdering::SeqCst);
+            each.file_name.clone()
+        })
+        .into_iter()
+        .collect();
+    assert_eq!(key_count.load(AtomicOrdering::SeqCst), 5);
 }
 
+// Metadata comes with the directory listing on Windows
+#[cfg(unix)]
 #[test]
-fn walk_file_serial() {
-    let (test_dir, _temp_dir) = test_dir();
-    let walk_dir = WalkDir::new(test_dir.join("a.txt")).parallelism(Parallelism::Serial);
-    let mut iter = walk_dir.into_iter();
-    assert!(iter.next().unwrap().unwrap().file_name.to_str().unwrap() == "a.txt");
-    assert!(iter.next().is_none());
+fn sort_size_metadata_error_last() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["b", "gone 2", "a", "gone 1"]);
+    fs::write(dir.join("big"), "contents").unwrap();
+    let names = |reverse| {
+        dir.touch_all(&["gone 1", "gone 2"]);
+        WalkDir::new(dir.path())
+            .sort_order(Sort::Size)
+            .sort_reverse(reverse)
+            .min_depth(1)
+            // Removed after being listed, so their metadata can't be read
+            .on_read_dir(|path, _| {
+                fs::remove_file(path.join("gone 1")).unwrap();
+                fs::remove_file(path.join("gone 2")).unwrap();
+            })
+            .into_iter()
+            .map(|each| each.unwrap().file_name.into_string().unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(false), vec!["a", "b", "big", "gone 1", "gone 2"]);
+    assert_eq!(names(true), vec!["big", "b", "a", "gone 1", "gone 2"]);
 }
 
 #[test]
-fn error_when_path_does_not_exist() {
+fn sort_size_reverse() {
     let (test_dir, _temp_dir) = test_dir();
-    let walk_dir = WalkDir::new(test_dir.join("path_does_not_exist"));
-    let mut iter = walk_dir.into_iter();
-    assert!(iter.next().unwrap().is_err());
-    assert!(iter.next().is_none());
+    let sizes: Vec<_> = WalkDir::new(test_dir)
+        .sort_order(Sort::Size)
+        .sort_reverse(true)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|each| {
+            let each = each.unwrap();
+            if each.file_type().is_file() {
+                Some((
+                    each.file_name().to_str().unwrap().to_owned(),
+                    each.metadata().unwrap().len(),
+                ))
+            } else {
+                None
+            }
+        })
+        .collect();
+    assert_eq!(sizes.len(), 3);
+    assert_eq!(sizes[0], ("b.txt".to_owned(), 9));
+    assert!(sizes.windows(2).all(|pair| pair[0].1 >= pair[1].1));
 }
 
+#[cfg(unix)]
 #[test]
-fn error_when_path_removed_durring_iteration() {
+fn sort_inode() {
     let (test_dir, _temp_dir) = test_dir();
-    let walk_dir = WalkDir::new(&test_dir).parallelism(Parallelism::Serial).sort(true);
-    let mut iter = walk_dir.into_iter();
-
-    // Read root. read_dir for root is also called since single thread mode.
-    let _ = iter.next().unwrap().is_ok(); // " (0)",
-
-    // Remove group 2 dir from disk
-    fs_extra::remove_items(&vec![test_dir.join("group 2")]).unwrap();
-
-    let _ = iter.next().unwrap().is_ok(); // "a.txt (1)",
-    let _ = iter.next().unwrap().is_ok(); // "b.txt (1)",
-    let _ = iter.next().unwrap().is_ok(); // "c.txt (1)",
-    let _ = iter.next().unwrap().is_ok(); // "group 1 (1)",
-    let _ = iter.next().unwrap().is_ok(); // "group 1/d.txt (2)",
-
-    // group 2 is read correctly, since it was read before path removed.
-    let group_2 = iter.next().unwrap().unwrap();
-
-    // group 2 content error IS set, since path is removed when try read_dir for
-    // group 2 path.
-    let _ = group_2.read_children_error.is_some();
-
-    // done!
-    assert!(iter.next().is_none());
-}
-
-#[test]
-fn walk_root() {
-    let paths: Vec<_> = WalkDir::new("/")
+    let inodes: Vec<_> = WalkDir::new(test_dir)
+        .sort_order(Sort::Inode)
         .max_depth(1)
-        .sort(true)
+        .min_depth(1)
         .into_iter()
-        .filter_map(|each| Some(each.ok()?.path().to_owned()))
+        .map(|each| {
+            let each = each.unwrap();
+            assert_eq!(each.ino(), std::os::unix::fs::MetadataExt::ino(&each.metadata().unwrap()));
+            each.ino()
+        })
         .collect();
-    assert!(paths.first().unwrap().to_str().unwrap() == "/");
-}
-
-lazy_static! {
-    static ref RELATIVE_MUTEX: Mutex<()> = Mutex::new(());
+    assert!(inodes.len() > 1);
+    assert!(inodes.windows(2).all(|pair| pair[0] <= pair[1]));
 }
 
 #[test]
-fn walk_relative_1() {
-    let _shared = RELATIVE_MUTEX.lock().unwrap();
+fn sort_by_dirs_first() {
     let (test_dir, _temp_dir) = test_dir();
-
-    env::set_current_dir(&test_dir).unwrap();
-
-    let paths = local_paths(WalkDir::new(".").sort(true));
-
+    let paths = local_paths(WalkDir::new(test_dir).sort(true).sort_by(|a, b| {
+        b.file_type().is_dir().cmp(&a.file_type().is_dir()).then(a.file_name().cmp(b.file_name()))
+    }));
     assert_eq!(
         paths,
         vec![
             " (0)",
-            "a.txt (1)",
-            "b.txt (1)",
-            "c.txt (1)",
             "group 1 (1)",
             "group 1/d.txt (2)",
             "group 2 (1)",
             "group 2/e.txt (2)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
         ]
     );
+}
 
-    let root_dir_entry = WalkDir::new("..").into_iter().next().unwrap().unwrap();
-    assert_eq!(&root_dir_entry.file_name, "..");
+#[test]
+fn sort_name_natural() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["img10.png", "img2.png", "img1.png", "img02b.png", "Img3.png", "img"]);
+
+    let wd = WalkDir::new(dir.path()).sort_order(Sort::NameNatural);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("Img3.png"),
+        dir.join("img"),
+        dir.join("img1.png"),
+        dir.join("img2.png"),
+        dir.join("img02b.png"),
+        dir.join("img10.png"),
+    ];
+    assert_eq!(expected, r.paths());
 }
 
+#[cfg(unix)]
 #[test]
-fn walk_relative_2() {
-    let _shared = RELATIVE_MUTEX.lock().unwrap();
-    let (test_dir, _temp_dir) = test_dir();
+fn sort_name_natural_non_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
 
-    env::set_current_dir(&test_dir.join("group 1")).unwrap();
+    let dir = Dir::tmp();
+    let names = [b"file10\xff".as_ref(), b"file9\xff", b"file1\xff"].map(OsStr::from_bytes);
+    dir.touch_all(&names);
 
-    let paths = local_paths(WalkDir::new("..").sort(true));
+    let wd = WalkDir::new(dir.path()).sort_order(Sort::NameNatural);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join(names[2]),
+        dir.join(names[1]),
+        dir.join(names[0]),
+    ];
+    assert_eq!(expected, r.paths());
+}
 
+#[test]
+fn sort_name_case_insensitive() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["banana", "Cherry", "avocado", "Apple"]);
+
+    let wd = WalkDir::new(dir.path()).sort_order(Sort::NameCaseInsensitive);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("Apple"),
+        dir.join("avocado"),
+        dir.join("banana"),
+        dir.join("Cherry"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn walk_contents_first() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(test_dir).contents_first(true).sort(true));
     assert_eq!(
         paths,
         vec![
-            " (0)",
             "a.txt (1)",
             "b.txt (1)",
             "c.txt (1)",
-            "group 1 (1)",
             "group 1/d.txt (2)",
-            "group 2 (1)",
+            "group 1 (1)",
             "group 2/e.txt (2)",
+            "group 2 (1)",
+            " (0)",
         ]
     );
-
-    let root_dir_entry = WalkDir::new(".").into_iter().next().unwrap().unwrap();
-    assert_eq!(&root_dir_entry.file_name, ".");
 }
 
 #[test]
-fn filter_groups_with_process_read_dir() {
+fn walk_contents_first_min_depth() {
     let (test_dir, _temp_dir) = test_dir();
     let paths = local_paths(
         WalkDir::new(test_dir)
+            .contents_first(true)
+            .min_depth(1)
+            .max_depth(1)
+            .parallelism(Parallelism::Serial)
+            .sort(true),
+    );
+    assert_eq!(paths, vec!["a.txt (1)", "b.txt (1)", "c.txt (1)", "group 1 (1)", "group 2 (1)",]);
+}
+
+#[test]
+fn walk_skip_empty_dirs() {
+    let (test_dir, _temp_dir) = test_dir();
+    fs::create_dir_all(test_dir.join("empty/nested")).unwrap();
+    let walk_dir = |contents_first| {
+        WalkDir::new(&test_dir)
+            .skip_empty_dirs(true)
+            .contents_first(contents_first)
+            .filter_entry(|each| each.file_name() != "e.txt")
             .sort(true)
-            // Filter groups out manually
-            .process_read_dir(|_depth, _path, _parent, children| {
-                children.retain(|each_result| {
+    };
+    assert_eq!(
+        local_paths(walk_dir(false)),
+        vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)", "group 1 (1)", "group 1/d.txt (2)",]
+    );
+    assert_eq!(
+        local_paths(walk_dir(true)),
+        vec!["a.txt (1)", "b.txt (1)", "c.txt (1)", "group 1/d.txt (2)", "group 1 (1)", " (0)",]
+    );
+    assert_eq!(local_paths(walk_dir(false).min_depth(2)), vec!["group 1/d.txt (2)"]);
+}
+
+#[test]
+fn walk_lossy_names() {
+    let (test_dir, _temp_dir) = test_dir();
+    for each in WalkDir::new(&test_dir) {
+        let each = each.unwrap();
+        assert!(matches!(each.file_name_lossy(), std::borrow::Cow::Borrowed(_)));
+        assert_eq!(each.file_name_lossy(), each.file_name().to_str().unwrap());
+        assert_eq!(each.path_lossy(), each.path().to_str().unwrap());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_non_utf8_names() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = Dir::tmp();
+    let hidden = OsStr::from_bytes(b".hidden \xff");
+    let visible = OsStr::from_bytes(b"visible \xff.TXT");
+    dir.touch_all(&[hidden, visible]);
+    let names = |walk_dir: WalkDir| -> Vec<_> {
+        walk_dir
+            .min_depth(1)
+            .sort(true)
+            .into_iter()
+            .map(|each| {
+                let each = each.unwrap();
+                assert_eq!(each.path_bytes(), each.path().as_os_str().as_bytes());
+                each.file_name
+            })
+            .collect()
+    };
+    assert_eq!(names(WalkDir::new(dir.path())), vec![visible]);
+    assert_eq!(names(WalkDir::new(dir.path()).skip_hidden(false)), vec![hidden, visible]);
+    assert_eq!(names(WalkDir::new(dir.path()).extensions(["txt"])), vec![visible]);
+    assert_eq!(names(WalkDir::new(dir.path()).glob_include(["visible ?.TXT"])), vec![visible]);
+}
+
+// Names starting with a `.` byte are hidden even if they aren't valid UTF-8
+#[cfg(unix)]
+#[test]
+fn walk_hidden_non_utf8_dotfile() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = Dir::tmp();
+    let hidden_dir = OsStr::from_bytes(b".\xfe\xff");
+    dir.mkdirp(hidden_dir);
+    dir.touch(dir.join(hidden_dir).join("inside"));
+    dir.touch(OsStr::from_bytes(b".\x80"));
+    dir.touch("visible");
+    let count = |skip_hidden| {
+        WalkDir::new(dir.path())
+            .skip_hidden(skip_hidden)
+            .min_depth(1)
+            .into_iter()
+            .inspect(|each| assert!(each.is_ok()))
+            .count()
+    };
+    assert_eq!(count(true), 1);
+    assert_eq!(count(false), 4);
+}
+
+#[test]
+fn walk_ancestors() {
+    let (test_dir, temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(&test_dir).sort(true);
+    let d_txt = walk_dir
+        .into_iter()
+        .map(|each| each.unwrap())
+        .find(|each| each.file_name() == "d.txt")
+        .unwrap();
+    assert_eq!(d_txt.parent_path(), test_dir.join("group 1"));
+    assert_eq!(d_txt.ancestors().collect::<Vec<_>>(), vec![test_dir.join("group 1"), test_dir]);
+
+    let root = WalkDir::new(temp_dir.path()).into_iter().next().unwrap().unwrap();
+    assert_eq!(root.ancestors().count(), 0);
+}
+
+#[test]
+fn walk_relative_path() {
+    let (test_dir, _temp_dir) = test_dir();
+    for each in WalkDir::new(&test_dir) {
+        let each = each.unwrap();
+        assert_eq!(test_dir.join(each.relative_path()), each.path());
+        assert_eq!(each.relative_path().components().count(), each.depth());
+    }
+}
+
+#[test]
+fn walk_extensions() {
+    let dir = Dir::tmp();
+    dir.mkdirp("src.rs/nested");
+    dir.touch_all(&["a.rs", "b.RS", "c.txt", "d", "src.rs/nested/e.rs", "src.rs/nested/f.md"]);
+
+    let wd = WalkDir::new(dir.path()).sort(true).extensions(["rs", ".md"]);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a.rs"),
+        dir.join("b.RS"),
+        dir.join("src.rs"),
+        dir.join("src.rs/nested"),
+        dir.join("src.rs/nested/e.rs"),
+        dir.join("src.rs/nested/f.md"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn walk_glob_include_exclude() {
+    let dir = Dir::tmp();
+    dir.mkdirp("logs/old");
+    dir.mkdirp("target/debug");
+    dir.touch_all(&[
+        "a.log",
+        "b.txt",
+        "logs/c.log",
+        "logs/old/d.log",
+        "logs/old/e.log1",
+        "target/f.log",
+        "target/debug/g.log",
+    ]);
+
+    let wd = WalkDir::new(dir.path())
+        .sort(true)
+        .glob_include(["**/*.log"])
+        .glob_exclude(["target/**", "logs/old/[d-e].*"]);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a.log"),
+        dir.join("logs"),
+        dir.join("logs/c.log"),
+        dir.join("logs/old"),
+    ];
+    assert_eq!(expected, r.paths());
+
+    // Patterns that would backtrack exponentially
+    let name = "a".repeat(100);
+    dir.mkdirp(["a"; 30].join("/"));
+    dir.touch(format!("{}/{}", ["a"; 30].join("/"), name));
+    let paths = |glob: &str| -> Vec<_> {
+        WalkDir::new(dir.path())
+            .glob_include([glob])
+            .into_iter()
+            .map(|each| each.unwrap().file_name)
+            .filter(|file_name| *file_name == *name)
+            .collect()
+    };
+    assert!(paths(&format!("**/{}b", "*a".repeat(20))).is_empty());
+    assert!(paths(&format!("{}b", "**/a/".repeat(15))).is_empty());
+    assert_eq!(paths(&format!("**/{}", "*a".repeat(20))).len(), 1);
+}
+
+#[test]
+fn walk_respect_gitignore() {
+    let dir = Dir::tmp();
+    dir.mkdirp("build/out");
+    dir.mkdirp("src/generated");
+    dir.mkdirp("docs");
+    dir.touch_all(&[
+        "a.log",
+        "keep.log",
+        "main.rs",
+        "build/out/b.o",
+        "src/lib.rs",
+        "src/c.log",
+        "src/generated/d.rs",
+        "docs/build",
+    ]);
+    fs::write(dir.join(".gitignore"), "# comment\n*.log\n!keep.log\nbuild/\n").unwrap();
+    fs::write(dir.join("src/.gitignore"), "/generated\n").unwrap();
+
+    let wd = WalkDir::new(dir.path()).sort(true).respect_gitignore(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("docs"),
+        dir.join("docs/build"),
+        dir.join("keep.log"),
+        dir.join("main.rs"),
+        dir.join("src"),
+        dir.join("src/lib.rs"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn walk_skip_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("node_modules/left-pad");
+    dir.mkdirp("src/target");
+    dir.touch_all(&["src/target/a", "src/b"]);
+
+    let wd = WalkDir::new(dir.path()).sort(true).skip_dirs(vec!["node_modules", "target"]);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("node_modules"),
+        dir.join("src"),
+        dir.join("src/b"),
+        dir.join("src/target"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn walk_file_type_shortcuts() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("b");
+    dir.symlink_file("b", "c");
+
+    for each in WalkDir::new(dir.path()) {
+        let each = each.unwrap();
+        assert_eq!(each.is_dir(), each.file_type().is_dir());
+        assert_eq!(each.is_file(), each.file_type().is_file());
+        assert_eq!(each.is_symlink(), each.file_type().is_symlink());
+        assert_eq!(each.is_symlink(), each.file_name() == "c");
+    }
+}
+
+#[test]
+fn walk_metadata_cached() {
+    let dir = Dir::tmp();
+    dir.touch("a");
+    dir.touch("b");
+
+    let entries: Vec<_> = WalkDir::new(dir.path()).min_depth(1).sort(true).into_iter().collect();
+    let a = entries[0].as_ref().unwrap();
+    let b = entries[1].as_ref().unwrap();
+    assert!(a.metadata().unwrap().is_file());
+
+    fs::remove_file(a.path()).unwrap();
+    fs::remove_file(b.path()).unwrap();
+    assert!(a.metadata().unwrap().is_file());
+    assert!(b.metadata().is_err());
+}
+
+#[test]
+fn walk_par_iter() {
+    let (test_dir, _temp_dir) = test_dir();
+    let local_path = |each_result: Result<DirEntry<((), ())>>| {
+        let each_entry = each_result.unwrap();
+        format!("{} ({})", each_entry.relative_path().to_str().unwrap(), each_entry.depth)
+    };
+
+    let expected = local_paths(WalkDir::new(&test_dir).min_depth(1).sort(true));
+    let mut paths: Vec<_> =
+        WalkDir::new(&test_dir).min_depth(1).into_par_iter().map(local_path).collect();
+    paths.sort();
+    assert_eq!(paths, expected);
+
+    let paths: Vec<_> = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .sort(true)
+        .into_par_iter()
+        .map(local_path)
+        .collect();
+    assert_eq!(paths, local_paths(WalkDir::new(&test_dir).sort(true)));
+
+    let paths: Vec<_> = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::RayonNewPool(2))
+        .max_depth(1)
+        .into_par_iter()
+        .map(local_path)
+        .collect();
+    assert_eq!(paths.len(), 6);
+}
+
+#[test]
+fn walk_process_read_dir_with_state() {
+    let (test_dir, _temp_dir) = test_dir();
+    let inits = Arc::new(AtomicUsize::new(0));
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let walk_dir = WalkDir::new(test_dir).parallelism(Parallelism::RayonNewPool(2));
+    let walk_dir = walk_dir.process_read_dir_with_state(
+        {
+            let inits = inits.clone();
+            move || {
+                inits.fetch_add(1, AtomicOrdering::SeqCst);
+                Vec::<String>::new()
+            }
+        },
+        {
+            let calls = calls.clone();
+            move |names, _, _, _, children| {
+                calls.fetch_add(1, AtomicOrdering::SeqCst);
+                names.clear();
+                names.extend(
+                    children
+                        .iter()
+                        .flatten()
+                        .map(|each| each.file_name.to_string_lossy().into_owned()),
+                );
+                assert_eq!(names.len(), children.len());
+            }
+        },
+    );
+
+    assert_eq!(walk_dir.into_iter().count(), 8);
+    assert_eq!(calls.load(AtomicOrdering::SeqCst), 4);
+    // At most one state for the thread processing the roots and one for each
+    // pool thread
+    assert!((1..=3).contains(&inits.load(AtomicOrdering::SeqCst)));
+}
+
+#[test]
+fn walk_process_read_dir_with_state_serial() {
+    let (test_dir, _temp_dir) = test_dir();
+    let inits = Arc::new(AtomicUsize::new(0));
+
+    let walk_dir = WalkDir::new(test_dir).parallelism(Parallelism::Serial);
+    let walk_dir = walk_dir.process_read_dir_with_state(
+        {
+            let inits = inits.clone();
+            move || {
+                inits.fetch_add(1, AtomicOrdering::SeqCst);
+                0
+            }
+        },
+        |calls, _, _, _, _| *calls += 1,
+    );
+
+    assert_eq!(walk_dir.into_iter().count(), 8);
+    assert_eq!(inits.load(AtomicOrdering::SeqCst), 1);
+}
+
+#[test]
+fn walk_cancellation() {
+    let (test_dir, _temp_dir) = test_dir();
+    let cancellation = Arc::new(AtomicBool::new(false));
+    let mut walk_dir = WalkDir::new(test_dir)
+        .parallelism(Parallelism::Serial)
+        .sort(true)
+        .cancellation(cancellation.clone())
+        .into_iter();
+
+    // The root directory has already been read when the root is yielded
+    assert_eq!(walk_dir.next().unwrap().unwrap().depth, 0);
+    cancellation.store(true, AtomicOrdering::SeqCst);
+    let paths: Vec<_> = walk_dir.map(|each| each.unwrap().file_name).collect();
+    assert_eq!(paths, vec!["a.txt", "b.txt", "c.txt", "group 1", "group 2"]);
+}
+
+#[test]
+fn walk_time_budget() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = || WalkDir::new(&test_dir).parallelism(Parallelism::Serial).sort(true);
+
+    let mut iter = walk_dir().time_budget(Duration::from_millis(100)).into_iter();
+    assert_eq!(iter.next().unwrap().unwrap().depth, 0);
+    std::thread::sleep(Duration::from_millis(150));
+    let paths: Vec<_> = iter.map(|each| each.unwrap().file_name).collect();
+    assert_eq!(paths, vec!["a.txt", "b.txt", "c.txt", "group 1", "group 2"]);
+
+    // Root entries are created before the walk starts
+    assert_eq!(local_paths(walk_dir().time_budget(Duration::ZERO)), vec![" (0)"]);
+}
+
+#[test]
+fn walk_max_queued_entries() {
+    let dir = Dir::tmp();
+    for i in 0..20 {
+        dir.mkdirp(i.to_string());
+        for j in 0..20 {
+            dir.touch(format!("{}/{}", i, j));
+        }
+    }
+
+    for max_queued_entries in [0, 1, 50] {
+        let walk_dir = WalkDir::new(dir.path())
+            .parallelism(Parallelism::RayonNewPool(4))
+            .max_queued_entries(max_queued_entries);
+        let mut count = 0;
+        for each in walk_dir {
+            each.unwrap();
+            count += 1;
+            if count % 50 == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        }
+        assert_eq!(count, 421);
+    }
+
+    // Dropping the iterator early must release waiting threads
+    let walk_dir = WalkDir::new(dir.path()).max_queued_entries(1);
+    assert_eq!(walk_dir.into_iter().take(3).count(), 3);
+}
+
+#[test]
+fn walk_thread_pool() {
+    let (test_dir, _temp_dir) = test_dir();
+    let pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .thread_name(|i| format!("walk-{}", i))
+            .build()
+            .unwrap(),
+    );
+    let thread_names = Arc::new(Mutex::new(Vec::new()));
+
+    for _ in 0..2 {
+        let thread_names = thread_names.clone();
+        let walk_dir = WalkDir::new(&test_dir).thread_pool(pool.clone()).process_read_dir(
+            move |depth, _, _, _| {
+                if depth.is_some() {
+                    let name = std::thread::current().name().map(str::to_owned);
+                    thread_names.lock().unwrap().push(name);
+                }
+            },
+        );
+        assert_eq!(walk_dir.into_iter().count(), 8);
+    }
+
+    let thread_names = thread_names.lock().unwrap();
+    assert_eq!(thread_names.len(), 6);
+    assert!(thread_names
+        .iter()
+        .all(|name| name.as_deref().is_some_and(|name| name.starts_with("walk-"))));
+}
+
+#[test]
+fn walk_stats() {
+    // Total bytes, files, dirs, symlinks and errors
+    let totals = |stats: WalkStats| {
+        (stats.total_bytes, stats.files, stats.dirs, stats.symlinks, stats.errors)
+    };
+    let (test_dir, _temp_dir) = test_dir();
+    let stats = WalkDir::new(&test_dir).skip_hidden(false).stats().unwrap();
+    assert_eq!(totals(stats), (27, 6, 3, 0, 0));
+
+    let stats = WalkDir::new(&test_dir).extensions(["md"]).stats().unwrap();
+    assert_eq!(totals(stats), (0, 0, 3, 0, 0));
+
+    let dir = Dir::tmp();
+    dir.touch("a");
+    dir.symlink_file("a", "b");
+    let stats = WalkDir::new(dir.path()).stats().unwrap();
+    assert_eq!(totals(stats), (0, 1, 1, 1, 0));
+
+    assert!(WalkDir::new(dir.join("missing")).stats().is_err());
+
+    // A root whose contents can't be read
+    struct Reader;
+
+    impl DirReader<((), ())> for Reader {
+        fn read_dir(
+            &self,
+            read_dir_spec: &ReadDirSpec<((), ())>,
+        ) -> Result<Vec<Result<DirEntry<((), ())>>>> {
+            let missing = read_dir_spec.path.join("missing");
+            Err(WalkDir::new(missing).into_iter().next().unwrap().unwrap_err())
+        }
+    }
+
+    let err = WalkDir::new(dir.path()).with_reader(Reader).stats().unwrap_err();
+    assert_eq!(err.depth(), 0);
+    let stats = WalkDir::new(dir.path()).min_depth(0).max_depth(0).stats().unwrap();
+    assert_eq!(totals(stats), (0, 0, 1, 0, 0));
+}
+
+#[test]
+fn walk_count() {
+    let (test_dir, _temp_dir) = test_dir();
+    assert_eq!(
+        WalkDir::new(&test_dir).count().unwrap(),
+        WalkDir::new(&test_dir).into_iter().count()
+    );
+    assert_eq!(WalkDir::new(&test_dir).sort(true).skip_hidden(false).count().unwrap(), 9);
+    assert_eq!(WalkDir::new(&test_dir).min_depth(1).extensions(["txt"]).count().unwrap(), 7);
+    assert!(WalkDir::new(test_dir.join("missing")).count().is_err());
+
+    // Options that depend on the yield order
+    let dir = Dir::tmp();
+    dir.mkdirp("empty/nested");
+    dir.mkdirp("full");
+    dir.touch_all(&["full/a", "b"]);
+    let walk_dir = || WalkDir::new(dir.path()).skip_empty_dirs(true);
+    assert_eq!(walk_dir().count().unwrap(), walk_dir().into_iter().count());
+    assert_eq!(walk_dir().count().unwrap(), 4);
+    assert_eq!(WalkDir::new(&test_dir).first_n(3).count().unwrap(), 3);
+}
+
+#[test]
+fn walk_client_state_with() {
+    let (test_dir, _temp_dir) = test_dir();
+    let entries: Vec<_> = WalkDirGeneric::<((), Option<String>)>::new(&test_dir)
+        .sort(true)
+        .client_state_with(|dir_entry| {
+            dir_entry.file_type().is_file().then(|| fs::read_to_string(dir_entry.path()).unwrap())
+        })
+        .process_read_dir(|_, _, _, dir_entry_results| {
+            for dir_entry in dir_entry_results.iter_mut().flatten() {
+                if let Some(contents) = dir_entry.client_state.as_mut() {
+                    contents.make_ascii_uppercase();
+                }
+            }
+        })
+        .into_iter()
+        .map(|each| {
+            let each = each.unwrap();
+            (each.file_name.to_str().unwrap().to_owned(), each.client_state)
+        })
+        .collect();
+    assert_eq!(entries[0], ("test_dir".to_owned(), None));
+    assert_eq!(entries[1].0, "a.txt");
+    assert_eq!(
+        entries[1].1,
+        Some(fs::read_to_string(test_dir.join("a.txt")).unwrap().to_uppercase())
+    );
+    assert_eq!(entries[4], ("group 1".to_owned(), None));
+}
+
+#[test]
+fn walk_file_type_filter() {
+    let (test_dir, _temp_dir) = test_dir();
+
+    let paths =
+        local_paths(WalkDir::new(&test_dir).sort(true).file_type_filter(FileTypeFilter::Files));
+    assert_eq!(
+        paths,
+        vec!["a.txt (1)", "b.txt (1)", "c.txt (1)", "group 1/d.txt (2)", "group 2/e.txt (2)"]
+    );
+
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .contents_first(true)
+            .file_type_filter(FileTypeFilter::Dirs),
+    );
+    assert_eq!(paths, vec!["group 1 (1)", "group 2 (1)", " (0)"]);
+
+    let mut paths: Vec<_> = WalkDir::new(&test_dir)
+        .file_type_filter(FileTypeFilter::Dirs)
+        .into_par_iter()
+        .map(|each| each.unwrap().file_name)
+        .collect();
+    paths.sort();
+    assert_eq!(paths, vec!["group 1", "group 2", "test_dir"]);
+}
+
+#[test]
+fn walk_from_roots() {
+    let (test_dir, _temp_dir) = test_dir();
+    let roots = vec![test_dir.join("group 2"), test_dir.join("b.txt"), test_dir.join("group 1")];
+    let expected = vec!["group 2 (0)", "e.txt (1)", "b.txt (0)", "group 1 (0)", "d.txt (1)"];
+
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = WalkDir::from_roots(&roots).parallelism(parallelism).sort(true);
+        assert_eq!(walk_dir.root(), roots[0]);
+        let paths: Vec<_> = walk_dir
+            .into_iter()
+            .map(|each| {
+                let each = each.unwrap();
+                format!("{} ({})", each.file_name().to_str().unwrap(), each.depth)
+            })
+            .collect();
+        assert_eq!(paths, expected);
+    }
+
+    let dir = Dir::tmp();
+    let mut roots = Vec::new();
+    let mut expected = Vec::new();
+    for i in 0..5 {
+        let root = format!("root{}", i);
+        for j in 0..4 {
+            dir.mkdirp(format!("{}/{}/{}", root, j, j));
+            dir.touch(format!("{}/{}/{}/file", root, j, j));
+        }
+        expected.extend(
+            WalkDir::new(dir.join(&root)).sort(true).into_iter().map(|each| each.unwrap().path),
+        );
+        roots.push(dir.join(root));
+    }
+    let paths: Vec<_> =
+        WalkDir::from_roots(roots).sort(true).into_iter().map(|each| each.unwrap().path).collect();
+    assert_eq!(paths, expected);
+
+    assert_eq!(WalkDir::from_roots(Vec::<PathBuf>::new()).into_iter().count(), 0);
+}
+
+#[test]
+fn walk_first_n() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(&test_dir).sort(true).first_n(5));
+    assert_eq!(paths, vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)", "group 1 (1)"]);
+
+    let paths = local_paths(WalkDir::new(&test_dir).sort(true).min_depth(1).first_n(2));
+    assert_eq!(paths, vec!["a.txt (1)", "b.txt (1)"]);
+
+    assert!(local_paths(WalkDir::new(&test_dir).first_n(0)).is_empty());
+
+    // No directories are read after the last entry is yielded
+    let dir = Dir::tmp();
+    for i in 0..50 {
+        dir.mkdirp(format!("{}/child", i));
+    }
+    let reads = Arc::new(AtomicUsize::new(0));
+    let walk_dir = WalkDir::new(dir.path())
+        .parallelism(Parallelism::RayonNewPool(2))
+        .first_n(1)
+        .process_read_dir({
+            let reads = reads.clone();
+            move |_, _, _, _| {
+                reads.fetch_add(1, AtomicOrdering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        });
+    let mut iter = walk_dir.into_iter();
+    assert!(iter.next().is_some());
+    assert!(iter.next().is_none());
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    let reads_after_stop = reads.load(AtomicOrdering::SeqCst);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(reads.load(AtomicOrdering::SeqCst), reads_after_stop);
+    assert!(reads_after_stop < 10);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn walk_serde() {
+    let (test_dir, _temp_dir) = test_dir();
+    let entries: Vec<_> =
+        WalkDir::new(&test_dir).sort(true).into_iter().map(|each| each.unwrap()).collect();
+
+    let json = serde_json::to_string(&entries).unwrap();
+    let snapshots: Vec<DirEntrySnapshot> = serde_json::from_str(&json).unwrap();
+    assert_eq!(snapshots, entries.iter().map(DirEntry::snapshot).collect::<Vec<_>>());
+
+    let b = &snapshots[2];
+    assert_eq!(b.path, test_dir.join("b.txt"));
+    assert_eq!((b.depth, b.is_file, b.is_dir, b.len), (1, true, false, Some(9)));
+    assert!(b.modified.is_some());
+}
+
+#[test]
+fn walk_read_dir_error_path() {
+    let (test_dir, _temp_dir) = test_dir();
+    let missing = test_dir.join("missing");
+    let walk_dir = WalkDir::new(&test_dir).process_read_dir({
+        let missing = missing.clone();
+        move |depth, _, _, children| {
+            if depth == Some(0) {
+                for each in children.iter_mut().flatten() {
+                    if each.file_name == "group 1" {
+                        each.read_children_path = Some(Arc::from(missing.as_path()));
+                    }
+                }
+            }
+        }
+    });
+
+    let group_1 = walk_dir
+        .into_iter()
+        .map(|each| each.unwrap())
+        .find(|each| each.file_name == "group 1")
+        .unwrap();
+    let err = group_1.read_children_error.unwrap();
+    assert_eq!(err.depth(), 1);
+    assert_eq!(err.path(), Some(missing.as_path()));
+    assert_eq!(err.io_error().unwrap().kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn walk_on_error() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = |on_error| {
+        let missing = test_dir.join("missing");
+        WalkDir::new(&test_dir).sort(true).on_error(on_error).process_read_dir(
+            move |depth, _, _, children| {
+                if depth == Some(0) {
+                    for each in children.iter_mut().flatten() {
+                        if each.file_name == "group 1" {
+                            each.read_children_path = Some(Arc::from(missing.as_path()));
+                        }
+                    }
+                }
+            },
+        )
+    };
+
+    let yielded: Vec<_> =
+        walk_dir(ErrorHandling::Yield).into_iter().map(|each| each.unwrap()).collect();
+    assert!(yielded.iter().any(|each| each.read_children_error.is_some()));
+
+    let skipped: Vec<_> =
+        walk_dir(ErrorHandling::Skip).into_iter().map(|each| each.unwrap()).collect();
+    assert_eq!(skipped.len(), yielded.len());
+    assert!(skipped.iter().all(|each| each.read_children_error.is_none()));
+
+    let aborted: Vec<_> =
+        walk_dir(ErrorHandling::Abort).into_iter().map(|each| each.unwrap()).collect();
+    assert!(aborted.len() < yielded.len());
+    let last = aborted.last().unwrap();
+    assert_eq!(last.file_name, "group 1");
+    assert!(last.read_children_error.is_some());
+
+    let par_aborted = walk_dir(ErrorHandling::Abort)
+        .parallelism(Parallelism::Serial)
+        .into_par_iter()
+        .map(|each| each.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(par_aborted.len(), aborted.len());
+
+    let missing_root = WalkDir::new(test_dir.join("missing")).on_error(ErrorHandling::Skip);
+    assert_eq!(missing_root.into_iter().count(), 0);
+}
+
+#[test]
+fn walk_read_dir_spec_depth() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(test_dir).process_read_dir(|depth, _, _, children| {
+        for each in children.iter().flatten() {
+            assert_eq!(each.read_dir_spec_depth(), depth);
+        }
+    });
+    for each in walk_dir {
+        let each = each.unwrap();
+        assert_eq!(each.read_dir_spec_depth(), each.depth().checked_sub(1));
+    }
+}
+
+#[test]
+fn walk_on_read_dir() {
+    let (test_dir, _temp_dir) = test_dir();
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let walk_dir = WalkDir::new(&test_dir).on_read_dir({
+        let reads = reads.clone();
+        move |path, count| reads.lock().unwrap().push((path.to_path_buf(), count))
+    });
+    assert_eq!(walk_dir.into_iter().count(), 8);
+
+    let mut reads = reads.lock().unwrap().clone();
+    reads.sort();
+    assert_eq!(
+        reads,
+        vec![(test_dir.clone(), 5), (test_dir.join("group 1"), 1), (test_dir.join("group 2"), 1)]
+    );
+}
+
+#[test]
+fn walk_max_concurrent_reads() {
+    let (test_dir, _temp_dir) = test_dir();
+    for i in 0..20 {
+        let dir = test_dir.join(format!("dir {}", i));
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("f.txt"), "f").unwrap();
+    }
+
+    // Tracks how many directories are being read at once
+    #[derive(Clone, Default)]
+    struct Reader {
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl DirReader<((), ())> for Reader {
+        fn read_dir(
+            &self,
+            read_dir_spec: &ReadDirSpec<((), ())>,
+        ) -> Result<Vec<Result<DirEntry<((), ())>>>> {
+            let in_flight = self.in_flight.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, AtomicOrdering::SeqCst);
+            std::thread::sleep(Duration::from_millis(1));
+            let dir_entry_results = FsDirReader.read_dir(read_dir_spec);
+            self.in_flight.fetch_sub(1, AtomicOrdering::SeqCst);
+            dir_entry_results
+        }
+    }
+
+    let walk_dir = |n, reader: &Reader| {
+        WalkDir::new(&test_dir)
+            .max_concurrent_reads(n)
+            .parallelism(Parallelism::RayonNewPool(4))
+            .with_reader(reader.clone())
+    };
+    for n in [1, 2] {
+        let reader = Reader::default();
+        assert_eq!(walk_dir(n, &reader).into_iter().count(), 48);
+        assert!(reader.max_in_flight.load(AtomicOrdering::SeqCst) <= n);
+
+        let reader = Reader::default();
+        assert_eq!(walk_dir(n, &reader).into_par_iter().count(), 48);
+        assert!(reader.max_in_flight.load(AtomicOrdering::SeqCst) <= n);
+    }
+
+    let reader = Reader::default();
+    assert_eq!(walk_dir(0, &reader).into_par_iter().count(), 48);
+    assert_eq!(reader.max_in_flight.load(AtomicOrdering::SeqCst), 1);
+}
+
+#[test]
+fn walk_unsorted_read_order() {
+    let (test_dir, _temp_dir) = test_dir();
+    for i in 0..10 {
+        let dir = test_dir.join(format!("dir {}", i));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("nested/f.txt"), "f").unwrap();
+    }
+    let serial = local_paths(WalkDir::new(&test_dir).parallelism(Parallelism::Serial));
+    for _ in 0..5 {
+        let parallel =
+            local_paths(WalkDir::new(&test_dir).parallelism(Parallelism::RayonNewPool(4)));
+        assert_eq!(parallel, serial);
+    }
+}
+
+#[test]
+#[cfg(jwalk_xattr)]
+fn walk_read_xattrs() {
+    let (test_dir, _temp_dir) = test_dir();
+    let a_txt = test_dir.join("a.txt");
+    if rustix::fs::setxattr(&a_txt, "user.jwalk", b"value", rustix::fs::XattrFlags::empty())
+        .is_err()
+    {
+        // Filesystem doesn't support user attributes
+        return;
+    }
+
+    let xattrs = |read_xattrs| {
+        WalkDir::new(&test_dir)
+            .read_xattrs(read_xattrs)
+            .into_iter()
+            .map(|each| each.unwrap())
+            .find(|each| each.path() == a_txt)
+            .unwrap()
+            .xattrs()
+            .unwrap()
+            .to_vec()
+    };
+    assert_eq!(xattrs(true), vec![("user.jwalk".into(), b"value".to_vec())]);
+    assert!(xattrs(false).is_empty());
+}
+
+#[test]
+#[cfg(jwalk_xattr)]
+fn walk_read_xattrs_error() {
+    let (test_dir, _temp_dir) = test_dir();
+    let a_txt = test_dir.join("a.txt");
+
+    // Attributes are read after filtering, by which time the file is gone
+    let entry = WalkDir::new(&test_dir)
+        .read_xattrs(true)
+        .filter_entry(|each| {
+            if each.file_name() == "a.txt" {
+                fs::remove_file(each.path()).unwrap();
+            }
+            true
+        })
+        .into_iter()
+        .map(|each| each.unwrap())
+        .find(|each| each.path() == a_txt)
+        .unwrap();
+    let err = entry.xattrs().unwrap_err();
+    assert_eq!(err.path(), Some(a_txt.as_path()));
+    assert_eq!(err.io_error().unwrap().kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn walk_hash_with() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(&test_dir).hash_with(|path| {
+        if path.ends_with("c.txt") {
+            return Err(std::io::Error::other("unreadable"));
+        }
+        fs::read(path).map(|contents| contents.len().to_le_bytes())
+    });
+    for each in walk_dir {
+        let each = each.unwrap();
+        if !each.file_type().is_file() {
+            assert!(each.hash().is_none());
+        } else if each.file_name() == "c.txt" {
+            let err = each.hash().unwrap().unwrap_err();
+            assert_eq!(err.path(), Some(each.path()));
+        } else {
+            let len = each.metadata().unwrap().len() as usize;
+            assert_eq!(each.hash().unwrap().unwrap(), len.to_le_bytes());
+        }
+    }
+}
+
+#[test]
+fn walk_traversal_order() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = |traversal_order| {
+        WalkDir::new(&test_dir)
+            .traversal_order(traversal_order)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .sort(true)
+    };
+    let depth_first = local_paths(walk_dir(TraversalOrder::DepthFirst));
+    assert_eq!(depth_first.len(), 8);
+    assert_eq!(local_paths(walk_dir(TraversalOrder::BreadthFirst)), depth_first);
+}
+
+#[test]
+fn walk_process_read_dir_by_path() {
+    let (test_dir, _temp_dir) = test_dir();
+    let group_1 = test_dir.join("group 1");
+    let paths = local_paths(WalkDir::new(&test_dir).sort(true).process_read_dir(
+        move |_, path, _, children| {
+            if path == group_1 {
+                children.clear();
+            }
+        },
+    ));
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn walk_root_trailing_separator() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = |root: &str| {
+        WalkDir::new(root)
+            .sort(true)
+            .into_iter()
+            .map(|each| {
+                let each = each.unwrap();
+                (each.path, each.parent_path.to_path_buf())
+            })
+            .collect::<Vec<_>>()
+    };
+    let root = test_dir.to_str().unwrap();
+    let expected = paths(root);
+    assert_eq!(expected.len(), 8);
+    assert_eq!(paths(&format!("{}/", root)), expected);
+    assert_eq!(paths(&format!("{}//", root)), expected);
+    assert_eq!(paths(&format!("{}/.", root)), expected);
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_root_symlink_trailing_separator() {
+    let (test_dir, temp_dir) = test_dir();
+    let link = temp_dir.path().join("link");
+    std::os::unix::fs::symlink(&test_dir, &link).unwrap();
+    let paths: Vec<_> = WalkDir::new(format!("{}//", link.display()))
+        .sort(true)
+        .into_iter()
+        .map(|each| each.unwrap().path)
+        .collect();
+    assert_eq!(paths.len(), 8);
+    assert_eq!(paths[1], link.join("a.txt"));
+    assert_eq!(paths[1].to_str().unwrap(), format!("{}/a.txt", link.display()));
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_follow_root_links() {
+    let (test_dir, temp_dir) = test_dir();
+    let link = temp_dir.path().join("link");
+    std::os::unix::fs::symlink(&test_dir, &link).unwrap();
+    std::os::unix::fs::symlink(test_dir.join("group 1"), test_dir.join("group 3")).unwrap();
+
+    let results: Vec<_> =
+        WalkDir::new(&link).sort(true).into_iter().map(|each| each.unwrap()).collect();
+    assert_eq!(results.len(), 9);
+    assert!(results[0].file_type().is_symlink());
+    assert!(results[8].file_type().is_symlink());
+    assert_eq!(results[8].file_name, "group 3");
+
+    let results: Vec<_> = WalkDir::new(&link)
+        .follow_root_links(false)
+        .into_iter()
+        .map(|each| each.unwrap())
+        .collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].file_type().is_symlink());
+
+    let walk_dir = WalkDir::new(&link).follow_root_links(false).follow_links(true);
+    assert_eq!(walk_dir.into_iter().count(), 10);
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_skip_special_files() {
+    let (test_dir, _temp_dir) = test_dir();
+    let _listener = std::os::unix::net::UnixListener::bind(test_dir.join("socket")).unwrap();
+    let paths = |skip_special_files| {
+        local_paths(WalkDir::new(&test_dir).skip_special_files(skip_special_files).max_depth(1))
+    };
+    assert!(paths(false).contains(&"socket (1)".to_string()));
+    assert!(!paths(true).contains(&"socket (1)".to_string()));
+    assert_eq!(paths(true).len(), 6);
+}
+
+#[test]
+fn walk_read_dir_batch_size() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = || {
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .filter_entry(|each| each.file_name() != "b.txt")
+            .skip_hidden(false)
+    };
+    let expected = local_paths(walk_dir());
+    assert_eq!(expected.len(), 8);
+    for n in [0, 1, 2] {
+        assert_eq!(local_paths(walk_dir().read_dir_batch_size(n)), expected);
+    }
+}
+
+#[test]
+fn walk_filter_entry_before_sort() {
+    let (test_dir, _temp_dir) = test_dir();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let event = |name: &'static str| {
+        let events = events.clone();
+        move || {
+            let mut events = events.lock().unwrap();
+            if events.last() != Some(&name) {
+                events.push(name);
+            }
+        }
+    };
+    let (filter, sort, process) = (event("filter"), event("sort"), event("process"));
+    let walk_dir = WalkDir::new(&test_dir)
+        .parallelism(Parallelism::Serial)
+        .max_depth(1)
+        .filter_entry(move |_| {
+            filter();
+            true
+        })
+        .sort_by(move |a, b| {
+            sort();
+            a.file_name.cmp(&b.file_name)
+        })
+        .process_read_dir(move |_, _, _, _| process());
+    assert_eq!(walk_dir.into_iter().count(), 6);
+    // Entries are filtered as they are read, then sorted
+    assert_eq!(*events.lock().unwrap(), ["filter", "process", "filter", "sort", "process"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_canonicalize() {
+    let (test_dir, _temp_dir) = test_dir();
+    std::os::unix::fs::symlink(test_dir.join("a.txt"), test_dir.join("link")).unwrap();
+    std::os::unix::fs::symlink(test_dir.join("missing"), test_dir.join("broken")).unwrap();
+    for each in WalkDir::new(&test_dir).max_depth(1) {
+        let each = each.unwrap();
+        match each.file_name().to_str().unwrap() {
+            "link" => {
+                assert_eq!(
+                    each.canonicalize().unwrap(),
+                    fs::canonicalize(test_dir.join("a.txt")).unwrap()
+                )
+            }
+            "broken" => {
+                let err = each.canonicalize().unwrap_err();
+                assert_eq!(err.path(), Some(each.path()));
+                assert_eq!(err.io_error().unwrap().kind(), std::io::ErrorKind::NotFound);
+            }
+            _ => assert_eq!(each.canonicalize().unwrap(), fs::canonicalize(each.path()).unwrap()),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_read_link() {
+    let (test_dir, _temp_dir) = test_dir();
+    std::os::unix::fs::symlink("a.txt", test_dir.join("link")).unwrap();
+    std::os::unix::fs::symlink("missing", test_dir.join("broken")).unwrap();
+    for follow_links in [false, true] {
+        let walk_dir = WalkDir::new(&test_dir).max_depth(1).follow_links(follow_links);
+        for each in walk_dir {
+            let each = match each {
+                Ok(each) => each,
+                // Broken link when following links
+                Err(_) => continue,
+            };
+            match each.file_name().to_str().unwrap() {
+                "link" => assert_eq!(each.read_link().unwrap(), PathBuf::from("a.txt")),
+                "broken" => assert_eq!(each.read_link().unwrap(), PathBuf::from("missing")),
+                _ => {
+                    let err = each.read_link().unwrap_err();
+                    assert_eq!(err.path(), Some(each.path()));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn walk_max_recursion_depth() {
+    let (test_dir, _temp_dir) = test_dir();
+    let results: Vec<_> =
+        WalkDir::new(&test_dir).sort(true).max_recursion_depth(1).into_iter().collect();
+    assert_eq!(results.len(), 6);
+    let errors: Vec<_> = results.iter().filter_map(|each| each.as_ref().err()).collect();
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0].is_too_deep());
+    assert_eq!(errors[0].path(), Some(test_dir.join("group 1").as_path()));
+    assert_eq!(errors[0].depth(), 1);
+    assert!(errors[0].io_error().is_none());
+
+    // No errors where max_depth already stops the walk
+    let walk_dir = WalkDir::new(&test_dir).max_recursion_depth(1).max_depth(1);
+    assert!(walk_dir.into_iter().all(|each| each.is_ok()));
+
+    let mut iter = WalkDir::new(&test_dir).max_recursion_depth(0).into_iter();
+    assert!(iter.next().unwrap().unwrap_err().is_too_deep());
+    assert!(iter.next().is_none());
+
+    // Skipped like any other error
+    let walk_dir =
+        WalkDir::new(&test_dir).sort(true).max_recursion_depth(1).on_error(ErrorHandling::Skip);
+    assert_eq!(local_paths(walk_dir), vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)"]);
+    let walk_dir = WalkDir::new(&test_dir).max_recursion_depth(0).on_error(ErrorHandling::Skip);
+    assert_eq!(walk_dir.into_iter().count(), 0);
+}
+
+#[test]
+fn walk_has_preloaded_metadata() {
+    let (test_dir, _temp_dir) = test_dir();
+    for each in WalkDir::new(&test_dir) {
+        let each = each.unwrap();
+        assert_eq!(each.has_preloaded_metadata(), each.depth == 0 || cfg!(windows));
+        each.metadata().unwrap();
+        assert!(each.has_preloaded_metadata());
+    }
+
+    for each in WalkDir::new(&test_dir).sort_order(Sort::Size) {
+        assert!(each.unwrap().has_preloaded_metadata());
+    }
+}
+
+#[test]
+fn walk_dir_entry_file_size() {
+    let (test_dir, _temp_dir) = test_dir();
+    for each in WalkDir::new(&test_dir).sort_order(Sort::Size) {
+        let each = each.unwrap();
+        assert_eq!(each.file_size(), Some(each.metadata().unwrap().len()));
+    }
+
+    #[cfg(unix)]
+    for each in WalkDir::new(&test_dir).min_depth(1) {
+        let each = each.unwrap();
+        assert_eq!(each.file_size(), None);
+        each.metadata().unwrap();
+        assert!(each.file_size().is_some());
+    }
+}
+
+#[test]
+fn walk_preload_metadata() {
+    let (test_dir, _temp_dir) = test_dir();
+    for each in WalkDir::new(&test_dir).preload_metadata(true) {
+        let each = each.unwrap();
+        assert!(each.has_preloaded_metadata());
+        let metadata = fs::symlink_metadata(each.path()).unwrap();
+        assert_eq!(each.file_size(), Some(metadata.len()));
+        assert_eq!(each.modified(), metadata.modified().ok());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(each.metadata().unwrap().ino(), metadata.ino());
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn walk_preload_metadata_links() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch("a/b");
+    dir.symlink_file("a/b", "link");
+
+    // Links are read themselves unless followed
+    let wd = WalkDir::new(dir.path()).preload_metadata(true);
+    let link = wd.into_iter().flatten().find(|each| each.file_name() == "link").unwrap();
+    assert!(link.has_preloaded_metadata());
+    assert!(link.metadata().unwrap().file_type().is_symlink());
+
+    let root = fs::File::open(dir.path()).unwrap();
+    let wd = WalkDir::from_dir(root, dir.path()).preload_metadata(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(r.ents().len(), 4);
+    for each in r.ents() {
+        assert!(each.has_preloaded_metadata());
+        let metadata = fs::symlink_metadata(each.path()).unwrap();
+        assert_eq!(each.file_size(), Some(metadata.len()));
+        assert_eq!(each.metadata().unwrap().file_type(), metadata.file_type());
+    }
+}
+
+#[test]
+fn walk_max_entries_per_dir() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(&test_dir).sort(true).max_entries_per_dir(1));
+    assert_eq!(paths, vec![" (0)", "a.txt (1)"]);
+
+    let paths =
+        local_paths(WalkDir::new(&test_dir).sort(true).sort_reverse(true).max_entries_per_dir(2));
+    assert_eq!(
+        paths,
+        vec![" (0)", "group 2 (1)", "group 2/e.txt (2)", "group 1 (1)", "group 1/d.txt (2)"]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_max_entries_per_dir_keeps_errors() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["a", "b"]);
+    dir.symlink_file("missing", "dangling");
+
+    let wd = WalkDir::new(dir.path()).sort(true).follow_links(true).max_entries_per_dir(1);
+    let r = dir.run_recursive(wd);
+    assert_eq!(r.paths(), vec![dir.path().to_path_buf(), dir.join("a")]);
+    assert_eq!(r.errs().len(), 1);
+    assert_eq!(r.errs()[0].path(), Some(dir.join("dangling").as_path()));
+}
+
+#[test]
+fn walk_into_dir_iter() {
+    let (test_dir, _temp_dir) = test_dir();
+    let dirs: Vec<_> = WalkDir::new(&test_dir)
+        .sort(true)
+        .into_dir_iter()
+        .map(|(spec, results)| {
+            let names: Vec<_> = results
+                .into_iter()
+                .map(|each| each.unwrap().file_name.to_string_lossy().into_owned())
+                .collect();
+            (spec.path.strip_prefix(&test_dir).unwrap().to_path_buf(), spec.depth, names)
+        })
+        .collect();
+    assert_eq!(
+        dirs,
+        vec![
+            (
+                PathBuf::new(),
+                0,
+                vec![
+                    "a.txt".into(),
+                    "b.txt".into(),
+                    "c.txt".into(),
+                    "group 1".into(),
+                    "group 2".into()
+                ]
+            ),
+            (PathBuf::from("group 1"), 1, vec!["d.txt".into()]),
+            (PathBuf::from("group 2"), 1, vec!["e.txt".into()]),
+        ]
+    );
+
+    let dirs: Vec<_> = WalkDir::new(&test_dir)
+        .sort(true)
+        .min_depth(2)
+        .into_dir_iter()
+        .map(|(_, results)| results.len())
+        .collect();
+    assert_eq!(dirs, vec![0, 1, 1]);
+}
+
+#[test]
+fn walk_into_dir_iter_max_depth() {
+    let (test_dir, _temp_dir) = test_dir();
+    // Directories at max_depth aren't read, so no empty contents are yielded
+    // for them
+    let dirs: Vec<_> = WalkDir::new(&test_dir)
+        .sort(true)
+        .max_depth(1)
+        .into_dir_iter()
+        .map(|(spec, results)| (spec.depth, results.len()))
+        .collect();
+    assert_eq!(dirs, vec![(0, 5)]);
+
+    let dirs: Vec<_> = WalkDir::new(&test_dir)
+        .sort(true)
+        .max_depth(2)
+        .into_dir_iter()
+        .map(|(spec, results)| (spec.depth, results.len()))
+        .collect();
+    assert_eq!(dirs, vec![(0, 5), (1, 1), (1, 1)]);
+}
+
+#[test]
+fn walk_into_std_iter() {
+    let (test_dir, _temp_dir) = test_dir();
+    fs::write(test_dir.join(".hidden"), "h").unwrap();
+    fs::create_dir(test_dir.join("group 1/nested")).unwrap();
+    fs::write(test_dir.join("group 1/nested/f.txt"), "f").unwrap();
+
+    // Listing order isn't sorted, so compare against the general path sorted
+    let std_paths = |walk_dir: WalkDir| {
+        let mut paths: Vec<_> = walk_dir
+            .into_std_iter()
+            .map(|each| each.unwrap().path().strip_prefix(&test_dir).unwrap().to_path_buf())
+            .collect();
+        paths.sort();
+        paths
+    };
+    let jwalk_paths = |walk_dir: WalkDir| {
+        let mut paths: Vec<_> = walk_dir
+            .min_depth(1)
+            .into_iter()
+            .map(|each| each.unwrap().path().strip_prefix(&test_dir).unwrap().to_path_buf())
+            .collect();
+        paths.sort();
+        paths
+    };
+
+    assert_eq!(std_paths(WalkDir::new(&test_dir)), jwalk_paths(WalkDir::new(&test_dir)));
+    assert_eq!(std_paths(WalkDir::new(&test_dir)).len(), 9);
+    assert_eq!(
+        std_paths(WalkDir::new(&test_dir).max_depth(1)),
+        jwalk_paths(WalkDir::new(&test_dir).max_depth(1))
+    );
+    assert_eq!(
+        std_paths(WalkDir::new(&test_dir).min_depth(2).max_depth(2)),
+        vec![PathBuf::from("group 1/d.txt"), "group 1/nested".into(), "group 2/e.txt".into()]
+    );
+    assert_eq!(
+        std_paths(WalkDir::new(&test_dir).skip_hidden(false)),
+        jwalk_paths(WalkDir::new(&test_dir).skip_hidden(false))
+    );
+    assert!(std_paths(WalkDir::new(&test_dir).max_depth(0)).is_empty());
+    assert!(std_paths(WalkDir::new(test_dir.join("a.txt"))).is_empty());
+
+    // Each directory is yielded before its contents
+    let paths: Vec<_> =
+        WalkDir::new(&test_dir).into_std_iter().map(|each| each.unwrap().path()).collect();
+    let position =
+        |path: &str| paths.iter().position(|each| *each == test_dir.join(path)).unwrap();
+    assert!(position("group 1") < position("group 1/nested"));
+    assert!(position("group 1/nested") < position("group 1/nested/f.txt"));
+
+    let mut iter = WalkDir::new(test_dir.join("missing")).into_std_iter();
+    let err = iter.next().unwrap().unwrap_err();
+    assert_eq!(err.depth(), 0);
+    assert_eq!(err.path(), Some(test_dir.join("missing").as_path()));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn walk_for_each() {
+    let (test_dir, _temp_dir) = test_dir();
+    fs::write(test_dir.join(".hidden"), "h").unwrap();
+    fs::create_dir(test_dir.join("group 1/nested")).unwrap();
+
+    let visit = |each: &DirEntry<((), ())>| {
+        (
+            each.path().strip_prefix(&test_dir).unwrap().display().to_string(),
+            each.depth(),
+            each.child_count(),
+            each.sibling_index(),
+            each.is_last_sibling(),
+        )
+    };
+    let lent = |walk_dir: WalkDir| {
+        let mut visits = Vec::new();
+        walk_dir.for_each(|each| visits.push(visit(each.unwrap())));
+        visits
+    };
+    let owned = |walk_dir: WalkDir| -> Vec<_> {
+        let walk_dir = walk_dir.parallelism(Parallelism::Serial);
+        walk_dir.into_iter().map(|each| visit(&each.unwrap())).collect()
+    };
+
+    let walk_dir = || WalkDir::new(&test_dir).sort(true);
+    assert_eq!(lent(walk_dir()), owned(walk_dir()));
+    assert_eq!(lent(walk_dir()).len(), 9);
+    assert_eq!(lent(walk_dir().skip_hidden(false)), owned(walk_dir().skip_hidden(false)));
+    let shallow = || walk_dir().min_depth(1).max_depth(1);
+    assert_eq!(lent(shallow()), owned(shallow()));
+    assert_eq!(lent(walk_dir().include_root(false)), owned(walk_dir().include_root(false)));
+    assert_eq!(lent(walk_dir().first_n(3)), owned(walk_dir().first_n(3)));
+    let file = || WalkDir::new(test_dir.join("a.txt"));
+    assert_eq!(lent(file()), owned(file()));
+
+    // Only sorting by name is honoured
+    let sorted = lent(walk_dir());
+    assert_eq!(lent(walk_dir().sort_order(Sort::Name)), sorted);
+    assert_eq!(lent(walk_dir().sort_reverse(true)), sorted);
+    assert_eq!(lent(walk_dir().sort_by(|a, b| b.file_name.cmp(&a.file_name))), sorted);
+    assert_eq!(lent(walk_dir().sort_by_key(|each| std::cmp::Reverse(each.depth))), sorted);
+
+    let mut errors = Vec::new();
+    WalkDir::new(test_dir.join("missing")).for_each(|each| {
+        errors.push(each.unwrap_err().path().map(|path| path.to_path_buf()));
+    });
+    assert_eq!(errors, vec![Some(test_dir.join("missing"))]);
+}
+
+#[test]
+fn walk_clone() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(&test_dir).sort(true).skip_hidden(false);
+    let cloned = walk_dir.clone();
+    let paths = local_paths(cloned);
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/.hidden_file.txt (2)",
+            "group 2/e.txt (2)",
+        ]
+    );
+    assert_eq!(local_paths(walk_dir.clone().max_depth(1)).len(), 6);
+    assert_eq!(local_paths(walk_dir), paths);
+}
+
+#[test]
+fn walk_clone_keeps_sort_order() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(&test_dir).sort_order(Sort::Size).sort_reverse(true);
+    // Sorting by size reads the metadata of every entry
+    for each in walk_dir.clone() {
+        assert!(each.unwrap().has_preloaded_metadata());
+    }
+    assert_eq!(local_paths(walk_dir.clone()), local_paths(walk_dir));
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_filter_entry_before_metadata() {
+    let (test_dir, _temp_dir) = test_dir();
+    let preloaded = Arc::new(AtomicUsize::new(0));
+    let preloaded_clone = preloaded.clone();
+    let paths = local_paths(WalkDir::new(&test_dir).sort(true).min_file_size(0).filter_entry(
+        move |each| {
+            if each.depth > 0 && each.has_preloaded_metadata() {
+                preloaded_clone.fetch_add(1, AtomicOrdering::SeqCst);
+            }
+            each.file_name != "group 1"
+        },
+    ));
+    assert_eq!(preloaded.load(AtomicOrdering::SeqCst), 0);
+    assert_eq!(
+        paths,
+        vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)", "group 2 (1)", "group 2/e.txt (2)"]
+    );
+}
+
+#[cfg(windows)]
+#[test]
+fn walk_windows_long_paths() {
+    let dir = Dir::tmp();
+    let long_name = "a".repeat(100);
+    let nested: PathBuf = std::iter::repeat(long_name.as_str()).take(4).collect();
+    // Created through the extended-length path, the plain path is too long
+    let verbatim_root = PathBuf::from(format!(r"\\?\{}", dir.path().display()));
+    fs::create_dir_all(verbatim_root.join(&nested)).unwrap();
+    fs::File::create(verbatim_root.join(&nested).join("deep.txt")).unwrap();
+    assert!(dir.join(&nested).join("deep.txt").as_os_str().len() > 260);
+
+    let r = dir.run_recursive(WalkDir::new(dir.path()).windows_long_paths(true));
+    r.assert_no_errors();
+    let deep = r.ents().iter().find(|each| each.file_name == "deep.txt").unwrap();
+    assert_eq!(deep.depth, 5);
+    assert!(deep.path().to_string_lossy().starts_with(r"\\?\"));
+    assert!(deep.metadata().is_ok());
+}
+
+#[test]
+fn walk_include_root() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(&test_dir).sort(true).include_root(false));
+    assert_eq!(
+        paths,
+        vec![
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+    assert_eq!(WalkDir::new(&test_dir).include_root(false).into_par_iter().count(), 7);
+    assert_eq!(WalkDir::new(&test_dir).include_root(false).max_depth(0).into_iter().count(), 0);
+    assert!(WalkDir::new(test_dir.join("missing"))
+        .include_root(false)
+        .into_iter()
+        .next()
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn walk_hidden_predicate() {
+    let dir = Dir::tmp();
+    dir.mkdirp("backup~");
+    dir.touch_all(&[".a", "b", "c~", "backup~/d"]);
+
+    let hidden =
+        |dir_entry: &DirEntry<((), ())>| dir_entry.file_name.to_string_lossy().ends_with('~');
+    let wd = WalkDir::new(dir.path()).sort(true).hidden_predicate(hidden);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(vec![dir.path().to_path_buf(), dir.join(".a"), dir.join("b")], r.paths());
+
+    let wd = WalkDir::new(dir.path()).sort(true).hidden_predicate(hidden).skip_hidden(false);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    assert_eq!(r.paths().len(), 6);
+}
+
+#[test]
+fn walk_file_size() {
+    let dir = Dir::tmp();
+    dir.mkdirp("small/nested");
+    dir.touch_all(&["empty", "small/nested/empty"]);
+    fs::write(dir.join("big"), vec![0; 100]).unwrap();
+    fs::write(dir.join("small/one"), "1").unwrap();
+
+    let wd = WalkDir::new(dir.path()).sort(true).min_file_size(1);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("big"),
+        dir.join("small"),
+        dir.join("small/nested"),
+        dir.join("small/one"),
+    ];
+    assert_eq!(expected, r.paths());
+
+    let wd = WalkDir::new(dir.path()).sort(true).min_file_size(1).max_file_size(99);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("small"),
+        dir.join("small/nested"),
+        dir.join("small/one"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[test]
+fn walk_modified_after() {
+    let dir = Dir::tmp();
+    dir.mkdirp("old/nested");
+    dir.touch_all(&["a", "old/b", "old/nested/c"]);
+    let now = SystemTime::now();
+    let hour = Duration::from_secs(3600);
+    for path in ["a", "old/b"] {
+        let file = fs::File::options().write(true).open(dir.join(path)).unwrap();
+        file.set_modified(now - hour).unwrap();
+    }
+
+    let wd = WalkDir::new(dir.path()).sort(true).modified_after(now - hour / 2);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("old"),
+        dir.join("old/nested"),
+        dir.join("old/nested/c"),
+    ];
+    assert_eq!(expected, r.paths());
+
+    let wd = WalkDir::new(dir.path())
+        .sort(true)
+        .modified_after(now - hour * 2)
+        .modified_before(now - hour / 2);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("old"),
+        dir.join("old/b"),
+        dir.join("old/nested"),
+    ];
+    assert_eq!(expected, r.paths());
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn walk_from_dir() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.touch_all(&["a/b/c", "d"]);
+    dir.symlink_dir("a", "link");
+
+    let root = fs::File::open(dir.path()).unwrap();
+    let wd = WalkDir::from_dir(root, dir.path()).sort(true).follow_links(true);
+    let r = dir.run_recursive(wd);
+    r.assert_no_errors();
+    let expected = vec![
+        dir.path().to_path_buf(),
+        dir.join("a"),
+        dir.join("a/b"),
+        dir.join("a/b/c"),
+        dir.join("d"),
+        dir.join("link"),
+    ];
+    assert_eq!(expected, r.paths());
+    let link = r.ents().iter().find(|each| each.file_name() == "link").unwrap();
+    assert!(link.file_type().is_symlink());
+    assert!(link.metadata().unwrap().file_type().is_symlink());
+    // Types and inode numbers come from the listing
+    for each in r.ents() {
+        use std::os::unix::fs::MetadataExt;
+        let metadata = fs::symlink_metadata(each.path()).unwrap();
+        assert_eq!(each.file_type(), metadata.file_type());
+        assert_eq!(each.ino(), metadata.ino());
+    }
+
+    // Entries are named by the given path, but read through the file
+    // descriptor
+    let root = fs::File::open(dir.join("a")).unwrap();
+    let paths: Vec<_> = WalkDir::from_dir(root, "renamed")
+        .sort(true)
+        .into_iter()
+        .map(|each| each.unwrap().path)
+        .collect();
+    assert_eq!(
+        paths,
+        vec![PathBuf::from("renamed"), PathBuf::from("renamed/b"), PathBuf::from("renamed/b/c")]
+    );
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn walk_from_dir_closes_read_dirs() {
+    let dir = Dir::tmp();
+    for i in 0..200 {
+        dir.mkdirp(format!("{}/child", i));
+    }
+    let open_fds = || fs::read_dir("/proc/self/fd").unwrap().count();
+
+    let before = open_fds();
+    let root = fs::File::open(dir.path()).unwrap();
+    let entries: Vec<_> = WalkDir::from_dir(root, dir.path()).into_iter().collect();
+    assert_eq!(entries.len(), 401);
+    // Collected entries don't keep their parent directories open. Other
+    // tests may open a few files meanwhile.
+    assert!(open_fds() < before + 100);
+}
+
+#[test]
+fn walk_sort_errors_last() {
+    let dir = Dir::tmp();
+    dir.touch_all(&["a", "c"]);
+    dir.symlink_file("missing", "b");
+
+    let names = |sort_errors_last: bool, sort_reverse: bool| -> Vec<String> {
+        WalkDir::new(dir.path())
+            .sort(true)
+            .follow_links(true)
+            .min_depth(1)
+            .sort_errors_last(sort_errors_last)
+            .sort_reverse(sort_reverse)
+            .into_iter()
+            .map(|each| match each {
+                Ok(each) => each.file_name.to_str().unwrap().to_owned(),
+                Err(err) => format!(
+                    "{} (error)",
+                    err.path().unwrap().file_name().unwrap().to_str().unwrap()
+                ),
+            })
+            .collect()
+    };
+    assert_eq!(names(true, false), vec!["a", "c", "b (error)"]);
+    assert_eq!(names(false, false), vec!["a", "b (error)", "c"]);
+    assert_eq!(names(false, true), vec!["c", "b (error)", "a"]);
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_owner_and_nlink() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = Dir::tmp();
+    dir.touch("a");
+    fs::hard_link(dir.join("a"), dir.join("b")).unwrap();
+    for each in WalkDir::new(dir.path()).min_depth(1) {
+        let each = each.unwrap();
+        let metadata = fs::metadata(each.path()).unwrap();
+        assert_eq!(each.uid().unwrap(), metadata.uid());
+        assert_eq!(each.gid().unwrap(), metadata.gid());
+        assert_eq!(each.nlink().unwrap(), 2);
+    }
+}
+
+#[test]
+fn walk_resume() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = || WalkDir::new(&test_dir).sort(true).skip_hidden(false);
+    let expected: Vec<_> = walk_dir()
+        .into_iter()
+        .map(|each| each.unwrap())
+        .map(|each| (each.path.clone(), each.depth))
+        .collect();
+
+    let mut paths = Vec::new();
+    let mut iter = walk_dir().into_iter();
+    loop {
+        for each in iter.by_ref().take(2) {
+            let each = each.unwrap();
+            paths.push((each.path.clone(), each.depth));
+        }
+        let state = iter.state().unwrap();
+        #[cfg(feature = "serde")]
+        let state: WalkState =
+            serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        if state.is_empty() {
+            break;
+        }
+        iter = WalkDir::resume(state).sort(true).skip_hidden(false).into_iter();
+    }
+    assert_eq!(paths, expected);
+
+    let mut iter = walk_dir().contents_first(true).into_iter();
+    iter.next();
+    assert!(iter.state().is_none());
+
+    let mut iter = walk_dir().first_n(2).into_iter();
+    assert_eq!(iter.by_ref().count(), 2);
+    assert_eq!(
+        WalkDir::resume(iter.state().unwrap()).sort(true).skip_hidden(false).into_iter().count(),
+        7
+    );
+}
+
+#[test]
+fn walk_resume_skip_dirs() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a/b");
+    dir.mkdirp("a/node_modules/x");
+    dir.touch("a/node_modules/x/f");
+    dir.mkdirp("z");
+    dir.touch("z/g");
+
+    let walk_dir = || {
+        WalkDir::new(dir.path())
+            .sort(true)
+            .parallelism(Parallelism::Serial)
+            .skip_dirs(["node_modules"])
+            .filter_map_entry(|each| match each.file_name.to_str() {
+                Some("b") => Action::SkipContents,
+                Some("z") => Action::Skip,
+                _ => Action::Keep,
+            })
+    };
+    let paths = |iter: &mut DirEntryIter<((), ())>| -> Vec<_> {
+        iter.map(|each| each.unwrap().path().strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect()
+    };
+    let expected: Vec<PathBuf> =
+        vec!["".into(), "a".into(), "a/b".into(), "a/node_modules".into(), "z/g".into()];
+    assert_eq!(paths(&mut walk_dir().into_iter()), expected);
+
+    // Resume once `a` is yielded, while its contents are pending
+    let mut iter = walk_dir().into_iter();
+    assert_eq!(iter.by_ref().take(2).count(), 2);
+    let state = iter.state().unwrap();
+    #[cfg(feature = "serde")]
+    let state: WalkState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+    let resumed = WalkDir::resume(state)
+        .sort(true)
+        .parallelism(Parallelism::Serial)
+        .skip_dirs(["node_modules"])
+        .filter_map_entry(|each| match each.file_name.to_str() {
+            Some("b") => Action::SkipContents,
+            Some("z") => Action::Skip,
+            _ => Action::Keep,
+        });
+    assert_eq!(paths(&mut resumed.into_iter()), &expected[2..]);
+}
+
+#[test]
+fn walk_root_index() {
+    let (test_dir, _temp_dir) = test_dir();
+    let roots = vec![test_dir.join("group 2"), test_dir.join("group 1"), test_dir.join("group 2")];
+    let walk_dir = || WalkDir::from_roots(&roots).sort(true);
+    let root_indices: Vec<_> = walk_dir()
+        .into_iter()
+        .map(|each| {
+            let each = each.unwrap();
+            (each.file_name.to_str().unwrap().to_owned(), each.root_index())
+        })
+        .collect();
+    assert_eq!(
+        root_indices,
+        vec![
+            ("group 2".to_owned(), 0),
+            ("e.txt".to_owned(), 0),
+            ("group 1".to_owned(), 1),
+            ("d.txt".to_owned(), 1),
+            ("group 2".to_owned(), 2),
+            ("e.txt".to_owned(), 2),
+        ]
+    );
+
+    let mut iter = walk_dir().into_iter();
+    assert_eq!(iter.by_ref().take(3).count(), 3);
+    let resumed: Vec<_> = WalkDir::resume(iter.state().unwrap())
+        .sort(true)
+        .into_iter()
+        .map(|each| each.unwrap().root_index())
+        .collect();
+    assert_eq!(resumed, vec![1, 2, 2]);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn walk_into_stream() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn collect<S: Stream + Unpin>(mut stream: S, limit: usize) -> Vec<S::Item> {
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut items = Vec::new();
+        while items.len() < limit {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => break,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+        items
+    }
+
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(&test_dir).sort(true);
+    let expected: Vec<_> = walk_dir.clone().into_iter().map(|each| each.unwrap().path).collect();
+    let paths: Vec<_> = collect(walk_dir.clone().into_stream(), usize::MAX)
+        .into_iter()
+        .map(|each| each.unwrap().path)
+        .collect();
+    assert_eq!(paths, expected);
+
+    // Dropping the stream early stops the walk
+    assert_eq!(collect(walk_dir.into_stream(), 2).len(), 2);
+}
+
+#[cfg(all(feature = "unicode", unix))]
+#[test]
+fn walk_normalize_names() {
+    let dir = Dir::tmp();
+    // Names stored decomposed, patterns written composed
+    dir.mkdirp("re\u{301}sume\u{301}");
+    dir.touch_all(&["cafe\u{301}.txt", "re\u{301}sume\u{301}/a.txt"]);
+    let names = |walk_dir: WalkDir| {
+        let mut names: Vec<_> = walk_dir
+            .min_depth(1)
+            .into_iter()
+            .map(|each| each.unwrap().file_name.into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    };
+
+    let walk_dir = || WalkDir::new(dir.path()).glob_include(["caf\u{e9}.txt"]);
+    assert_eq!(names(walk_dir()), vec!["re\u{301}sume\u{301}"]);
+    assert_eq!(
+        names(walk_dir().normalize_names(NormalizationForm::Nfc)),
+        vec!["cafe\u{301}.txt", "re\u{301}sume\u{301}"]
+    );
+
+    let walk_dir = || WalkDir::new(dir.path()).skip_dirs(["r\u{e9}sum\u{e9}"]);
+    assert_eq!(names(walk_dir()).len(), 3);
+    assert_eq!(names(walk_dir().normalize_names(NormalizationForm::Nfd)).len(), 2);
+}
+
+#[test]
+fn walk_background() {
+    let (test_dir, _temp_dir) = test_dir();
+    let read_threads = Arc::new(Mutex::new(Vec::new()));
+    let read_threads_clone = read_threads.clone();
+    let walk_dir = WalkDir::new(&test_dir).parallelism(Parallelism::Serial).sort(true).on_read_dir(
+        move |_, _| read_threads_clone.lock().unwrap().push(std::thread::current().id()),
+    );
+    assert_eq!(local_paths(walk_dir.clone().background(true)), local_paths(walk_dir.clone()));
+
+    read_threads.lock().unwrap().clear();
+    let _ = local_paths(walk_dir.clone());
+    assert!(read_threads.lock().unwrap().iter().all(|id| *id == std::thread::current().id()));
+
+    read_threads.lock().unwrap().clear();
+    let _ = local_paths(walk_dir.clone().background(true));
+    assert_eq!(read_threads.lock().unwrap().len(), 3);
+    assert!(read_threads.lock().unwrap().iter().all(|id| *id != std::thread::current().id()));
+
+    // Dropping the iterator early stops the background thread
+    let mut iter = walk_dir.background(true).max_queued_entries(1).into_iter();
+    assert!(iter.next().unwrap().is_ok());
+    drop(iter);
+}
+
+#[cfg(unix)]
+#[test]
+fn walk_detect_changes() {
+    let dir = Dir::tmp();
+    dir.mkdirp("a");
+    dir.touch_all(&["a/x", "a/y", "b"]);
+    let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    fs::File::open(dir.join("a")).unwrap().set_modified(old).unwrap();
+
+    let created = Arc::new(AtomicBool::new(false));
+    let new_file = dir.join("a/z");
+    let stale: Vec<_> = WalkDir::new(dir.path())
+        .sort(true)
+        .detect_changes(true)
+        .read_dir_batch_size(1)
+        // Changes "a" while it is being read
+        .filter_entry(move |each| {
+            if each.depth == 2 && !created.swap(true, AtomicOrdering::SeqCst) {
+                fs::File::create(&new_file).unwrap();
+            }
+            true
+        })
+        .into_iter()
+        .map(|each| {
+            let each = each.unwrap();
+            let possibly_stale = each.possibly_stale();
+            (each.file_name.into_string().unwrap(), possibly_stale)
+        })
+        .filter(|(_, possibly_stale)| *possibly_stale)
+        .collect();
+    assert!(stale.len() >= 2);
+    assert!(stale.iter().all(|(name, _)| ["x", "y", "z"].contains(&name.as_str())));
+
+    let walk_dir = WalkDir::new(dir.path()).detect_changes(true);
+    assert!(walk_dir.into_iter().all(|each| !each.unwrap().possibly_stale()));
+}
+
+#[test]
+fn walk_detect_changes_with_reader() {
+    let (test_dir, _temp_dir) = test_dir();
+
+    // Adds a directory that doesn't exist, containing one file
+    struct Reader;
+
+    impl DirReader<((), ())> for Reader {
+        fn read_dir(
+            &self,
+            read_dir_spec: &ReadDirSpec<((), ())>,
+        ) -> Result<Vec<Result<DirEntry<((), ())>>>> {
+            if read_dir_spec.depth > 0 {
+                let file_type = fs::metadata(file!()).unwrap().file_type();
+                return Ok(vec![Ok(DirEntry::new(read_dir_spec, "file".into(), file_type))]);
+            }
+            let dir_type = fs::metadata(".").unwrap().file_type();
+            Ok(vec![Ok(DirEntry::new(read_dir_spec, "virtual".into(), dir_type))])
+        }
+    }
+
+    let entries: Vec<_> = WalkDir::new(&test_dir)
+        .with_reader(Reader)
+        .detect_changes(true)
+        .into_iter()
+        .map(|each| each.unwrap())
+        .collect();
+    assert_eq!(entries.len(), 3);
+    assert!(entries.iter().all(|each| !each.possibly_stale()));
+}
+
+#[test]
+fn walk_with_precount() {
+    let (test_dir, _temp_dir) = test_dir();
+    let (count, iter) = WalkDir::new(&test_dir).skip_hidden(false).with_precount().unwrap();
+    assert_eq!(count, 9);
+    assert_eq!(iter.filter(Result::is_ok).count(), count);
+
+    let (count, _) = WalkDir::new(&test_dir).max_depth(1).with_precount().unwrap();
+    assert_eq!(count, 6);
+
+    let dir = Dir::tmp();
+    dir.mkdirp("empty/nested");
+    dir.touch_all(&["a"]);
+    let (count, iter) = WalkDir::new(dir.path()).skip_empty_dirs(true).with_precount().unwrap();
+    assert_eq!(count, iter.count());
+    assert_eq!(count, 2);
+
+    assert!(WalkDir::new(test_dir.join("missing")).with_precount().is_err());
+}
+
+#[test]
+fn walk_filter_map_entry() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = |action: Action| {
+        local_paths(WalkDir::new(&test_dir).sort(true).filter_map_entry(move |each| {
+            if each.file_name == "group 1" {
+                action
+            } else {
+                Action::Keep
+            }
+        }))
+    };
+    let with_group_1 = |group_1: &[&'static str]| {
+        let mut expected = vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)"];
+        expected.extend(group_1);
+        expected.extend(["group 2 (1)", "group 2/e.txt (2)"]);
+        expected
+    };
+    assert_eq!(paths(Action::Keep), with_group_1(&["group 1 (1)", "group 1/d.txt (2)"]));
+    assert_eq!(paths(Action::Skip), with_group_1(&["group 1/d.txt (2)"]));
+    assert_eq!(paths(Action::SkipSubtree), with_group_1(&[]));
+    assert_eq!(paths(Action::SkipContents), with_group_1(&["group 1 (1)"]));
+
+    // Skipped directories stay skipped with a file type filter
+    let paths = local_paths(
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .file_type_filter(FileTypeFilter::Dirs)
+            .filter_map_entry(|each| if each.depth == 0 { Action::Skip } else { Action::Keep }),
+    );
+    assert_eq!(paths, vec!["group 1 (1)", "group 2 (1)"]);
+}
+
+#[test]
+fn walk_with_reader() {
+    let (test_dir, _temp_dir) = test_dir();
+
+    // Hides `a.txt` and adds a file that doesn't exist to each directory
+    struct Reader {
+        file_type: fs::FileType,
+    }
+
+    impl DirReader<((), ())> for Reader {
+        fn read_dir(
+            &self,
+            read_dir_spec: &ReadDirSpec<((), ())>,
+        ) -> Result<Vec<Result<DirEntry<((), ())>>>> {
+            let mut dir_entry_results = FsDirReader.read_dir(read_dir_spec)?;
+            dir_entry_results.retain(|each| each.as_ref().unwrap().file_name != "a.txt");
+            dir_entry_results.push(Ok(DirEntry::new(
+                read_dir_spec,
+                "virtual.txt".into(),
+                self.file_type,
+            )));
+            Ok(dir_entry_results)
+        }
+    }
+
+    let file_type = fs::metadata(test_dir.join("a.txt")).unwrap().file_type();
+    let paths = local_paths(WalkDir::new(&test_dir).sort(true).with_reader(Reader { file_type }));
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 1/virtual.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+            "group 2/virtual.txt (2)",
+            "virtual.txt (1)",
+        ]
+    );
+}
+
+#[test]
+fn walk_is_finished() {
+    let (test_dir, _temp_dir) = test_dir();
+
+    let mut iter = WalkDir::new(&test_dir).into_iter();
+    assert_eq!(iter.by_ref().take(7).count(), 7);
+    assert!(!iter.is_finished());
+    assert!(iter.next().is_some());
+    assert!(!iter.is_finished());
+    assert!(iter.next().is_none());
+    assert!(iter.is_finished());
+
+    // Finished as soon as the last requested entry is yielded
+    let mut iter = WalkDir::new(&test_dir).first_n(2).into_iter();
+    assert!(iter.next().is_some());
+    assert!(!iter.is_finished());
+    assert!(iter.next().is_some());
+    assert!(iter.is_finished());
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn walk_into_iter_with_capacity() {
+    let (test_dir, _temp_dir) = test_dir();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir = WalkDir::new(&test_dir).sort(true).parallelism(parallelism);
+        let paths = |iter: DirEntryIter<((), ())>| -> Vec<_> {
+            iter.map(|each| each.unwrap().path().to_path_buf()).collect()
+        };
+        assert_eq!(
+            paths(walk_dir.clone().into_iter_with_capacity(1024)),
+            paths(walk_dir.into_iter())
+        );
+    }
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn walk_path_regex_exclude() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = |regex: &str| {
+        local_paths(
+            WalkDir::new(&test_dir)
+                .sort(true)
+                .path_regex_exclude(regex::Regex::new(regex).unwrap()),
+        )
+    };
+    assert_eq!(paths(r"^group \d$"), vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)"]);
+    assert_eq!(
+        paths(r"[ace]\.txt$"),
+        vec![" (0)", "b.txt (1)", "group 1 (1)", "group 1/d.txt (2)", "group 2 (1)"]
+    );
+}
+
+#[test]
+fn walk_child_count() {
+    let (test_dir, _temp_dir) = test_dir();
+    fs::create_dir(test_dir.join("empty")).unwrap();
+    let child_counts = |walk_dir: WalkDir| -> Vec<_> {
+        walk_dir
+            .sort(true)
+            .into_iter()
+            .map(|each| {
+                let each = each.unwrap();
+                assert_eq!(each.is_empty_dir(), each.child_count() == Some(0));
+                (each.relative_path().to_path_buf(), each.child_count())
+            })
+            .filter(|(_, child_count)| child_count.is_some())
+            .collect()
+    };
+    let counts = |counts: &[(&str, usize)]| -> Vec<_> {
+        counts.iter().map(|(path, count)| (PathBuf::from(path), Some(*count))).collect()
+    };
+
+    assert_eq!(
+        child_counts(WalkDir::new(&test_dir)),
+        counts(&[("", 6), ("empty", 0), ("group 1", 1), ("group 2", 1)])
+    );
+    assert_eq!(
+        child_counts(WalkDir::new(&test_dir).contents_first(true)),
+        counts(&[("empty", 0), ("group 1", 1), ("group 2", 1), ("", 6)])
+    );
+    // Filtered entries aren't counted, unread directories have no count
+    assert_eq!(
+        child_counts(WalkDir::new(&test_dir).filter_entry(|each| each.file_name() != "d.txt")),
+        counts(&[("", 6), ("empty", 0), ("group 1", 0), ("group 2", 1)])
+    );
+    assert_eq!(child_counts(WalkDir::new(&test_dir).max_depth(1)), counts(&[("", 6)]));
+    // Entries that are kept but not yielded are counted
+    assert_eq!(
+        child_counts(WalkDir::new(&test_dir).filter_map_entry(|each| {
+            if each.file_name() == "d.txt" {
+                Action::Skip
+            } else {
+                Action::Keep
+            }
+        })),
+        counts(&[("", 6), ("empty", 0), ("group 1", 1), ("group 2", 1)])
+    );
+
+    let mut par_child_counts: Vec<_> = WalkDir::new(&test_dir)
+        .into_par_iter()
+        .map(|each| {
+            let each = each.unwrap();
+            (each.relative_path().to_path_buf(), each.child_count())
+        })
+        .filter(|(_, child_count)| child_count.is_some())
+        .collect();
+    par_child_counts.sort();
+    assert_eq!(par_child_counts, counts(&[("", 6), ("empty", 0), ("group 1", 1), ("group 2", 1)]));
+}
+
+#[test]
+fn walk_child_count_skipped_read_error() {
+    let (test_dir, _temp_dir) = test_dir();
+
+    // Fails to read "group 1"
+    struct Reader;
+
+    impl DirReader<((), ())> for Reader {
+        fn read_dir(
+            &self,
+            read_dir_spec: &ReadDirSpec<((), ())>,
+        ) -> Result<Vec<Result<DirEntry<((), ())>>>> {
+            if read_dir_spec.path.ends_with("group 1") {
+                let missing = read_dir_spec.path.join("missing");
+                return Err(WalkDir::new(missing).into_iter().next().unwrap().unwrap_err());
+            }
+            FsDirReader.read_dir(read_dir_spec)
+        }
+    }
+
+    let group_1 = WalkDir::new(&test_dir)
+        .with_reader(Reader)
+        .on_error(ErrorHandling::Skip)
+        .into_iter()
+        .map(Result::unwrap)
+        .find(|each| each.file_name() == "group 1")
+        .unwrap();
+    assert_eq!(group_1.child_count(), None);
+    assert!(!group_1.is_empty_dir());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let unreadable = test_dir.join("unreadable");
+        fs::create_dir(&unreadable).unwrap();
+        fs::set_permissions(&unreadable, fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions don't apply to root
+        if fs::read_dir(&unreadable).is_err() {
+            let unreadable_entry = WalkDir::new(&test_dir)
+                .on_error(ErrorHandling::Skip)
+                .into_iter()
+                .map(Result::unwrap)
+                .find(|each| each.file_name() == "unreadable")
+                .unwrap();
+            assert_eq!(unreadable_entry.child_count(), None);
+            assert!(!unreadable_entry.is_empty_dir());
+        }
+        fs::set_permissions(&unreadable, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+#[test]
+fn walk_max_depth_dirs_unread() {
+    let (test_dir, _temp_dir) = test_dir();
+    fs::create_dir(test_dir.join("group 1/nested")).unwrap();
+    fs::write(test_dir.join("group 1/nested/f.txt"), "").unwrap();
+    for parallelism in [Parallelism::Serial, Parallelism::RayonNewPool(2)] {
+        let walk_dir =
+            || WalkDir::new(&test_dir).sort(true).max_depth(2).parallelism(parallelism.clone());
+
+        // Directories at max_depth are still yielded, but never read
+        let paths = local_paths(walk_dir().skip_empty_dirs(true));
+        assert_eq!(
+            paths,
+            vec![
+                " (0)",
+                "a.txt (1)",
+                "b.txt (1)",
+                "c.txt (1)",
+                "group 1 (1)",
+                "group 1/d.txt (2)",
+                "group 2 (1)",
+                "group 2/e.txt (2)",
+            ]
+        );
+        let nested = walk_dir()
+            .into_iter()
+            .map(Result::unwrap)
+            .find(|each| each.file_name == "nested")
+            .unwrap();
+        assert_eq!(nested.child_count(), None);
+        assert!(nested.read_children_error.is_none());
+        assert_eq!(walk_dir().into_dir_iter().count(), 3);
+
+        let mut par_depths: Vec<_> =
+            walk_dir().into_par_iter().map(|each| each.unwrap().depth).collect();
+        par_depths.sort();
+        assert_eq!(par_depths, vec![0, 1, 1, 1, 1, 1, 2, 2, 2]);
+    }
+}
+
+#[test]
+fn walk_sibling_index() {
+    let (test_dir, _temp_dir) = test_dir();
+    let siblings = |walk_dir: WalkDir| -> Vec<_> {
+        walk_dir
+            .sort(true)
+            .filter_entry(|each| each.file_name() != "b.txt")
+            .into_iter()
+            .map(|each| {
+                let each = each.unwrap();
+                let name = each.file_name.to_string_lossy().into_owned();
+                (name, each.sibling_index(), each.is_last_sibling())
+            })
+            .collect()
+    };
+    let expected = |siblings: &[(&str, usize, bool)]| -> Vec<_> {
+        siblings.iter().map(|(name, index, last)| (name.to_string(), *index, *last)).collect()
+    };
+    let root_name = test_dir.file_name().unwrap().to_str().unwrap();
+
+    assert_eq!(
+        siblings(WalkDir::new(&test_dir)),
+        expected(&[
+            (root_name, 0, true),
+            ("a.txt", 0, false),
+            ("c.txt", 1, false),
+            ("group 1", 2, false),
+            ("d.txt", 0, true),
+            ("group 2", 3, true),
+            ("e.txt", 0, true),
+        ])
+    );
+    assert_eq!(
+        siblings(
+            WalkDir::new(&test_dir).sort_reverse(true).file_type_filter(FileTypeFilter::Files)
+        ),
+        expected(&[
+            ("e.txt", 0, true),
+            ("d.txt", 0, true),
+            ("c.txt", 0, false),
+            ("a.txt", 1, true)
+        ])
+    );
+}
+
+#[test]
+fn walk_absolute() {
+    // Tests run in the package root
+    let root = PathBuf::from("tests/assets");
+    let canonical_root = fs::canonicalize(&root).unwrap();
+    let mut count = 0;
+    for each in WalkDir::new(&root).absolute(true) {
+        let each = each.unwrap();
+        assert!(each.path().is_absolute());
+        assert!(each.path().starts_with(&canonical_root));
+        assert_eq!(each.relative_path(), each.path().strip_prefix(&canonical_root).unwrap());
+        count += 1;
+    }
+    assert_eq!(count, WalkDir::new(&root).into_iter().count());
+
+    // Roots that can't be canonicalized are yielded as an error
+    let (test_dir, _temp_dir) = test_dir();
+    let results: Vec<_> =
+        WalkDir::new(test_dir.join("missing")).absolute(true).into_iter().collect();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].as_ref().unwrap_err().path(), Some(test_dir.join("missing").as_path()));
+}
+
+#[test]
+fn walk_rayon_no_lockup() {
+    // Without jwalk_par_bridge this locks
+    let pool = std::sync::Arc::new(rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap());
+    let _: Vec<_> = WalkDir::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+        .parallelism(Parallelism::RayonExistingPool(pool))
+        .process_read_dir(|_, _, _, dir_entry_results| {
+            for dir_entry_result in dir_entry_results {
+                let _ = dir_entry_result.as_ref().map(|dir_entry| dir_entry.metadata());
+            }
+        })
+        .sort(true)
+        .into_iter()
+        .collect();
+}
+
+#[test]
+fn combine_rayon_no_lockup() {
+    WalkDir::new(PathBuf::from(env!("CARGO_MANIFEST_DIR")))
+        .parallelism(Parallelism::RayonNewPool(0)) // lockup if don't use separate rayon pool
+        .sort(true)
+        .into_iter()
+        .par_bridge()
+        .filter_map(|dir_entry_result| {
+            let dir_entry = dir_entry_result.ok()?;
+            if dir_entry.file_type().is_file() {
+                let path = dir_entry.path();
+                let text = std::fs::read_to_string(path).ok()?;
+                if text.contains("hello world") {
+                    return Some(true);
+                }
+            }
+            None
+        })
+        .count();
+}
+
+#[test]
+fn walk_min_depth_1() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(test_dir).min_depth(1).sort(true));
+    assert_eq!(
+        paths,
+        vec![
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+}
+
+#[test]
+fn walk_min_depth_2() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(test_dir).min_depth(2).sort(true));
+    assert_eq!(paths, vec!["group 1/d.txt (2)", "group 2/e.txt (2)",]);
+}
+
+#[test]
+fn see_hidden_files() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(WalkDir::new(test_dir).skip_hidden(false).sort(true));
+    assert!(paths.contains(&"group 2/.hidden_file.txt (2)".to_string()));
+}
+
+#[test]
+fn walk_file() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(test_dir.join("a.txt"));
+    let mut iter = walk_dir.into_iter();
+    assert!(iter.next().unwrap().unwrap().file_name.to_str().unwrap() == "a.txt");
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn walk_file_serial() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(test_dir.join("a.txt")).parallelism(Parallelism::Serial);
+    let mut iter = walk_dir.into_iter();
+    assert!(iter.next().unwrap().unwrap().file_name.to_str().unwrap() == "a.txt");
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn error_when_path_does_not_exist() {
+    let (test_dir, _temp_dir) = test_dir();
+    let root = test_dir.join("path_does_not_exist");
+    let walk_dir = WalkDir::new(&root);
+    let mut iter = walk_dir.into_iter();
+    let err = iter.next().unwrap().unwrap_err();
+    assert_eq!(err.depth(), 0);
+    assert_eq!(err.path(), Some(root.as_path()));
+    assert_eq!(err.io_error().unwrap().kind(), std::io::ErrorKind::NotFound);
+    assert!(iter.next().is_none());
+
+    let results: Vec<_> = WalkDir::new(&root).into_par_iter().collect();
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_err());
+}
+
+#[test]
+fn error_when_path_removed_durring_iteration() {
+    let (test_dir, _temp_dir) = test_dir();
+    let walk_dir = WalkDir::new(&test_dir).parallelism(Parallelism::Serial).sort(true);
+    let mut iter = walk_dir.into_iter();
+
+    // Read root. read_dir for root is also called since single thread mode.
+    let _ = iter.next().unwrap().is_ok(); // " (0)",
+
+    // Remove group 2 dir from disk
+    fs_extra::remove_items(&vec![test_dir.join("group 2")]).unwrap();
+
+    let _ = iter.next().unwrap().is_ok(); // "a.txt (1)",
+    let _ = iter.next().unwrap().is_ok(); // "b.txt (1)",
+    let _ = iter.next().unwrap().is_ok(); // "c.txt (1)",
+    let _ = iter.next().unwrap().is_ok(); // "group 1 (1)",
+    let _ = iter.next().unwrap().is_ok(); // "group 1/d.txt (2)",
+
+    // group 2 is read correctly, since it was read before path removed.
+    let group_2 = iter.next().unwrap().unwrap();
+
+    // group 2 content error IS set, since path is removed when try read_dir for
+    // group 2 path.
+    let _ = group_2.read_children_error.is_some();
+
+    // done!
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn walk_root() {
+    let paths: Vec<_> = WalkDir::new("/")
+        .max_depth(1)
+        .sort(true)
+        .into_iter()
+        .filter_map(|each| Some(each.ok()?.path().to_owned()))
+        .collect();
+    assert!(paths.first().unwrap().to_str().unwrap() == "/");
+}
+
+lazy_static! {
+    static ref RELATIVE_MUTEX: Mutex<()> = Mutex::new(());
+}
+
+#[test]
+fn walk_relative_1() {
+    let _shared = RELATIVE_MUTEX.lock().unwrap();
+    let (test_dir, _temp_dir) = test_dir();
+
+    env::set_current_dir(&test_dir).unwrap();
+
+    let paths = local_paths(WalkDir::new(".").sort(true));
+
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+
+    let root_dir_entry = WalkDir::new("..").into_iter().next().unwrap().unwrap();
+    assert_eq!(&root_dir_entry.file_name, "..");
+}
+
+#[test]
+fn walk_relative_2() {
+    let _shared = RELATIVE_MUTEX.lock().unwrap();
+    let (test_dir, _temp_dir) = test_dir();
+
+    env::set_current_dir(&test_dir.join("group 1")).unwrap();
+
+    let paths = local_paths(WalkDir::new("..").sort(true));
+
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+
+    let root_dir_entry = WalkDir::new(".").into_iter().next().unwrap().unwrap();
+    assert_eq!(&root_dir_entry.file_name, ".");
+}
+
+#[test]
+fn filter_groups_with_process_read_dir() {
+    let (test_dir, _temp_dir) = test_dir();
+    let paths = local_paths(
+        WalkDir::new(test_dir)
+            .sort(true)
+            // Filter groups out manually
+            .process_read_dir(|_depth, _path, _parent, children| {
+                children.retain(|each_result| {
                     each_result
                         .as_ref()
                         .map(|dir_entry| {
@@ -1259,3 +3988,277 @@ fn save_state_with_process_read_dir() {
     assert!(iter.next().is_none());
 }
 */
+
+#[test]
+fn walk_unordered_first() {
+    let (test_dir, _temp_dir) = test_dir();
+    // Reading "group 1" blocks until "group 2/e.txt" has been yielded
+    let (sender, receiver) = std::sync::mpsc::channel::<()>();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let walk_dir = |unordered_first: bool| {
+        let receiver = receiver.clone();
+        WalkDir::new(&test_dir)
+            .parallelism(Parallelism::RayonNewPool(2))
+            .sort(true)
+            .unordered_first(unordered_first)
+            .process_read_dir(move |_, path, _, _| {
+                if unordered_first && path.ends_with("group 1") {
+                    let _ = receiver.lock().unwrap().recv_timeout(Duration::from_secs(10));
+                }
+            })
+    };
+
+    assert_eq!(
+        local_paths(walk_dir(false)),
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 1/d.txt (2)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+        ]
+    );
+
+    let mut paths = Vec::new();
+    for each_result in walk_dir(true) {
+        let each_entry = each_result.unwrap();
+        if each_entry.relative_path().ends_with("group 2/e.txt") {
+            sender.send(()).unwrap();
+        }
+        let mut path_string = each_entry.relative_path().to_str().unwrap().to_string();
+        path_string.push_str(&format!(" ({})", each_entry.depth));
+        paths.push(path_string);
+    }
+    assert_eq!(
+        paths,
+        vec![
+            " (0)",
+            "a.txt (1)",
+            "b.txt (1)",
+            "c.txt (1)",
+            "group 1 (1)",
+            "group 2 (1)",
+            "group 2/e.txt (2)",
+            "group 1/d.txt (2)",
+        ]
+    );
+
+    sender.send(()).unwrap();
+    let mut iter = walk_dir(true).into_iter();
+    assert!(iter.state().is_none());
+    assert_eq!(iter.by_ref().count(), 8);
+    assert!(iter.is_finished());
+}
+
+#[test]
+fn walk_dir_entry_times() {
+    let (test_dir, _temp_dir) = test_dir();
+    for each in WalkDir::new(&test_dir).sort_order(Sort::Size) {
+        let each = each.unwrap();
+        let metadata = fs::metadata(each.path()).unwrap();
+        assert_eq!(each.modified(), metadata.modified().ok());
+        // Reading a directory can update its access time
+        assert_eq!(each.accessed().is_some(), metadata.accessed().is_ok());
+        assert_eq!(each.created(), metadata.created().ok());
+        assert!(each.modified().is_some());
+    }
+
+    #[cfg(unix)]
+    for each in WalkDir::new(&test_dir).min_depth(1) {
+        let each = each.unwrap();
+        assert_eq!(each.modified(), None);
+        assert_eq!(each.accessed(), None);
+        each.metadata().unwrap();
+        assert!(each.modified().is_some());
+    }
+}
+
+#[test]
+fn walk_remove_all() {
+    let dir = Dir::tmp();
+    dir.mkdirp("root/a/b");
+    dir.mkdirp("outside");
+    dir.touch_all(&["root/.hidden", "root/a/file", "root/a/b/file", "outside/keep"]);
+    #[cfg(unix)]
+    dir.symlink_dir("outside", "root/a/link");
+
+    WalkDir::new(dir.join("root")).min_depth(1).remove_all().unwrap();
+    assert!(dir.join("root").is_dir());
+    assert_eq!(fs::read_dir(dir.join("root")).unwrap().count(), 0);
+
+    // Links are removed without touching their targets, even as the root
+    #[cfg(unix)]
+    {
+        dir.symlink_dir("outside", "outside-link");
+        WalkDir::new(dir.join("outside-link")).remove_all().unwrap();
+        assert!(fs::symlink_metadata(dir.join("outside-link")).is_err());
+
+        dir.symlink_dir("outside", "outside-link");
+        WalkDir::new(dir.join("outside-link/")).remove_all().unwrap();
+        assert!(fs::symlink_metadata(dir.join("outside-link")).is_err());
+
+        dir.symlink_dir("outside", "outside-link");
+        WalkDir::new(dir.join("outside-link")).absolute(true).remove_all().unwrap();
+        assert!(fs::symlink_metadata(dir.join("outside-link")).is_err());
+
+        dir.symlink_dir("outside", "outside-link");
+        WalkDir::new(dir.join("outside-link/")).min_depth(1).remove_all().unwrap();
+        assert!(fs::symlink_metadata(dir.join("outside-link")).is_ok());
+    }
+    assert!(dir.join("outside/keep").is_file());
+
+    WalkDir::new(dir.join("root")).remove_all().unwrap();
+    assert!(!dir.join("root").exists());
+
+    let errors = WalkDir::new(dir.join("root")).remove_all().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].depth(), 0);
+}
+
+#[test]
+fn walk_prune_dirs() {
+    let (test_dir, _temp_dir) = test_dir();
+    // A directory and a file that share the pruned name
+    fs::create_dir(test_dir.join("cache")).unwrap();
+    fs::write(test_dir.join("cache/f.txt"), "").unwrap();
+    fs::write(test_dir.join("group 1/cache"), "").unwrap();
+    let walk_dir = || {
+        WalkDir::new(&test_dir)
+            .sort(true)
+            .prune_dirs(|name| name == "cache" || name == "group 2" || name == "b.txt")
+    };
+    let expected = vec![
+        " (0)",
+        "a.txt (1)",
+        "b.txt (1)",
+        "c.txt (1)",
+        "group 1 (1)",
+        "group 1/cache (2)",
+        "group 1/d.txt (2)",
+    ];
+
+    // Pruned entries never reach the other filters
+    let filter_entry_names = Arc::new(Mutex::new(Vec::new()));
+    let names = filter_entry_names.clone();
+    let walk = walk_dir().filter_entry(move |each| {
+        names.lock().unwrap().push(each.file_name().to_owned());
+        true
+    });
+    assert_eq!(local_paths(walk), expected);
+    assert!(!filter_entry_names.lock().unwrap().iter().any(|name| name == "group 2"));
+
+    assert_eq!(local_paths(walk_dir().with_reader(FsDirReader)), expected);
+    assert_eq!(
+        local_paths(WalkDir::new(&test_dir).sort(true).prune_dirs(|_| true)),
+        vec![" (0)", "a.txt (1)", "b.txt (1)", "c.txt (1)"]
+    );
+
+    // Without the type from the listing, files with a pruned name are dropped
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let dir = fs::File::open(&test_dir).unwrap();
+        let walk = WalkDir::from_dir(dir, &test_dir)
+            .sort(true)
+            .prune_dirs(|name| name == "cache" || name == "group 2" || name == "b.txt");
+        assert_eq!(
+            local_paths(walk),
+            vec![" (0)", "a.txt (1)", "c.txt (1)", "group 1 (1)", "group 1/d.txt (2)"]
+        );
+    }
+}
+
+#[test]
+fn walk_par_entries_threshold() {
+    let dir = Dir::tmp();
+    dir.mkdirp("big/sub");
+    for i in 0..200 {
+        fs::write(dir.join(format!("big/{i}")), vec![0; i % 7]).unwrap();
+    }
+    let walk = |parallelism: Parallelism, threshold: usize| -> Vec<_> {
+        WalkDir::new(dir.path())
+            .parallelism(parallelism)
+            .par_entries_threshold(threshold)
+            .sort_order(Sort::Size)
+            .filter_entry(|each| each.file_name() != "13")
+            .min_file_size(1)
+            .hash_with(|path| fs::read(path).map(|contents| contents.len().to_le_bytes()))
+            .into_iter()
+            .map(|each| {
+                let each = each.unwrap();
+                let hash = each.hash().map(|hash| hash.unwrap().to_vec());
+                (each.path().to_path_buf(), each.file_size(), hash)
+            })
+            .collect()
+    };
+
+    let expected = walk(Parallelism::Serial, usize::MAX);
+    assert_eq!(expected.len(), 173);
+    assert_eq!(walk(Parallelism::RayonNewPool(4), usize::MAX), expected);
+    assert_eq!(walk(Parallelism::RayonNewPool(4), 10), expected);
+    assert_eq!(walk(Parallelism::Serial, 10), expected);
+}
+
+#[test]
+fn walk_par_entries_threshold_max_queued_entries() {
+    let dir = Dir::tmp();
+    for i in 0..10 {
+        dir.mkdirp(format!("{i}"));
+        for j in 0..20 {
+            dir.touch(format!("{i}/{j}"));
+        }
+    }
+    let walk = |parallelism: Parallelism| {
+        local_paths(
+            WalkDir::new(dir.path())
+                .parallelism(parallelism)
+                .par_entries_threshold(1)
+                .max_queued_entries(1)
+                .sort(true)
+                .filter_entry(|each| each.file_name() != "13"),
+        )
+    };
+    let expected = walk(Parallelism::Serial);
+    assert_eq!(expected.len(), 201);
+    // Small pools where every thread can end up waiting on the limit
+    for threads in 1..=4 {
+        assert_eq!(walk(Parallelism::RayonNewPool(threads)), expected);
+    }
+}
+
+#[test]
+fn walk_options() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let (test_dir, _temp_dir) = test_dir();
+    let options = WalkOptions::new()
+        .configure(|walk_dir| walk_dir.sort(true))
+        .configure(|walk_dir| walk_dir.filter_entry(|each| each.file_name() != "b.txt"));
+    assert_send_sync(&options);
+    let expected = local_paths(WalkDir::new(&test_dir).sort(true).filter_entry(|each| {
+        each.file_name() != "b.txt"
+    }));
+    assert_eq!(expected.len(), 7);
+
+    let options = Arc::new(options);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let options = options.clone();
+            let test_dir = test_dir.clone();
+            std::thread::spawn(move || local_paths(walk_dir(&options, test_dir)))
+        })
+        .collect();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), expected);
+    }
+
+    // Options taken from a builder leave its roots behind
+    let walk_dir = WalkDir::new(&test_dir).sort(true).max_depth(1);
+    let options = walk_dir.options();
+    let group_dir = test_dir.join("group 1");
+    let walk_dir = options.walk_dir(&group_dir);
+    assert_eq!(walk_dir.roots(), [group_dir]);
+    assert_eq!(local_paths(walk_dir), vec![" (0)", "d.txt (1)"]);
+}